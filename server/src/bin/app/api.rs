@@ -0,0 +1,1566 @@
+use std::sync::Arc;
+
+use axum::body::{boxed, Body};
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::response::Response;
+use axum::{Extension, Json};
+use chrono::{Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use scylla::frame::value::Timestamp;
+use serde::{Deserialize, Serialize};
+use tower_cookies::Cookies;
+use uuid::Uuid;
+
+use crate::auth::{self, Claims};
+use crate::bulk;
+use crate::channel::Channel;
+use crate::db::{decode_cursor, encode_cursor, SchedFilter, SchedPage, Store};
+use crate::ical;
+use crate::invite::{self, Invite};
+use crate::member::{Member, Role};
+use crate::pat::{PersonalToken, Scope};
+use crate::perm::Access;
+use crate::recur::{self, RRule};
+use crate::refresh;
+use crate::sched::{Sched, SchedTime};
+use crate::session::Session;
+use crate::tz;
+use crate::user::User;
+use crate::AppState;
+
+pub const MAX_LIMIT: i32 = 500;
+/// How far series are expanded when a listing has no `to` date.
+const RECURRENCE_DAYS: i64 = 90;
+/// How far back calendar exports reach.
+const CALENDAR_PAST_DAYS: i64 = 365;
+
+#[derive(Deserialize, Debug)]
+pub struct NewSched {
+    sched: String,
+    date_at: NaiveDate,
+    start_time: Option<NaiveTime>,
+    end_time: Option<NaiveTime>,
+    duration: Option<i64>,
+    all_day: Option<bool>,
+    rrule: Option<String>,
+    #[serde(default)]
+    exdates: Vec<NaiveDate>,
+}
+
+/// `rrule: ""` turns a series back into a single schedule, `exdates`
+/// replaces the exception dates.
+#[derive(Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct SchedPatch {
+    pub sched: Option<String>,
+    pub date_at: Option<NaiveDate>,
+    pub start_time: Option<NaiveTime>,
+    pub end_time: Option<NaiveTime>,
+    pub duration: Option<i64>,
+    pub all_day: Option<bool>,
+    pub rrule: Option<String>,
+    pub exdates: Option<Vec<NaiveDate>>,
+}
+
+impl SchedPatch {
+    /// Applies the time fields on top of the current ones. Giving a duration
+    /// replaces the end time, and `all_day: true` clears both times.
+    fn time(&self, prev: &Sched) -> Option<anyhow::Result<SchedTime>> {
+        if self.start_time.is_none()
+            && self.end_time.is_none()
+            && self.duration.is_none()
+            && self.all_day.is_none()
+        {
+            return None;
+        }
+
+        if self.all_day == Some(true) {
+            return Some(SchedTime::resolve(None, None, None, Some(true)));
+        }
+
+        let end = match self.duration {
+            Some(_) => None,
+            None => self.end_time.or(prev.end_time),
+        };
+
+        Some(SchedTime::resolve(
+            self.start_time.or(prev.start_time),
+            end,
+            self.duration,
+            self.all_day,
+        ))
+    }
+}
+
+pub fn response(status: StatusCode, body: String) -> Response {
+    Response::builder()
+        .status(status)
+        .body(boxed(Body::from(body)))
+        .unwrap()
+}
+
+pub fn json_response(status: StatusCode, content: serde_json::Value) -> Response {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(boxed(Body::from(content.to_string())))
+        .unwrap()
+}
+
+pub type ApiError = (StatusCode, String);
+
+pub fn internal_error(err: anyhow::Error) -> ApiError {
+    println!("err: {:?}", err);
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
+
+/// Validates a recurrence rule and writes it the way it is stored. An empty
+/// rule means the schedule doesn't repeat.
+fn check_rrule(rrule: &str) -> Result<Option<String>, ApiError> {
+    if rrule.trim().is_empty() {
+        return Ok(None);
+    }
+    rrule
+        .parse::<RRule>()
+        .map(|rule| Some(rule.to_string()))
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))
+}
+
+/// Archived channels are read-only.
+pub fn check_open(channel: &Channel) -> Result<(), ApiError> {
+    if channel.is_archived() {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("channel {} is archived", channel.channel),
+        ));
+    }
+    Ok(())
+}
+
+/// Whether a channel id is in use. Channels from before `ks.c` rows were
+/// written are known by their members.
+pub async fn channel_exists(db: &dyn Store, channel: &str) -> anyhow::Result<bool> {
+    Ok(db.find_channel(channel).await?.is_some()
+        || !db.find_members_by_channel(channel).await?.is_empty())
+}
+
+/// The zone dates and times are shown and entered in: the user's, else the
+/// channel's, else UTC.
+pub async fn viewer_tz(state: &AppState, user: &User, channel: &str) -> Tz {
+    if let Some(tz) = user.tz.as_deref().and_then(|tz| tz::parse_tz(tz).ok()) {
+        return tz;
+    }
+
+    match state.db.find_channel(channel).await {
+        Ok(Some(Channel { tz: Some(tz), .. })) => tz::parse_tz(&tz).unwrap_or(Tz::UTC),
+        _ => Tz::UTC,
+    }
+}
+
+/// Where a listing left off: the store's cursor, and the key up to which
+/// occurrences of series were merged into the pages so far.
+#[derive(Serialize, Deserialize)]
+struct ListCursor {
+    store: String,
+    after: SortKey,
+}
+
+type SortKey = (NaiveDate, String, i64);
+
+fn sort_key(sched: &Sched) -> SortKey {
+    (
+        sched.date_at,
+        sched.id.to_owned(),
+        sched.create_at.0.num_milliseconds(),
+    )
+}
+
+impl ListCursor {
+    fn encode(&self) -> anyhow::Result<String> {
+        Ok(encode_cursor(&serde_json::to_vec(self)?))
+    }
+
+    fn decode(cursor: &str) -> anyhow::Result<Self> {
+        serde_json::from_slice(&decode_cursor(cursor)?)
+            .map_err(|_| anyhow::anyhow!("invalid cursor"))
+    }
+}
+
+/// Lists a channel with `from` and `to` taken as dates in `tz`, returning
+/// schedules in wall-clock time of `tz`. Occurrences of series, up to `to`
+/// or `RECURRENCE_DAYS` ahead, go to the page whose stored schedules they
+/// sort among: after where the previous page ended, up to the last
+/// schedule of this one, or to the end on the last page.
+pub async fn list_scheds(
+    db: &dyn Store,
+    channel: &str,
+    filter: &SchedFilter,
+    tz: Tz,
+) -> anyhow::Result<SchedPage> {
+    let cursor = filter
+        .cursor
+        .as_deref()
+        .map(ListCursor::decode)
+        .transpose()?;
+    let local = SchedFilter {
+        from: Some(filter.from.unwrap_or_else(|| tz::today(tz))),
+        ..filter.clone()
+    };
+    let (from, to) = tz::utc_date_range(local.start(), local.to);
+    let stored = SchedFilter {
+        from: Some(from),
+        to,
+        cursor: cursor.as_ref().map(|cursor| cursor.store.to_owned()),
+        ..filter.clone()
+    };
+
+    let page = db.find_sched_by_channel(channel, &stored).await?;
+
+    let rows = page
+        .scheds
+        .iter()
+        .map(|sched| tz::to_local(sched, tz))
+        .collect::<Vec<_>>();
+    let after = cursor.map(|cursor| cursor.after);
+    let last = rows.iter().map(sort_key).chain(after.clone()).max();
+    let in_page = |sched: &Sched| {
+        let key = sort_key(sched);
+        after.as_ref().is_none_or(|after| &key > after)
+            && (page.next.is_none() || last.as_ref().is_some_and(|last| &key <= last))
+    };
+
+    let mut scheds = rows
+        .iter()
+        .filter(|sched| sched.rrule.is_none() && local.matches(sched))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    let to = local
+        .to
+        .unwrap_or_else(|| local.start() + Duration::days(RECURRENCE_DAYS));
+    for series in db.find_recurring_by_channel(channel).await? {
+        if filter.user.as_ref().is_some_and(|user| &series.id != user) {
+            continue;
+        }
+        match recur::expand_stored(&series, tz, local.start(), to) {
+            Ok(occurrences) => scheds.extend(occurrences.into_iter().filter(|o| in_page(o))),
+            Err(err) => println!("err: {} {:?}", series.sid, err),
+        }
+    }
+
+    scheds.sort_by_key(sort_key);
+
+    let next = match page.next {
+        Some(store) => Some(
+            ListCursor {
+                store,
+                after: last.unwrap_or((NaiveDate::MIN, String::new(), i64::MIN)),
+            }
+            .encode()?,
+        ),
+        None => None,
+    };
+
+    Ok(SchedPage { scheds, next })
+}
+
+pub async fn find_sched(state: &AppState, channel: &str, sid: &Uuid) -> Result<Sched, ApiError> {
+    match state.db.find_sched_by_id(channel, sid).await {
+        Ok(Some(sched)) => Ok(sched),
+        Ok(None) => Err((StatusCode::NOT_FOUND, format!("schedule {} not found", sid))),
+        Err(err) => Err(internal_error(err)),
+    }
+}
+
+pub async fn get_scheds(
+    Path(channel): Path<String>,
+    Query(filter): Query<SchedFilter>,
+    Access { user, .. }: Access,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, ApiError> {
+    if let Some(limit) = filter.limit {
+        if !(1..=MAX_LIMIT).contains(&limit) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("limit must be between 1 and {}", MAX_LIMIT),
+            ));
+        }
+    }
+    if let (Some(from), Some(to)) = (filter.from, filter.to) {
+        if from > to {
+            return Err((StatusCode::BAD_REQUEST, "from is after to".to_owned()));
+        }
+    }
+    if let Some(cursor) = &filter.cursor {
+        if let Err(err) = ListCursor::decode(cursor) {
+            return Err((StatusCode::BAD_REQUEST, err.to_string()));
+        }
+    }
+
+    let tz = viewer_tz(&state, &user, &channel).await;
+    let page = list_scheds(state.db.as_ref(), &channel, &filter, tz)
+        .await
+        .map_err(internal_error)?;
+
+    let content = serde_json::json!({
+        "user": user.id,
+        "channel": channel,
+        "tz": tz.name(),
+        "data": page.scheds,
+        "next": page.next,
+    });
+
+    println!("scheds: {:?}", content);
+
+    Ok(json_response(StatusCode::OK, content))
+}
+
+pub async fn create_sched(
+    Path(channel): Path<String>,
+    access: Access,
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<NewSched>,
+) -> Result<Response, ApiError> {
+    access.require(Role::Editor)?;
+    check_open(&access.channel)?;
+    let user = access.user;
+
+    if input.sched.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "empty schedule".to_owned()));
+    }
+
+    let time = SchedTime::resolve(
+        input.start_time,
+        input.end_time,
+        input.duration,
+        input.all_day,
+    )
+    .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    let mut sched = Sched::new(&channel, &user.id, &input.sched, input.date_at);
+    sched.set_time(time);
+    sched.rrule = check_rrule(input.rrule.as_deref().unwrap_or_default())?;
+    sched.exdates = input.exdates;
+
+    let tz = viewer_tz(&state, &user, &channel).await;
+
+    state
+        .db
+        .insert_sched(&tz::to_utc(&sched, tz))
+        .await
+        .map_err(internal_error)?;
+
+    Ok(json_response(StatusCode::CREATED, serde_json::json!(sched)))
+}
+
+pub async fn get_sched(
+    Path((channel, sid)): Path<(String, Uuid)>,
+    Access { user, .. }: Access,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, ApiError> {
+    let sched = find_sched(&state, &channel, &sid).await?;
+    let tz = viewer_tz(&state, &user, &channel).await;
+
+    Ok(json_response(
+        StatusCode::OK,
+        serde_json::json!(tz::to_local(&sched, tz)),
+    ))
+}
+
+pub async fn update_sched(
+    Path((channel, sid)): Path<(String, Uuid)>,
+    access: Access,
+    State(state): State<Arc<AppState>>,
+    Json(patch): Json<SchedPatch>,
+) -> Result<Response, ApiError> {
+    access.require(Role::Editor)?;
+    check_open(&access.channel)?;
+
+    let stored = find_sched(&state, &channel, &sid).await?;
+    let next = patch_sched(&state, &access, &stored, &patch).await?;
+
+    Ok(json_response(StatusCode::OK, serde_json::json!(next)))
+}
+
+/// Applies a patch to a stored schedule, with dates and times in the
+/// user's zone, or for a series in the zone its rule repeats in, and
+/// returns it that way.
+pub async fn patch_sched(
+    state: &AppState,
+    access: &Access,
+    stored: &Sched,
+    patch: &SchedPatch,
+) -> Result<Sched, ApiError> {
+    access.require_manage(&stored.id)?;
+    let tz = tz::zone(
+        stored,
+        viewer_tz(state, &access.user, &stored.channel).await,
+    );
+    let prev = tz::to_local(stored, tz);
+
+    let mut next = prev.clone();
+    if let Some(sched) = &patch.sched {
+        if sched.trim().is_empty() {
+            return Err((StatusCode::BAD_REQUEST, "empty schedule".to_owned()));
+        }
+        next.sched = sched.to_owned();
+    }
+    if let Some(date_at) = patch.date_at {
+        next.date_at = date_at;
+    }
+    if let Some(time) = patch.time(&prev) {
+        next.set_time(time.map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?);
+    }
+    if let Some(rrule) = &patch.rrule {
+        next.rrule = check_rrule(rrule)?;
+    }
+    if let Some(exdates) = &patch.exdates {
+        next.exdates = exdates.to_owned();
+    }
+
+    state
+        .db
+        .update_sched(stored, &tz::to_utc(&next, tz))
+        .await
+        .map_err(internal_error)?;
+
+    Ok(next)
+}
+
+pub async fn delete_sched(
+    Path((channel, sid)): Path<(String, Uuid)>,
+    access: Access,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, ApiError> {
+    access.require(Role::Editor)?;
+    check_open(&access.channel)?;
+
+    let sched = find_sched(&state, &channel, &sid).await?;
+    remove_sched(&state, &access, &sched).await?;
+
+    Ok(response(StatusCode::NO_CONTENT, String::new()))
+}
+
+/// Deletes a stored schedule, all of it for a series.
+pub async fn remove_sched(
+    state: &AppState,
+    access: &Access,
+    sched: &Sched,
+) -> Result<(), ApiError> {
+    access.require_manage(&sched.id)?;
+
+    state.db.delete_sched(sched).await.map_err(internal_error)
+}
+
+#[derive(Deserialize, Debug)]
+pub struct TzPatch {
+    tz: Option<String>,
+}
+
+fn check_tz(patch: &TzPatch) -> Result<(), ApiError> {
+    if let Some(tz) = &patch.tz {
+        tz::parse_tz(tz).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    }
+    Ok(())
+}
+
+pub async fn update_me(
+    Extension(user): Extension<User>,
+    State(state): State<Arc<AppState>>,
+    Json(patch): Json<TzPatch>,
+) -> Result<Response, ApiError> {
+    check_tz(&patch)?;
+
+    let mut stored = state
+        .db
+        .find_user_by_id(&user.id)
+        .await
+        .map_err(internal_error)?
+        .unwrap_or(user);
+    stored.tz = patch.tz;
+
+    state
+        .db
+        .insert_user(&stored)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(json_response(
+        StatusCode::OK,
+        serde_json::json!({ "user": stored.id, "channel": stored.channel, "tz": stored.tz }),
+    ))
+}
+
+/// The provider accounts the user signs in with.
+pub async fn get_identities(
+    Extension(user): Extension<User>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, ApiError> {
+    let identities = state
+        .db
+        .find_identities_by_user(&user.id)
+        .await
+        .map_err(internal_error)?
+        .iter()
+        .map(|i| serde_json::json!({ "provider": i.provider, "subject": i.subject, "login": i.login }))
+        .collect::<Vec<_>>();
+
+    Ok(json_response(
+        StatusCode::OK,
+        serde_json::json!({ "user": user.id, "data": identities }),
+    ))
+}
+
+const TOKEN_MAX_DAYS: i64 = 365;
+const TOKEN_DEFAULT_DAYS: i64 = 30;
+
+fn personal_token_json(token: &PersonalToken) -> serde_json::Value {
+    let at = |ts: &Timestamp| Utc.timestamp_millis_opt(ts.0.num_milliseconds()).single();
+    serde_json::json!({
+        "id": token.tid,
+        "name": token.name,
+        "scope": token.scope(),
+        "channel": token.channel,
+        "created_at": at(&token.created_at),
+        "expires_at": at(&token.expires_at),
+        "last_used_at": token.last_used_at.as_ref().and_then(at),
+    })
+}
+
+/// The user's personal access tokens, without their secrets.
+pub async fn get_tokens(
+    Extension(user): Extension<User>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, ApiError> {
+    let tokens = state
+        .db
+        .find_personal_tokens_by_user(&user.id)
+        .await
+        .map_err(internal_error)?
+        .iter()
+        .map(personal_token_json)
+        .collect::<Vec<_>>();
+
+    Ok(json_response(
+        StatusCode::OK,
+        serde_json::json!({ "user": user.id, "data": tokens }),
+    ))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct NewToken {
+    name: String,
+    scope: Scope,
+    channel: Option<String>,
+    expires_in_days: Option<i64>,
+}
+
+/// Creates a personal access token. Its secret is only ever in this
+/// response.
+pub async fn create_token(
+    Extension(user): Extension<User>,
+    State(state): State<Arc<AppState>>,
+    Json(payload): Json<NewToken>,
+) -> Result<Response, ApiError> {
+    let name = payload.name.trim();
+    if name.is_empty() || name.chars().count() > 100 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "name must be 1 to 100 characters".to_owned(),
+        ));
+    }
+    let days = payload.expires_in_days.unwrap_or(TOKEN_DEFAULT_DAYS);
+    if !(1..=TOKEN_MAX_DAYS).contains(&days) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("expires_in_days must be 1 to {}", TOKEN_MAX_DAYS),
+        ));
+    }
+    if let Some(channel) = &payload.channel {
+        Access::load(&state, &user, channel).await?;
+    }
+
+    let (secret, token) = PersonalToken::issue(
+        &user.id,
+        name,
+        payload.scope,
+        payload.channel.as_deref(),
+        Duration::days(days),
+    );
+    state
+        .db
+        .insert_personal_token(&token)
+        .await
+        .map_err(internal_error)?;
+
+    let mut body = personal_token_json(&token);
+    body["token"] = serde_json::Value::String(secret);
+    Ok(json_response(StatusCode::CREATED, body))
+}
+
+pub async fn delete_token(
+    Path(tid): Path<Uuid>,
+    Extension(user): Extension<User>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, ApiError> {
+    let token = state
+        .db
+        .find_personal_tokens_by_user(&user.id)
+        .await
+        .map_err(internal_error)?
+        .into_iter()
+        .find(|token| token.tid == tid)
+        .ok_or((StatusCode::NOT_FOUND, format!("no token {}", tid)))?;
+
+    state
+        .db
+        .delete_personal_token(&token)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(response(StatusCode::NO_CONTENT, String::new()))
+}
+
+fn session_json(session: &Session, current: bool) -> serde_json::Value {
+    serde_json::json!({
+        "id": session.id,
+        "device": session.device,
+        "ip": session.ip,
+        "created_at": Utc.timestamp_millis_opt(session.created_at.0.num_milliseconds()).single(),
+        "last_seen_at": Utc.timestamp_millis_opt(session.last_seen_at.0.num_milliseconds()).single(),
+        "current": current,
+    })
+}
+
+/// The devices the user is signed in on, most recently used first.
+pub async fn get_sessions(
+    Extension(user): Extension<User>,
+    Extension(claims): Extension<Claims>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, ApiError> {
+    let sessions = state
+        .db
+        .find_sessions_by_user(&user.id)
+        .await
+        .map_err(internal_error)?
+        .iter()
+        .map(|s| session_json(s, Some(s.id) == claims.session()))
+        .collect::<Vec<_>>();
+
+    Ok(json_response(
+        StatusCode::OK,
+        serde_json::json!({ "user": user.id, "data": sessions }),
+    ))
+}
+
+/// Signs one of the user's devices out, the current one included.
+pub async fn delete_session(
+    Path(id): Path<Uuid>,
+    Extension(user): Extension<User>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, ApiError> {
+    let session = state
+        .db
+        .find_session(&id)
+        .await
+        .map_err(internal_error)?
+        .filter(|session| session.user == user.id)
+        .ok_or((StatusCode::NOT_FOUND, format!("no session {}", id)))?;
+
+    auth::revoke_session(state.db.as_ref(), &session)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(response(StatusCode::NO_CONTENT, String::new()))
+}
+
+/// Unlinks a provider account. The last one stays, or the user couldn't
+/// sign in anymore.
+pub async fn delete_identity(
+    Path((provider, subject)): Path<(String, String)>,
+    Extension(user): Extension<User>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, ApiError> {
+    let identities = state
+        .db
+        .find_identities_by_user(&user.id)
+        .await
+        .map_err(internal_error)?;
+    let identity = identities
+        .iter()
+        .find(|i| i.provider == provider && i.subject == subject)
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            format!("no {} account {} linked", provider, subject),
+        ))?;
+    if identities.len() == 1 {
+        return Err((
+            StatusCode::CONFLICT,
+            "the only linked account can't be unlinked".to_owned(),
+        ));
+    }
+
+    state
+        .db
+        .delete_identity(identity)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(response(StatusCode::NO_CONTENT, String::new()))
+}
+
+fn channel_json(channel: &Channel, user: &User, role: Role) -> serde_json::Value {
+    serde_json::json!({
+        "channel": channel.channel,
+        "name": channel.name(),
+        "tz": channel.tz,
+        "archived": channel.is_archived(),
+        "active": channel.channel == user.channel,
+        "role": role,
+    })
+}
+
+/// Channel ids end up in URLs and calendar paths.
+fn check_channel_id(channel: &str) -> Result<(), ApiError> {
+    if channel.is_empty()
+        || channel.len() > 32
+        || !channel
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "channel ids are 1 to 32 letters, digits, - or _".to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+fn check_channel_name(name: &str) -> Result<(), ApiError> {
+    if name.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "empty channel name".to_owned()));
+    }
+    Ok(())
+}
+
+/// The channels the user is a member of, archived ones included.
+pub async fn get_channels(
+    Extension(user): Extension<User>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, ApiError> {
+    let members = state
+        .db
+        .find_members_by_user(&user.id)
+        .await
+        .map_err(internal_error)?;
+
+    let mut channels = vec![];
+    for member in members {
+        let access = Access::load(&state, &user, &member.channel).await?;
+        channels.push(channel_json(&access.channel, &user, access.role()));
+    }
+
+    Ok(json_response(
+        StatusCode::OK,
+        serde_json::json!({ "user": user.id, "data": channels }),
+    ))
+}
+
+#[derive(Deserialize, Debug)]
+pub struct NewChannel {
+    channel: String,
+    name: Option<String>,
+    tz: Option<String>,
+}
+
+/// Creates a channel with the user as its first member.
+pub async fn create_channel(
+    Extension(user): Extension<User>,
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<NewChannel>,
+) -> Result<Response, ApiError> {
+    check_channel_id(&input.channel)?;
+    if let Some(name) = &input.name {
+        check_channel_name(name)?;
+    }
+    if let Some(tz) = &input.tz {
+        tz::parse_tz(tz).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    }
+
+    if channel_exists(state.db.as_ref(), &input.channel)
+        .await
+        .map_err(internal_error)?
+    {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("channel {} already exists", input.channel),
+        ));
+    }
+
+    let channel = Channel {
+        channel: input.channel,
+        name: input.name.map(|name| name.trim().to_owned()),
+        tz: input.tz,
+        ..Default::default()
+    };
+
+    state
+        .db
+        .insert_channel(&channel)
+        .await
+        .map_err(internal_error)?;
+    state
+        .db
+        .insert_member(&Member::new(&user.id, &channel.channel, Role::Owner))
+        .await
+        .map_err(internal_error)?;
+
+    Ok(json_response(
+        StatusCode::CREATED,
+        channel_json(&channel, &user, Role::Owner),
+    ))
+}
+
+/// Tells a missing field from an explicit `null`, which clears it.
+fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// Fields left out stay as they are, `tz: null` clears the time zone.
+#[derive(Deserialize, Debug)]
+pub struct ChannelPatch {
+    #[serde(default, deserialize_with = "nullable")]
+    tz: Option<Option<String>>,
+    name: Option<String>,
+    archived: Option<bool>,
+}
+
+/// Sets the time zone, renames or (un)archives a channel. The id stays the
+/// same, so links and calendar subscriptions keep working after a rename.
+pub async fn update_channel(
+    access: Access,
+    State(state): State<Arc<AppState>>,
+    Json(patch): Json<ChannelPatch>,
+) -> Result<Response, ApiError> {
+    access.require(Role::Owner)?;
+    let mut channel = access.channel.clone();
+
+    if patch.tz.is_some() || patch.name.is_some() {
+        check_open(&channel)?;
+    }
+    if let Some(Some(tz)) = &patch.tz {
+        tz::parse_tz(tz).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    }
+    if let Some(name) = &patch.name {
+        check_channel_name(name)?;
+    }
+
+    if let Some(tz) = patch.tz {
+        channel.tz = tz;
+    }
+    if let Some(name) = patch.name {
+        channel.name = Some(name.trim().to_owned());
+    }
+    match patch.archived {
+        Some(true) if !channel.is_archived() => {
+            channel.archived_at = Some(Timestamp(Duration::milliseconds(
+                Utc::now().timestamp_millis(),
+            )));
+        }
+        Some(false) => channel.archived_at = None,
+        _ => {}
+    }
+
+    state
+        .db
+        .insert_channel(&channel)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(json_response(
+        StatusCode::OK,
+        channel_json(&channel, &access.user, Role::Owner),
+    ))
+}
+
+/// Makes the channel the user's active one, which is carried in a new token
+/// and kept for their next sign-in.
+pub async fn switch_channel(
+    access: Access,
+    Extension(claims): Extension<Claims>,
+    State(state): State<Arc<AppState>>,
+    cookies: Cookies,
+) -> Result<Response, ApiError> {
+    let channel = &access.channel;
+    check_open(channel)?;
+
+    let mut stored = state
+        .db
+        .find_user_by_id(&access.user.id)
+        .await
+        .map_err(internal_error)?
+        .unwrap_or_else(|| access.user.clone());
+    stored.channel = channel.channel.to_owned();
+    state
+        .db
+        .insert_user(&stored)
+        .await
+        .map_err(internal_error)?;
+
+    let jwt = claims
+        .with_channel(&stored.channel)
+        .encode(&state.keys)
+        .map_err(internal_error)?;
+    auth::set_session_cookies(&cookies, &stored, &jwt);
+
+    let mut res = json_response(
+        StatusCode::OK,
+        channel_json(channel, &stored, access.role()),
+    );
+    res.headers_mut().insert(
+        header::AUTHORIZATION,
+        HeaderValue::from_str(&jwt).map_err(|e| internal_error(e.into()))?,
+    );
+    Ok(res)
+}
+
+/// A channel keeps at least one owner, or no one could manage it. Errors
+/// when `member` is the last one and is about to stop being an owner.
+async fn check_owners_left(state: &AppState, member: &Member) -> Result<(), ApiError> {
+    if !member.is_owner() {
+        return Ok(());
+    }
+
+    let members = state
+        .db
+        .find_members_by_channel(&member.channel)
+        .await
+        .map_err(internal_error)?;
+    if !members.iter().any(|m| m.id != member.id && m.is_owner()) {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("{} is the last owner of {}", member.id, member.channel),
+        ));
+    }
+    Ok(())
+}
+
+/// Leaves a channel. The last owner can't leave; the channel can be
+/// archived instead.
+pub async fn leave_channel(
+    access: Access,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, ApiError> {
+    check_owners_left(&state, &access.member).await?;
+
+    state
+        .db
+        .delete_member(&access.member)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(response(StatusCode::NO_CONTENT, String::new()))
+}
+
+pub async fn get_members(
+    access: Access,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, ApiError> {
+    let members = state
+        .db
+        .find_members_by_channel(&access.channel.channel)
+        .await
+        .map_err(internal_error)?
+        .iter()
+        .map(|m| serde_json::json!({ "id": m.id, "role": m.role() }))
+        .collect::<Vec<_>>();
+
+    Ok(json_response(
+        StatusCode::OK,
+        serde_json::json!({ "channel": access.channel.channel, "data": members }),
+    ))
+}
+
+async fn find_member(state: &AppState, channel: &str, id: &str) -> Result<Member, ApiError> {
+    match state.db.find_member(id, channel).await {
+        Ok(Some(member)) => Ok(member),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            format!("{} is not a member of {}", id, channel),
+        )),
+        Err(err) => Err(internal_error(err)),
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MemberPatch {
+    role: Role,
+}
+
+pub async fn update_member(
+    Path((channel, id)): Path<(String, String)>,
+    access: Access,
+    State(state): State<Arc<AppState>>,
+    Json(patch): Json<MemberPatch>,
+) -> Result<Response, ApiError> {
+    access.require(Role::Owner)?;
+
+    let member = find_member(&state, &channel, &id).await?;
+    if patch.role != Role::Owner {
+        check_owners_left(&state, &member).await?;
+    }
+
+    let member = Member::new(&id, &channel, patch.role);
+    state
+        .db
+        .insert_member(&member)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(json_response(
+        StatusCode::OK,
+        serde_json::json!({ "id": member.id, "role": member.role() }),
+    ))
+}
+
+/// Removes someone from the channel. Their schedules stay, and owners can
+/// still manage them.
+pub async fn remove_member(
+    Path((channel, id)): Path<(String, String)>,
+    access: Access,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, ApiError> {
+    access.require(Role::Owner)?;
+
+    let member = find_member(&state, &channel, &id).await?;
+    check_owners_left(&state, &member).await?;
+
+    state
+        .db
+        .delete_member(&member)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(response(StatusCode::NO_CONTENT, String::new()))
+}
+
+/// Invites last a week unless asked otherwise, and a month at most.
+const INVITE_HOURS: i64 = 24 * 7;
+const MAX_INVITE_HOURS: i64 = 24 * 30;
+
+fn invite_json(invite: &Invite, token: &str) -> serde_json::Value {
+    serde_json::json!({
+        "id": invite.id,
+        "channel": invite.channel,
+        "created_by": invite.created_by,
+        "expires_at": Utc.timestamp_millis_opt(invite.expires_at.0.num_milliseconds()).single(),
+        "single_use": invite.single_use,
+        "url": invite::invite_path(token),
+    })
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct NewInvite {
+    expires_in_hours: Option<i64>,
+    #[serde(default)]
+    single_use: bool,
+}
+
+pub async fn create_invite(
+    Path(channel): Path<String>,
+    access: Access,
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<NewInvite>,
+) -> Result<Response, ApiError> {
+    access.require(Role::Owner)?;
+    check_open(&access.channel)?;
+
+    let hours = input.expires_in_hours.unwrap_or(INVITE_HOURS);
+    if !(1..=MAX_INVITE_HOURS).contains(&hours) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "expires_in_hours must be between 1 and {}",
+                MAX_INVITE_HOURS
+            ),
+        ));
+    }
+
+    let expires_at = Utc::now() + Duration::hours(hours);
+    let invite = Invite {
+        channel: channel.to_owned(),
+        id: Uuid::new_v4(),
+        created_by: access.user.id.to_owned(),
+        expires_at: Timestamp(Duration::milliseconds(expires_at.timestamp_millis())),
+        single_use: input.single_use,
+    };
+    let token = invite::sign(&state.keys, &invite).map_err(internal_error)?;
+
+    state
+        .db
+        .insert_invite(&invite)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(json_response(
+        StatusCode::CREATED,
+        invite_json(&invite, &token),
+    ))
+}
+
+/// Pending invites of the channel. Their links can be shown again, since a
+/// link is only a signature over the stored invite.
+pub async fn get_invites(
+    Path(channel): Path<String>,
+    access: Access,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, ApiError> {
+    access.require(Role::Owner)?;
+
+    let invites = state
+        .db
+        .find_invites_by_channel(&channel)
+        .await
+        .map_err(internal_error)?
+        .into_iter()
+        .filter(|invite| !invite.is_expired())
+        .map(|invite| {
+            let token = invite::sign(&state.keys, &invite)?;
+            Ok(invite_json(&invite, &token))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()
+        .map_err(internal_error)?;
+
+    Ok(json_response(
+        StatusCode::OK,
+        serde_json::json!({ "channel": channel, "invites": invites }),
+    ))
+}
+
+pub async fn delete_invite(
+    Path((channel, id)): Path<(String, Uuid)>,
+    access: Access,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, ApiError> {
+    access.require(Role::Owner)?;
+
+    let invite = state
+        .db
+        .find_invite(&channel, &id)
+        .await
+        .map_err(internal_error)?
+        .ok_or((StatusCode::NOT_FOUND, format!("no invite {}", id)))?;
+    state
+        .db
+        .delete_invite(&invite)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(response(StatusCode::NO_CONTENT, String::new()))
+}
+
+/// Adds the user to the channel an invite link names as an editor, unless
+/// the link or the invite behind it is no longer good. Members accepting
+/// again keep their role and don't use up a single-use invite.
+pub async fn accept_invite(state: &AppState, user: &User, token: &str) -> Result<Access, ApiError> {
+    let claims = invite::verify(&state.keys, token).map_err(|err| {
+        println!("invite: {:?}", err);
+        (StatusCode::GONE, "invalid or expired invite".to_owned())
+    })?;
+
+    let invite = state
+        .db
+        .find_invite(&claims.channel, &claims.id)
+        .await
+        .map_err(internal_error)?
+        .filter(|invite| !invite.is_expired())
+        .ok_or((StatusCode::GONE, "invite revoked or expired".to_owned()))?;
+
+    let channel = state
+        .db
+        .find_channel(&invite.channel)
+        .await
+        .map_err(internal_error)?
+        .unwrap_or(Channel {
+            channel: invite.channel.to_owned(),
+            ..Default::default()
+        });
+    check_open(&channel)?;
+
+    if state
+        .db
+        .find_member(&user.id, &invite.channel)
+        .await
+        .map_err(internal_error)?
+        .is_some()
+    {
+        return Access::load(state, user, &invite.channel).await;
+    }
+
+    state
+        .db
+        .insert_member(&Member::new(&user.id, &invite.channel, Role::Editor))
+        .await
+        .map_err(internal_error)?;
+    if invite.single_use {
+        state
+            .db
+            .delete_invite(&invite)
+            .await
+            .map_err(internal_error)?;
+    }
+
+    println!(
+        "{} joined {} by invite {}",
+        user.id, invite.channel, invite.id
+    );
+
+    Access::load(state, user, &invite.channel).await
+}
+
+pub async fn join_channel(
+    Path(token): Path<String>,
+    Extension(user): Extension<User>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, ApiError> {
+    let access = accept_invite(&state, &user, &token).await?;
+
+    Ok(json_response(
+        StatusCode::OK,
+        channel_json(&access.channel, &user, access.role()),
+    ))
+}
+
+/// Stored schedules of a channel for calendar clients: everything from
+/// `CALENDAR_PAST_DAYS` ago on, plus series that started before that.
+pub async fn calendar_scheds(state: &AppState, channel: &str) -> anyhow::Result<Vec<Sched>> {
+    let mut filter = SchedFilter {
+        from: Some(Utc::now().date_naive() - Duration::days(CALENDAR_PAST_DAYS)),
+        limit: Some(MAX_LIMIT),
+        ..Default::default()
+    };
+
+    let mut scheds = vec![];
+    loop {
+        let page = state.db.find_sched_by_channel(channel, &filter).await?;
+        scheds.extend(page.scheds);
+        match page.next {
+            Some(next) => filter.cursor = Some(next),
+            None => break,
+        }
+    }
+
+    for series in state.db.find_recurring_by_channel(channel).await? {
+        if !scheds.iter().any(|s| s.sid == series.sid) {
+            scheds.push(series);
+        }
+    }
+
+    Ok(scheds)
+}
+
+async fn calendar_response(state: &AppState, channel: &str, tz: Tz) -> Result<Response, ApiError> {
+    let scheds = calendar_scheds(state, channel)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "text/calendar; charset=utf-8")
+        .body(boxed(Body::from(ical::calendar(channel, &scheds, tz))))
+        .unwrap())
+}
+
+pub async fn get_calendar(
+    Path(channel): Path<String>,
+    Access { user, .. }: Access,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, ApiError> {
+    let tz = viewer_tz(&state, &user, &channel).await;
+
+    calendar_response(&state, &channel, tz).await
+}
+
+/// The subscription feed, for calendar clients that can't log in. It is
+/// served outside of `auth::auth`, the token in the URL is the credential.
+pub async fn get_feed(
+    Path((channel, token)): Path<(String, String)>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, ApiError> {
+    let found = state
+        .db
+        .find_channel(&channel)
+        .await
+        .map_err(internal_error)?;
+
+    match found.and_then(|c| c.feed_token) {
+        Some(feed_token) if feed_token == refresh::hash(&token) => {}
+        _ => return Err((StatusCode::NOT_FOUND, "no such feed".to_owned())),
+    }
+
+    let tz = viewer_tz(&state, &User::default(), &channel).await;
+
+    calendar_response(&state, &channel, tz).await
+}
+
+/// Imports the VEVENTs of an uploaded calendar, owned by the uploader, and
+/// reports what became of each. Events imported before are left alone.
+pub async fn import_calendar(
+    Path(channel): Path<String>,
+    access: Access,
+    State(state): State<Arc<AppState>>,
+    body: String,
+) -> Result<Response, ApiError> {
+    access.require(Role::Editor)?;
+    check_open(&access.channel)?;
+    let user = access.user;
+
+    let events = ical::parse_components(&body, "VEVENT")
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    let tz = viewer_tz(&state, &user, &channel).await;
+
+    let (mut imported, mut duplicates, mut failed) = (0, 0, 0);
+    let mut report = vec![];
+
+    for props in &events {
+        let text = |name: &str| {
+            props
+                .iter()
+                .find(|p| p.name == name)
+                .map(|p| ical::unescape_text(&p.value))
+        };
+        let (uid, summary) = (text("UID"), text("SUMMARY"));
+
+        let sched = match ical::sched_from_event(props, &channel, &user.id, tz) {
+            Ok(sched) => sched,
+            Err(err) => {
+                failed += 1;
+                report.push(serde_json::json!({
+                    "uid": uid, "sched": summary, "status": "failed", "error": err.to_string(),
+                }));
+                continue;
+            }
+        };
+
+        let found = state
+            .db
+            .find_sched_by_id(&channel, &sched.sid)
+            .await
+            .map_err(internal_error)?;
+
+        let status = if found.is_some() {
+            duplicates += 1;
+            "duplicate"
+        } else {
+            state
+                .db
+                .insert_sched(&tz::to_utc(&sched, tz))
+                .await
+                .map_err(internal_error)?;
+            imported += 1;
+            "imported"
+        };
+
+        report.push(serde_json::json!({
+            "uid": uid, "sched": summary, "status": status, "sid": sched.sid,
+        }));
+    }
+
+    Ok(json_response(
+        StatusCode::OK,
+        serde_json::json!({
+            "imported": imported,
+            "duplicates": duplicates,
+            "failed": failed,
+            "events": report,
+        }),
+    ))
+}
+
+pub fn feed_path(channel: &str, token: &str) -> String {
+    format!("/feeds/{}/{}/calendar.ics", channel, token)
+}
+
+/// Issues a new feed URL for the channel, revoking the previous one. The
+/// feed makes the channel readable by anyone with the URL, so only owners
+/// manage it.
+pub async fn create_feed(
+    Path(channel): Path<String>,
+    access: Access,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, ApiError> {
+    access.require(Role::Owner)?;
+    check_open(&access.channel)?;
+
+    let mut found = access.channel;
+    let token = Uuid::new_v4().simple().to_string();
+    found.feed_token = Some(refresh::hash(&token));
+
+    state
+        .db
+        .insert_channel(&found)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(json_response(
+        StatusCode::CREATED,
+        serde_json::json!({ "channel": channel, "url": feed_path(&channel, &token) }),
+    ))
+}
+
+pub async fn delete_feed(
+    Path(channel): Path<String>,
+    access: Access,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, ApiError> {
+    access.require(Role::Owner)?;
+
+    if let Some(mut found) = state
+        .db
+        .find_channel(&channel)
+        .await
+        .map_err(internal_error)?
+    {
+        found.feed_token = None;
+        state
+            .db
+            .insert_channel(&found)
+            .await
+            .map_err(internal_error)?;
+    }
+
+    Ok(response(StatusCode::NO_CONTENT, String::new()))
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct BulkQuery {
+    #[serde(default)]
+    format: bulk::Format,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+/// Dumps the stored rows of a channel, past ones included, as a download.
+pub async fn export_scheds(
+    Path(channel): Path<String>,
+    Query(query): Query<BulkQuery>,
+    _: Access,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, ApiError> {
+    let scheds = bulk::dump(state.db.as_ref(), &channel)
+        .await
+        .map_err(internal_error)?;
+    let body = bulk::write(&scheds, query.format).map_err(internal_error)?;
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, query.format.content_type())
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"{}.{}\"",
+                channel,
+                query.format.extension()
+            ),
+        )
+        .body(boxed(Body::from(body)))
+        .unwrap())
+}
+
+/// Imports rows written by `export_scheds` or by hand. With `dry_run` the
+/// rows are only validated; otherwise an invalid row fails the whole file.
+/// Editors import only schedules they own.
+pub async fn import_scheds(
+    Path(channel): Path<String>,
+    Query(query): Query<BulkQuery>,
+    access: Access,
+    State(state): State<Arc<AppState>>,
+    body: String,
+) -> Result<Response, ApiError> {
+    access.require(Role::Editor)?;
+    check_open(&access.channel)?;
+
+    let rows = bulk::read(&body, query.format)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?
+        .into_iter()
+        .map(|(line, row)| {
+            let row = row.and_then(|row| {
+                if access.can_manage(row.id.trim()) {
+                    Ok(row)
+                } else {
+                    Err(anyhow::anyhow!(
+                        "{} can't import schedules of {}",
+                        access.user.id,
+                        row.id
+                    ))
+                }
+            });
+            (line, row)
+        })
+        .collect();
+    let report = bulk::import(state.db.as_ref(), &channel, rows, query.dry_run)
+        .await
+        .map_err(internal_error)?;
+
+    let status = if report.failed > 0 && !report.dry_run {
+        StatusCode::UNPROCESSABLE_ENTITY
+    } else {
+        StatusCode::OK
+    };
+
+    Ok(json_response(status, serde_json::json!(report)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{Memory, ScheduleStore};
+
+    use super::*;
+
+    fn date(d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2030, 1, d).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_list_scheds_pages_series() {
+        let db = Memory::default();
+        for d in [2, 3, 9, 10, 16, 30] {
+            db.insert_sched(&Sched::new("home", "21kyu", &format!("{}일", d), date(d)))
+                .await
+                .unwrap();
+        }
+        let mut series = Sched::new("home", "21kyu", "스터디", date(1));
+        series.rrule = Some("FREQ=WEEKLY".to_owned());
+        db.insert_sched(&series).await.unwrap();
+
+        let mut filter = SchedFilter {
+            from: Some(date(1)),
+            to: Some(date(31)),
+            limit: Some(2),
+            ..SchedFilter::default()
+        };
+        let mut pages = vec![];
+        loop {
+            let page = list_scheds(&db, "home", &filter, Tz::UTC).await.unwrap();
+            pages.push(page.scheds.iter().map(|s| s.date_at).collect::<Vec<_>>());
+            match page.next {
+                Some(next) => filter.cursor = Some(next),
+                None => break,
+            }
+        }
+
+        assert!(pages.len() > 2);
+        assert!(pages[1].contains(&date(8)));
+        assert_eq!(
+            pages.concat(),
+            [1, 2, 3, 8, 9, 10, 15, 16, 22, 29, 30]
+                .into_iter()
+                .map(date)
+                .collect::<Vec<_>>()
+        );
+    }
+}
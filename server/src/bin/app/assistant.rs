@@ -0,0 +1,316 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::Response;
+use axum::{Extension, Json};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::api::{self, ApiError, SchedPatch};
+use crate::db::SchedFilter;
+use crate::gpt::{self, Intent, Question, SchedPayload, Target};
+use crate::member::Role;
+use crate::pat::Scope;
+use crate::perm::Access;
+use crate::sched::Sched;
+use crate::tz;
+use crate::AppState;
+
+#[derive(Deserialize, Debug)]
+pub struct Request {
+    query: String,
+    /// The schedule to change, picked from the candidates of an ambiguous
+    /// request.
+    #[serde(default)]
+    sid: Option<Uuid>,
+}
+
+/// The schedules a target could mean.
+enum Found {
+    One(Box<Sched>),
+    Several(Vec<Sched>),
+}
+
+fn bad_request(err: anyhow::Error) -> ApiError {
+    println!("err: {:?}", err);
+    (StatusCode::BAD_REQUEST, err.to_string())
+}
+
+/// Creates, moves or cancels a schedule in the user's active channel as the
+/// query asks, for those who can edit it and not with a read token, or
+/// answers a question about it without writing anything. When several schedules match a change,
+/// answers `409 Conflict` with the candidates, and the query can be sent
+/// again with the `sid` of the one meant.
+pub async fn invoke(
+    access: Access,
+    scope: Option<Extension<Scope>>,
+    State(state): State<Arc<AppState>>,
+    Json(input): Json<Request>,
+) -> Result<Response, ApiError> {
+    let user = &access.user;
+
+    let members = state
+        .db
+        .find_members_by_channel(&user.channel)
+        .await
+        .map_err(api::internal_error)?
+        .into_iter()
+        .map(|member| member.id)
+        .collect::<Vec<_>>();
+    let tz = api::viewer_tz(&state, user, &user.channel).await;
+    let intent = gpt::read_intent(state.llm.as_deref(), user, &members, tz, &input.query).await;
+
+    let can_write = || {
+        if matches!(scope, Some(Extension(Scope::Read))) {
+            return Err((
+                StatusCode::FORBIDDEN,
+                "read tokens can't change schedules".to_owned(),
+            ));
+        }
+        access
+            .require(Role::Editor)
+            .and_then(|_| api::check_open(&access.channel))
+    };
+    let (name, sched) = match intent.map_err(bad_request)? {
+        Intent::Query(question) => return ask(&state, &access, tz, &input.query, question).await,
+        Intent::Create(payload) => {
+            can_write()?;
+            ("create", create(&state, &access, tz, &payload).await?)
+        }
+        Intent::Update { target, changes } => {
+            can_write()?;
+            let stored = match find(&state, &access, tz, &target, input.sid).await? {
+                Found::One(stored) => *stored,
+                Found::Several(candidates) => return Ok(ambiguous(candidates)),
+            };
+            (
+                "update",
+                update(&state, &access, tz, &stored, changes).await?,
+            )
+        }
+        Intent::Delete { target } => {
+            can_write()?;
+            let stored = match find(&state, &access, tz, &target, input.sid).await? {
+                Found::One(stored) => *stored,
+                Found::Several(candidates) => return Ok(ambiguous(candidates)),
+            };
+            api::remove_sched(&state, &access, &stored).await?;
+            ("delete", tz::to_local(&stored, tz))
+        }
+    };
+
+    let page = api::list_scheds(
+        state.db.as_ref(),
+        &user.channel,
+        &SchedFilter::default(),
+        tz,
+    )
+    .await
+    .map_err(api::internal_error)?;
+    let content = serde_json::json!({
+        "intent": name,
+        "sched": sched,
+        "user": user.id,
+        "channel": user.channel,
+        "tz": tz.name(),
+        "data": page.scheds,
+        "next": page.next,
+    });
+
+    Ok(api::json_response(StatusCode::OK, content))
+}
+
+/// Answers with the schedules the question is about, and writes nothing.
+async fn ask(
+    state: &AppState,
+    access: &Access,
+    tz: Tz,
+    query: &str,
+    question: Question,
+) -> Result<Response, ApiError> {
+    let user = &access.user;
+    let filter = SchedFilter {
+        from: Some(question.from),
+        to: Some(question.to),
+        user: question.user.to_owned(),
+        limit: Some(api::MAX_LIMIT),
+        ..SchedFilter::default()
+    };
+    let page = api::list_scheds(state.db.as_ref(), &user.channel, &filter, tz)
+        .await
+        .map_err(api::internal_error)?;
+    let answer = gpt::read_answer(state.llm.as_deref(), tz, query, &question, &page.scheds).await;
+
+    let content = serde_json::json!({
+        "intent": "query",
+        "answer": answer,
+        "question": question,
+        "scheds": page.scheds,
+        "user": user.id,
+        "channel": user.channel,
+        "tz": tz.name(),
+    });
+
+    Ok(api::json_response(StatusCode::OK, content))
+}
+
+async fn create(
+    state: &AppState,
+    access: &Access,
+    tz: Tz,
+    payload: &SchedPayload,
+) -> Result<Sched, ApiError> {
+    let user = &access.user;
+    if let Err(err) = payload.validate(user) {
+        println!("err: {:?}", err);
+        return Err((StatusCode::FORBIDDEN, err.to_string()));
+    }
+    let time = payload.time().map_err(bad_request)?;
+    let rrule = payload.rrule().map_err(bad_request)?;
+
+    let mut sched = Sched::new(&user.channel, &user.id, &payload.title, payload.date);
+    sched.set_time(time);
+    sched.rrule = rrule;
+
+    state
+        .db
+        .insert_sched(&tz::to_utc(&sched, tz))
+        .await
+        .map_err(api::internal_error)?;
+
+    Ok(sched)
+}
+
+/// Moving a timed schedule to a new start keeps how long it takes.
+async fn update(
+    state: &AppState,
+    access: &Access,
+    tz: Tz,
+    stored: &Sched,
+    mut changes: SchedPatch,
+) -> Result<Sched, ApiError> {
+    let prev = tz::to_local(stored, tz);
+    if changes.start_time.is_some() && changes.end_time.is_none() && changes.duration.is_none() {
+        if let (Some(start), Some(end)) = (prev.start_time, prev.end_time) {
+            changes.duration = Some((end - start).num_minutes());
+        }
+    }
+
+    api::patch_sched(state, access, stored, &changes).await
+}
+
+/// Looks for the target among the channel's schedules on its date, or from
+/// today on when it has none.
+async fn find(
+    state: &AppState,
+    access: &Access,
+    tz: Tz,
+    target: &Target,
+    sid: Option<Uuid>,
+) -> Result<Found, ApiError> {
+    let channel = &access.user.channel;
+    if let Some(sid) = sid {
+        return api::find_sched(state, channel, &sid)
+            .await
+            .map(|sched| Found::One(Box::new(sched)));
+    }
+
+    let filter = SchedFilter {
+        from: target.date,
+        to: target.date,
+        limit: Some(api::MAX_LIMIT),
+        ..SchedFilter::default()
+    };
+    let page = api::list_scheds(state.db.as_ref(), channel, &filter, tz)
+        .await
+        .map_err(api::internal_error)?;
+
+    let mut found = candidates(target, page.scheds);
+    match found.len() {
+        0 => Err((
+            StatusCode::NOT_FOUND,
+            format!("no schedule matches {:?}", target.title),
+        )),
+        1 => api::find_sched(state, channel, &found.remove(0).sid)
+            .await
+            .map(|sched| Found::One(Box::new(sched))),
+        _ => Ok(Found::Several(found)),
+    }
+}
+
+fn ambiguous(candidates: Vec<Sched>) -> Response {
+    api::json_response(
+        StatusCode::CONFLICT,
+        serde_json::json!({
+            "message": "several schedules match, send the query again with the sid of one",
+            "candidates": candidates,
+        }),
+    )
+}
+
+/// The schedules sharing the most title words with the target, once per
+/// series. Without title words, everything at its date and time.
+fn candidates(target: &Target, scheds: Vec<Sched>) -> Vec<Sched> {
+    let words = target.words();
+    let score = |sched: &Sched| {
+        let title = sched.sched.to_lowercase();
+        words
+            .iter()
+            .filter(|word| title.contains(word.as_str()))
+            .count()
+    };
+
+    let mut seen = HashSet::new();
+    let scheds = scheds
+        .into_iter()
+        .filter(|sched| target.start_time.is_none() || sched.start_time == target.start_time)
+        .filter(|sched| seen.insert(sched.sid))
+        .collect::<Vec<_>>();
+
+    let best = scheds.iter().map(score).max().unwrap_or_default();
+    if best == 0 && !words.is_empty() {
+        return vec![];
+    }
+    scheds
+        .into_iter()
+        .filter(|sched| score(sched) == best)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    #[test]
+    fn test_candidates() {
+        let date = NaiveDate::from_ymd_opt(2023, 6, 30).unwrap();
+        let picnic = Sched::new("home", "21kyu", "봄소풍", date);
+        let dentist = Sched::new("home", "21kyu", "Dentist appointment", date);
+        let checkup = Sched::new("home", "21kyu", "Dentist checkup", date);
+        let mut repeat = checkup.clone();
+        repeat.date_at = date.succ_opt().unwrap();
+        let scheds = vec![picnic, dentist, checkup, repeat];
+
+        let target = |title: &str| Target {
+            title: title.to_owned(),
+            ..Target::default()
+        };
+
+        let found = candidates(&target("봄소풍"), scheds.clone());
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].sched, "봄소풍");
+
+        let found = candidates(&target("my dentist appointment"), scheds.clone());
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].sched, "Dentist appointment");
+
+        assert_eq!(candidates(&target("the dentist"), scheds.clone()).len(), 2);
+        assert!(candidates(&target("haircut"), scheds.clone()).is_empty());
+        assert_eq!(candidates(&Target::default(), scheds).len(), 3);
+    }
+}
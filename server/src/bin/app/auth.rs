@@ -1,69 +1,114 @@
-use std::env;
 use std::sync::Arc;
 
 use anyhow::{anyhow, Result};
 use axum::body::{boxed, Body};
 use axum::extract::State;
-use axum::http::{header, HeaderValue, Request, StatusCode};
+use axum::http::{header, HeaderValue, Method, Request, StatusCode};
 use axum::middleware::Next;
 use axum::response::Response;
+use base64::{engine::general_purpose, Engine as _};
 use hyper::HeaderMap;
-use oauth2::basic::BasicClient;
 use oauth2::reqwest::async_http_client;
-use oauth2::{
-    AuthUrl, AuthorizationCode, ClientId, ClientSecret, RedirectUrl, TokenResponse, TokenUrl,
-};
-use octocrab::Octocrab;
+use oauth2::{AuthorizationCode, CsrfToken, PkceCodeChallenge, PkceCodeVerifier, TokenResponse};
 use serde::{Deserialize, Serialize};
 use tower_cookies::{Cookie, Cookies};
+use uuid::Uuid;
 
+use crate::api;
+use crate::channel::Channel;
+use crate::db::Store;
+use crate::identity::Identity;
+use crate::idp::Profile;
+use crate::keys::{KeyRing, SESSION_AUDIENCE};
+use crate::member::{Member, Role};
+use crate::pat::{self, Scope};
+use crate::refresh::{self, RefreshToken, REFRESH_MAX_AGE};
+use crate::session::Session;
 use crate::user::User;
 use crate::AppState;
 
 const BEARER: &str = "Bearer ";
+const BASIC: &str = "Basic ";
 const JWT_MAX_AGES: i64 = 600;
-const DEFAULT_CHANNEL: &str = "home";
+const LOGIN_COOKIE: &str = "oauth_login";
+/// How long a login attempt may spend at the provider.
+const LOGIN_MAX_AGE: i64 = 600;
+/// The `aud` of login attempt cookies.
+const LOGIN_AUDIENCE: &str = "sched-bird:login";
+/// Only sent to `/auth`, where sessions are started and refreshed.
+const REFRESH_COOKIE: &str = "refresh_token";
 
-#[derive(Debug, Deserialize, Serialize)]
-struct Claims {
+/// The signed-in user and their active channel. Handlers that switch
+/// channels issue a new token from it.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Claims {
     user: String,
     channel: String,
     token: String,
+    /// The `Session` the token was issued in. Tokens from before sessions
+    /// have none and are let through until they expire.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    jti: Option<Uuid>,
     exp: usize,
 }
 
-pub fn create_jwt(user: &str, channel: &str, token: &str) -> Result<String> {
-    let expiration = chrono::Utc::now()
-        .checked_add_signed(chrono::Duration::seconds(JWT_MAX_AGES))
-        .expect("valid timestamp")
-        .timestamp();
+impl Claims {
+    fn new(user: &str, channel: &str, token: &str, jti: Option<Uuid>) -> Self {
+        let expiration = chrono::Utc::now()
+            .checked_add_signed(chrono::Duration::seconds(JWT_MAX_AGES))
+            .expect("valid timestamp")
+            .timestamp();
 
-    let claims = Claims {
-        user: user.to_owned(),
-        channel: channel.to_owned(),
-        token: token.to_owned(),
-        exp: expiration as usize,
-    };
+        Self {
+            user: user.to_owned(),
+            channel: channel.to_owned(),
+            token: token.to_owned(),
+            jti,
+            exp: expiration as usize,
+        }
+    }
 
-    let header = jsonwebtoken::Header::new(jsonwebtoken::Algorithm::HS512);
-    jsonwebtoken::encode(
-        &header,
-        &claims,
-        &jsonwebtoken::EncodingKey::from_secret(env::var("JWT_SECRET")?.as_bytes()),
-    )
-    .map_err(|e| anyhow!("failed to encode jwt: {}", e))
+    /// The same sign-in with `channel` as the active channel.
+    pub fn with_channel(&self, channel: &str) -> Self {
+        Self::new(&self.user, channel, &self.token, self.jti)
+    }
+
+    pub fn session(&self) -> Option<Uuid> {
+        self.jti
+    }
+
+    pub fn encode(&self, keys: &KeyRing) -> Result<String> {
+        keys.encode(SESSION_AUDIENCE, self)
+    }
 }
 
-fn channel_from_header(headers: &HeaderMap<HeaderValue>) -> Result<String> {
-    let header = match headers.get("channel") {
-        Some(header) => header,
-        None => return Err(anyhow!("missing channel header")),
-    };
-    let channel = match header.to_str() {
-        Ok(channel) => channel,
-        Err(_) => return Err(anyhow!("invalid channel header")),
-    };
-    Ok(channel.to_owned())
+/// One login attempt: the provider, the `state` sent to it, the PKCE
+/// verifier for the code it sends back and the nonce its ID token must
+/// carry. It waits in a signed cookie for the callback, so a callback
+/// started by someone else's browser is refused.
+#[derive(Debug, Deserialize, Serialize)]
+struct LoginAttempt {
+    provider: String,
+    state: String,
+    verifier: String,
+    nonce: String,
+    /// The user signed in when linking another provider to them.
+    link: Option<String>,
+    exp: usize,
+}
+
+/// Signed with the session keys for an audience of its own, so it never
+/// passes for a session token, here or with services using the JWKS.
+impl LoginAttempt {
+    fn encode(&self, keys: &KeyRing) -> Result<String> {
+        keys.encode(LOGIN_AUDIENCE, self)
+            .map_err(|e| anyhow!("failed to encode login attempt: {}", e))
+    }
+
+    fn decode(keys: &KeyRing, jwt: &str) -> Result<Self> {
+        keys.decode(LOGIN_AUDIENCE, jwt)
+            .map_err(|e| anyhow!("failed to decode login attempt: {}", e))
+    }
 }
 
 fn jwt_from_header(headers: &HeaderMap<HeaderValue>) -> Result<String> {
@@ -93,41 +138,352 @@ fn jwt_from_cookie(cookies: &Cookies) -> Result<String> {
     Ok(auth_cookie)
 }
 
-async fn authorize(channel: &str, jwt: &str) -> Result<User> {
-    let decoded = jsonwebtoken::decode::<Claims>(
-        jwt,
-        &jsonwebtoken::DecodingKey::from_secret(env::var("JWT_SECRET")?.as_bytes()),
-        &jsonwebtoken::Validation::new(jsonwebtoken::Algorithm::HS512),
-    )
-    .map_err(|e| anyhow!("failed to decode jwt: {}", e))?;
+/// User id and password of a Basic authorization header.
+fn basic_from_header(headers: &HeaderMap<HeaderValue>) -> Result<(String, String)> {
+    let auth_header = headers
+        .get(header::AUTHORIZATION)
+        .ok_or_else(|| anyhow!("missing authorization header"))?
+        .to_str()
+        .map_err(|_| anyhow!("invalid authorization header"))?;
+    let encoded = auth_header
+        .strip_prefix(BASIC)
+        .ok_or_else(|| anyhow!("invalid authorization header"))?;
+    let decoded = String::from_utf8(general_purpose::STANDARD.decode(encoded)?)?;
 
-    if decoded.claims.channel != channel {
-        return Err(anyhow!("invalid channel"));
+    match decoded.split_once(':') {
+        Some((user, password)) => Ok((user.to_owned(), password.to_owned())),
+        None => Err(anyhow!("invalid authorization header")),
     }
+}
 
-    if decoded.claims.exp < chrono::Utc::now().timestamp() as usize {
+fn decode_claims(keys: &KeyRing, jwt: &str) -> Result<Claims> {
+    let claims = keys.decode::<Claims>(SESSION_AUDIENCE, jwt)?;
+
+    if claims.exp < chrono::Utc::now().timestamp() as usize {
         return Err(anyhow!("expired jwt"));
     }
 
-    Ok(User {
-        id: decoded.claims.user,
-        channel: decoded.claims.channel,
+    Ok(claims)
+}
+
+/// Whether `id` may make `channel` their active channel.
+async fn can_activate(db: &dyn Store, id: &str, channel: &str) -> Result<bool> {
+    if db.find_member(id, channel).await?.is_none() {
+        return Ok(false);
+    }
+    Ok(!db
+        .find_channel(channel)
+        .await?
+        .is_some_and(|c| c.is_archived()))
+}
+
+/// Creates the channel every new user starts in, named after them.
+async fn create_personal_channel(db: &dyn Store, id: &str) -> Result<String> {
+    let mut channel = id.to_owned();
+    while api::channel_exists(db, &channel).await? {
+        channel = format!("{}-{}", id, &uuid::Uuid::new_v4().simple().to_string()[..6]);
+    }
+
+    db.insert_channel(&Channel {
+        channel: channel.to_owned(),
+        name: Some(id.to_owned()),
+        ..Default::default()
     })
+    .await?;
+    db.insert_member(&Member::new(id, &channel, Role::Owner))
+        .await?;
+
+    Ok(channel)
+}
+
+/// The channel a user lands in: the one stored with them while they may use
+/// it, else the first of their channels that isn't archived, else a new
+/// personal channel.
+async fn active_channel(db: &dyn Store, user: &User) -> Result<String> {
+    if !user.channel.is_empty() && can_activate(db, &user.id, &user.channel).await? {
+        return Ok(user.channel.to_owned());
+    }
+
+    let members = db.find_members_by_user(&user.id).await?;
+
+    // Users from before memberships only have the channel stored with them.
+    if members.is_empty() && !user.channel.is_empty() {
+        let legacy = Member {
+            id: user.id.to_owned(),
+            channel: user.channel.to_owned(),
+            role: None,
+        };
+        db.insert_member(&Member::new(&user.id, &user.channel, legacy.role()))
+            .await?;
+        return Ok(user.channel.to_owned());
+    }
+
+    for member in members {
+        if can_activate(db, &user.id, &member.channel).await? {
+            return Ok(member.channel);
+        }
+    }
+
+    create_personal_channel(db, &user.id).await
+}
+
+/// Loads the user with `claimed` as the active channel when they are still a
+/// member of it and it isn't archived, otherwise with `active_channel`,
+/// which is remembered for their next sign-in. New users are stored.
+async fn resolve_user(db: &dyn Store, id: &str, claimed: Option<&str>) -> Result<User> {
+    let found = db.find_user_by_id(id).await?;
+    let mut user = found.clone().unwrap_or_else(|| User {
+        id: id.to_owned(),
+        ..Default::default()
+    });
+
+    if let Some(channel) = claimed {
+        if found.is_some() && can_activate(db, id, channel).await? {
+            user.channel = channel.to_owned();
+            return Ok(user);
+        }
+    }
+
+    user.channel = active_channel(db, &user).await?;
+    if found.map(|found| found.channel) != Some(user.channel.to_owned()) {
+        db.insert_user(&user).await?;
+    }
+
+    Ok(user)
+}
+
+fn dav_unauthorized() -> Response {
+    Response::builder()
+        .status(StatusCode::UNAUTHORIZED)
+        .header(
+            header::WWW_AUTHENTICATE,
+            "Basic realm=\"sched-bird\", charset=\"UTF-8\"",
+        )
+        .body(boxed(Body::empty()))
+        .unwrap()
+}
+
+/// Authenticates CalDAV clients, which can't follow the OAuth redirect.
+/// They send the user id with an auth token or a personal access token as
+/// Basic credentials, or the auth token alone as a Bearer token, and are
+/// asked for credentials on failure.
+pub async fn dav_auth<B>(
+    State(shared): State<Arc<AppState>>,
+    mut req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let basic = basic_from_header(req.headers());
+
+    if let Some((login, secret)) = basic
+        .as_ref()
+        .ok()
+        .filter(|(_, password)| password.starts_with(pat::PREFIX))
+    {
+        let found =
+            personal_token_user(shared.db.as_ref(), secret, req.method(), req.uri().path()).await;
+        return match found {
+            Ok((user, scope)) if user.id == *login => {
+                req.extensions_mut().insert(user);
+                req.extensions_mut().insert(scope);
+                next.run(req).await
+            }
+            Ok(_) | Err(StatusCode::UNAUTHORIZED) => dav_unauthorized(),
+            Err(status) => Response::builder()
+                .status(status)
+                .body(boxed(Body::empty()))
+                .unwrap(),
+        };
+    }
+
+    let claims = match basic {
+        Ok((user, password)) => decode_claims(&shared.keys, &password)
+            .ok()
+            .filter(|claims| claims.user == user),
+        Err(_) => jwt_from_header(req.headers())
+            .and_then(|jwt| decode_claims(&shared.keys, &jwt))
+            .ok(),
+    };
+
+    let claims = match claims {
+        Some(claims) => claims,
+        None => return dav_unauthorized(),
+    };
+
+    let ip = client_ip(req.headers());
+    if let Err(status) = check_session(shared.db.as_ref(), &claims, ip.as_deref()).await {
+        return Response::builder()
+            .status(status)
+            .body(boxed(Body::empty()))
+            .unwrap();
+    }
+
+    let user = match resolve_user(shared.db.as_ref(), &claims.user, Some(&claims.channel)).await {
+        Ok(user) => user,
+        Err(err) => {
+            println!("err: {:?}", err);
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(boxed(Body::empty()))
+                .unwrap();
+        }
+    };
+
+    req.extensions_mut().insert(user);
+
+    next.run(req).await
+}
+
+/// The client's address as the proxy in front saw it.
+fn client_ip(headers: &HeaderMap<HeaderValue>) -> Option<String> {
+    headers
+        .get("x-forwarded-for")
+        .or_else(|| headers.get("x-real-ip"))
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(',').next())
+        .map(|ip| ip.trim().to_owned())
+        .filter(|ip| !ip.is_empty())
+}
+
+fn user_agent(headers: &HeaderMap<HeaderValue>) -> String {
+    headers
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .map(|agent| agent.chars().take(200).collect())
+        .unwrap_or_else(|| "unknown".to_owned())
+}
+
+/// Rejects tokens of sessions that were signed out, and notes when and
+/// from where the others were last used.
+async fn check_session(
+    db: &dyn Store,
+    claims: &Claims,
+    ip: Option<&str>,
+) -> Result<(), StatusCode> {
+    let jti = match claims.jti {
+        Some(jti) => jti,
+        None => return Ok(()),
+    };
+
+    let mut session = db
+        .find_session(&jti)
+        .await
+        .map_err(internal_status)?
+        .filter(|session| session.user == claims.user)
+        .ok_or_else(|| {
+            println!("session {} of {} was revoked", jti, claims.user);
+            StatusCode::UNAUTHORIZED
+        })?;
+
+    if session.seen(ip) {
+        db.insert_session(&session).await.map_err(internal_status)?;
+    }
+    Ok(())
+}
+
+/// The user a personal access token acts for, in the token's channel when
+/// it is for one, and the token's scope.
+async fn personal_token_user(
+    db: &dyn Store,
+    secret: &str,
+    method: &Method,
+    path: &str,
+) -> Result<(User, Scope), StatusCode> {
+    let mut token = db
+        .find_personal_token(&refresh::hash(secret))
+        .await
+        .map_err(internal_status)?
+        .filter(|token| !token.is_expired())
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+
+    if !token.allows(method, path) {
+        println!(
+            "token {} of {} can't {} {}",
+            token.tid, token.id, method, path
+        );
+        return Err(StatusCode::FORBIDDEN);
+    }
+    if token.used() {
+        db.insert_personal_token(&token)
+            .await
+            .map_err(internal_status)?;
+    }
+
+    let user = resolve_user(db, &token.id, token.channel.as_deref())
+        .await
+        .map_err(internal_status)?;
+    // Left or archived since the token was created.
+    if matches!(&token.channel, Some(channel) if channel != &user.channel) {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    Ok((user, token.scope()))
+}
+
+/// Signs a session out: its access tokens are refused from now on and its
+/// refresh tokens are gone.
+pub async fn revoke_session(db: &dyn Store, session: &Session) -> Result<()> {
+    db.delete_refresh_tokens_by_session(&session.id).await?;
+    db.delete_session(session).await
+}
+
+fn internal_status(err: anyhow::Error) -> StatusCode {
+    println!("err: {:?}", err);
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
+fn query_param(query: &str, name: &str) -> Option<String> {
+    url::form_urlencoded::parse(query.as_bytes())
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
 }
 
 pub async fn auth<B>(
     cookies: Cookies,
     State(shared): State<Arc<AppState>>,
-    req: Request<B>,
+    mut req: Request<B>,
     next: Next<B>,
 ) -> Result<Response, StatusCode> {
-    println!("cookies len: {}", cookies.list().len());
-    println!("request headers: {:?}", req.headers());
+    let query = req.uri().query().unwrap_or_default().to_owned();
+    let ip = client_ip(req.headers());
 
-    let request_channel = match channel_from_header(req.headers()) {
-        Ok(channel) => channel,
-        _ => DEFAULT_CHANNEL.to_owned(),
-    };
+    // Scripts and bots, with a personal access token instead of a session.
+    if let Some(secret) = jwt_from_header(req.headers())
+        .ok()
+        .filter(|bearer| bearer.starts_with(pat::PREFIX))
+    {
+        let (user, scope) =
+            personal_token_user(shared.db.as_ref(), &secret, req.method(), req.uri().path())
+                .await?;
+        println!("user: {:?}", user);
+        req.extensions_mut().insert(user);
+        req.extensions_mut().insert(scope);
+        return Ok(next.run(req).await);
+    }
+
+    // A provider sending the user back, from a login or from linking
+    // another provider to the account signed in.
+    let callback = query_param(&query, "code").zip(query_param(&query, "state"));
+    if let Some((code, state)) = &callback {
+        if let Some(attempt) = take_login_attempt(&shared.keys, &cookies) {
+            let (id, token) = complete_login(&shared, attempt, code, state).await?;
+            let user = resolve_user(shared.db.as_ref(), &id, None)
+                .await
+                .map_err(internal_status)?;
+            let session = Session::new(&id, &user_agent(req.headers()), ip.as_deref());
+            shared
+                .db
+                .insert_session(&session)
+                .await
+                .map_err(internal_status)?;
+            issue_refresh_token(shared.db.as_ref(), &cookies, &id, session.id)
+                .await
+                .map_err(internal_status)?;
+            let claims = Claims::new(&id, &user.channel, &token, Some(session.id));
+            let jwt = claims.encode(&shared.keys).map_err(internal_status)?;
+
+            println!("user: {:?}", user);
+            return Ok(auth_next(req, next, user, claims, &jwt, &cookies).await);
+        }
+    }
 
     let jwt = match jwt_from_header(req.headers()) {
         Ok(jwt) => Ok(jwt),
@@ -139,164 +495,677 @@ pub async fn auth<B>(
 
     match jwt {
         Ok(jwt) => {
-            println!("jwt: {:?}", jwt);
-            let user = match authorize(&request_channel, &jwt).await {
-                Ok(user) => user,
+            let claims = match decode_claims(&shared.keys, &jwt) {
+                Ok(claims) => claims,
+                _ if cookies.get(REFRESH_COOKIE).is_some() => {
+                    let (user, claims, jwt) =
+                        refresh_session(&shared, &cookies, ip.as_deref()).await?;
+                    return Ok(auth_next(req, next, user, claims, &jwt, &cookies).await);
+                }
                 _ => return Err(StatusCode::UNAUTHORIZED),
             };
+            check_session(shared.db.as_ref(), &claims, ip.as_deref()).await?;
+            let user = resolve_user(shared.db.as_ref(), &claims.user, Some(&claims.channel))
+                .await
+                .map_err(internal_status)?;
+
+            // The claimed channel was left or archived since the token was
+            // issued, so the user moves to another one.
+            let (claims, jwt) = if user.channel != claims.channel {
+                let claims = claims.with_channel(&user.channel);
+                let jwt = claims
+                    .encode(&shared.keys)
+                    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+                (claims, jwt)
+            } else {
+                (claims, jwt)
+            };
+
             println!("user: {:?}", user);
-            Ok(auth_next(req, next, user, &jwt, &cookies).await)
+            Ok(auth_next(req, next, user, claims, &jwt, &cookies).await)
         }
-        _ => {
-            let query = req.uri().query().unwrap_or_default();
-            println!("query: {}", query);
-
-            match get_github_user_id_and_token(query, &shared).await {
-                Ok((id, token)) => {
-                    let jwt = create_jwt(&id, &request_channel, &token).unwrap();
-                    let user_opt = shared.db.find_user_by_id(&id).await.unwrap();
-                    let user = match user_opt {
-                        Some(user) => user,
-                        None => {
-                            let user = User {
-                                id,
-                                channel: request_channel.clone(),
-                            };
-                            shared.db.insert_user(&user).await.unwrap();
-                            user
-                        }
-                    };
-
-                    println!("user: {:?}", user);
-                    Ok(auth_next(req, next, user, &jwt, &cookies).await)
+        // A callback without the login attempt it answers.
+        _ if callback.is_some() => Err(StatusCode::UNAUTHORIZED),
+        // The access token ran out, but maybe not the session.
+        _ if cookies.get(REFRESH_COOKIE).is_some() => {
+            match refresh_session(&shared, &cookies, ip.as_deref()).await {
+                Ok((user, claims, jwt)) => {
+                    Ok(auth_next(req, next, user, claims, &jwt, &cookies).await)
                 }
-                Err(StatusCode::FOUND) => Ok(response_redirect_auth(&shared)),
-                _ => Err(StatusCode::UNAUTHORIZED),
+                Err(StatusCode::UNAUTHORIZED) => start_login(
+                    &shared,
+                    &cookies,
+                    query_param(&query, "provider").as_deref(),
+                    None,
+                ),
+                Err(status) => Err(status),
             }
         }
+        _ => start_login(
+            &shared,
+            &cookies,
+            query_param(&query, "provider").as_deref(),
+            None,
+        ),
     }
 }
 
-fn response_redirect_auth(shared: &Arc<AppState>) -> Response {
-    println!("redirect to github");
+/// Starts a login attempt at `provider`, the first configured one when
+/// `None`, with a fresh state and PKCE challenge. With `link` the account
+/// signed in at the provider is linked to that user instead.
+pub fn start_login(
+    shared: &AppState,
+    cookies: &Cookies,
+    provider: Option<&str>,
+    link: Option<&str>,
+) -> Result<Response, StatusCode> {
+    let provider = shared
+        .providers
+        .get(provider)
+        .ok_or(StatusCode::NOT_FOUND)?;
 
-    Response::builder()
+    println!("redirect to {}", provider.name());
+
+    let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
+    let nonce = CsrfToken::new_random();
+    let mut request = provider
+        .client()
+        .authorize_url(CsrfToken::new_random)
+        .add_scopes(provider.scopes())
+        .set_pkce_challenge(pkce_challenge);
+    if provider.uses_nonce() {
+        request = request.add_extra_param("nonce", nonce.secret());
+    }
+    let (authorize_url, csrf_state) = request.url();
+
+    let attempt = LoginAttempt {
+        provider: provider.name().to_owned(),
+        state: csrf_state.secret().to_owned(),
+        verifier: pkce_verifier.secret().to_owned(),
+        nonce: nonce.secret().to_owned(),
+        link: link.map(str::to_owned),
+        exp: (chrono::Utc::now().timestamp() + LOGIN_MAX_AGE) as usize,
+    }
+    .encode(&shared.keys)
+    .map_err(internal_status)?;
+    cookies.add(
+        Cookie::parse(format!(
+            "{}={}; Secure; HttpOnly; SameSite=Lax; Path=/auth; Max-Age={}",
+            LOGIN_COOKIE, attempt, LOGIN_MAX_AGE
+        ))
+        .unwrap(),
+    );
+
+    Ok(Response::builder()
         .status(StatusCode::FOUND)
-        .header(header::LOCATION, shared.authorize_url.to_string())
+        .header(header::LOCATION, authorize_url.to_string())
         .body(boxed(Body::empty()))
-        .unwrap()
+        .unwrap())
 }
 
-pub fn create_github_client() -> BasicClient {
-    let github_client_id =
-        ClientId::new(env::var("GITHUB_CLIENT_ID").expect("Missing the GITHUB_CLIENT_ID env"));
-    let github_client_secret = ClientSecret::new(
-        env::var("GITHUB_CLIENT_SECRET").expect("Missing the GITHUB_CLIENT_SECRET env"),
-    );
+/// Each attempt is good for one callback.
+fn take_login_attempt(keys: &KeyRing, cookies: &Cookies) -> Option<LoginAttempt> {
+    let attempt = cookies
+        .get(LOGIN_COOKIE)
+        .and_then(|cookie| LoginAttempt::decode(keys, cookie.value()).ok());
+    cookies.remove(Cookie::build(LOGIN_COOKIE, "").path("/auth").finish());
+    attempt
+}
 
-    let auth_url = AuthUrl::new("https://github.com/login/oauth/authorize".to_string())
-        .expect("Invalid auth url");
-    let token_url = TokenUrl::new("https://github.com/login/oauth/access_token".to_string())
-        .expect("Invalid token url");
+/// Exchanges the code of a callback and returns the user signed in with
+/// the provider's access token.
+async fn complete_login(
+    shared: &AppState,
+    attempt: LoginAttempt,
+    code: &str,
+    state: &str,
+) -> Result<(String, String), StatusCode> {
+    if attempt.state != state {
+        println!("state {} doesn't match a login attempt", state);
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    let provider = shared
+        .providers
+        .get(Some(&attempt.provider))
+        .ok_or(StatusCode::UNAUTHORIZED)?;
 
-    BasicClient::new(
-        github_client_id,
-        Some(github_client_secret),
-        auth_url,
-        Some(token_url),
-    )
-    .set_redirect_uri(
-        RedirectUrl::new("https://sched.sinabro.io/auth".to_string())
-            .expect("Invalid redirect url"),
+    let token = provider
+        .client()
+        .exchange_code(AuthorizationCode::new(code.to_owned()))
+        .set_pkce_verifier(PkceCodeVerifier::new(attempt.verifier))
+        .request_async(async_http_client)
+        .await
+        .map_err(|err| {
+            println!("{} token exchange failed: {:?}", provider.name(), err);
+            StatusCode::UNAUTHORIZED
+        })?;
+
+    let profile = provider
+        .profile(&token, &attempt.nonce)
+        .await
+        .map_err(|err| {
+            println!("{} profile failed: {:?}", provider.name(), err);
+            StatusCode::UNAUTHORIZED
+        })?;
+    println!("{} user: {:?}", provider.name(), profile);
+
+    let id = sign_in(
+        shared.db.as_ref(),
+        provider.name(),
+        &profile,
+        attempt.link.as_deref(),
     )
+    .await?;
+
+    Ok((id, token.access_token().secret().to_owned()))
 }
 
-async fn get_github_user_id_and_token(
-    query: &str,
-    shared: &Arc<AppState>,
-) -> Result<(String, String), StatusCode> {
-    println!("query: {}", query);
+/// Whether no one signed in or was added to a channel as `id` yet.
+async fn is_free(db: &dyn Store, id: &str) -> Result<bool> {
+    Ok(db.find_user_by_id(id).await?.is_none()
+        && db.find_members_by_user(id).await?.is_empty()
+        && db.find_identities_by_user(id).await?.is_empty())
+}
+
+/// A user id from a provider login, in the letters, digits and `-` of the
+/// GitHub logins ids used to be.
+fn user_id_hint(login: &str) -> String {
+    let local = login.split('@').next().unwrap_or(login);
+    let id = local
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .take(32)
+        .collect::<String>();
+
+    match id.trim_matches('-') {
+        "" => "user".to_owned(),
+        id => id.to_owned(),
+    }
+}
+
+/// The id of a user signing in for the first time. A GitHub login stays the
+/// id, which also picks up users from before identities were linked, unless
+/// another account claimed it. Other logins get a suffix when taken.
+async fn new_user_id(db: &dyn Store, provider: &str, login: &str) -> Result<String> {
+    let hint = user_id_hint(login);
+    if provider == "github" && hint == login && db.find_identities_by_user(login).await?.is_empty()
+    {
+        return Ok(hint);
+    }
+
+    let mut id = hint.to_owned();
+    while !is_free(db, &id).await? {
+        id = format!(
+            "{}-{}",
+            hint,
+            &uuid::Uuid::new_v4().simple().to_string()[..6]
+        );
+    }
+    Ok(id)
+}
+
+/// The user a provider account signs in as. With `link` an account not
+/// linked yet is linked to that user; one linked to someone else can't be.
+async fn sign_in(
+    db: &dyn Store,
+    provider: &str,
+    profile: &Profile,
+    link: Option<&str>,
+) -> Result<String, StatusCode> {
+    let found = db
+        .find_identity(provider, &profile.subject)
+        .await
+        .map_err(internal_status)?;
+
+    let id = match (found, link) {
+        (Some(found), Some(link)) if found.id != link => {
+            println!(
+                "{} {} is linked to {}, not {}",
+                provider, profile.login, found.id, link
+            );
+            return Err(StatusCode::CONFLICT);
+        }
+        (Some(found), _) => found.id,
+        (None, Some(link)) => link.to_owned(),
+        (None, None) => new_user_id(db, provider, &profile.login)
+            .await
+            .map_err(internal_status)?,
+    };
 
-    let mut params = url::form_urlencoded::parse(query.as_bytes()).into_owned();
+    // Also keeps the login current.
+    db.insert_identity(&Identity {
+        provider: provider.to_owned(),
+        subject: profile.subject.to_owned(),
+        id: id.to_owned(),
+        login: profile.login.to_owned(),
+    })
+    .await
+    .map_err(internal_status)?;
+
+    Ok(id)
+}
 
-    let code = params
-        .find(|(key, _)| key == "code")
-        .map(|(_, value)| value);
+/// Stores a new refresh token for `id` in `session` and hands it to the
+/// browser.
+async fn issue_refresh_token(
+    db: &dyn Store,
+    cookies: &Cookies,
+    id: &str,
+    session: Uuid,
+) -> Result<()> {
+    let (secret, token) = RefreshToken::issue(id, session);
+    db.insert_refresh_token(&token).await?;
 
-    let state = params
-        .find(|(key, _)| key == "state")
-        .map(|(_, value)| value);
+    cookies.add(
+        Cookie::parse(format!(
+            "{}={}; Secure; HttpOnly; SameSite=Lax; Path=/auth; Max-Age={}",
+            REFRESH_COOKIE, secret, REFRESH_MAX_AGE
+        ))
+        .unwrap(),
+    );
+    Ok(())
+}
 
-    println!("code: {:?}, state: {:?}", code, state);
+/// Marks the refresh token `secret` used and returns it. A token shown a
+/// second time was copied, so its whole session is revoked.
+async fn redeem_refresh_token(db: &dyn Store, secret: &str) -> Result<RefreshToken, StatusCode> {
+    let mut token = db
+        .find_refresh_token(&refresh::hash(secret))
+        .await
+        .map_err(internal_status)?
+        .ok_or(StatusCode::UNAUTHORIZED)?;
 
-    if code.is_none() || state.is_none() {
-        return Err(StatusCode::FOUND);
+    if token.used {
+        println!(
+            "refresh token of {} reused, revoking session {}",
+            token.id, token.session
+        );
+        match db
+            .find_session(&token.session)
+            .await
+            .map_err(internal_status)?
+        {
+            Some(session) => revoke_session(db, &session).await,
+            None => db.delete_refresh_tokens_by_session(&token.session).await,
+        }
+        .map_err(internal_status)?;
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    if token.is_expired() {
+        return Err(StatusCode::UNAUTHORIZED);
     }
 
-    let code = AuthorizationCode::new(code.unwrap());
+    token.used = true;
+    db.insert_refresh_token(&token)
+        .await
+        .map_err(internal_status)?;
+    Ok(token)
+}
 
-    println!("Github returned the following code:\n{}\n", code.secret());
+/// Trades the refresh token cookie for a new access token and a new
+/// refresh token in the same session.
+async fn refresh_session(
+    shared: &AppState,
+    cookies: &Cookies,
+    ip: Option<&str>,
+) -> Result<(User, Claims, String), StatusCode> {
+    let secret = cookies
+        .get(REFRESH_COOKIE)
+        .map(|cookie| cookie.value().to_owned())
+        .ok_or(StatusCode::UNAUTHORIZED)?;
 
-    let token_res = shared
-        .client
-        .exchange_code(code)
-        .request_async(async_http_client)
-        .await;
-
-    println!("Github returned the following token:\n{:?}\n", token_res);
-
-    if let Ok(token) = token_res {
-        let scopes = if let Some(scopes_vec) = token.scopes() {
-            scopes_vec
-                .iter()
-                .flat_map(|comma_separated| comma_separated.split(','))
-                .collect::<Vec<_>>()
-        } else {
-            vec![]
-        };
-        println!("Github returned the following scopes:\n{:?}\n", scopes);
+    let token = match redeem_refresh_token(shared.db.as_ref(), &secret).await {
+        Ok(token) => token,
+        Err(status) => {
+            cookies.remove(Cookie::build(REFRESH_COOKIE, "").path("/auth").finish());
+            return Err(status);
+        }
+    };
+    // Refresh tokens from before sessions carry on in a new one.
+    let mut session = shared
+        .db
+        .find_session(&token.session)
+        .await
+        .map_err(internal_status)?
+        .unwrap_or_else(|| Session {
+            id: token.session,
+            ..Session::new(&token.id, "unknown", ip)
+        });
+    session.seen(ip);
+    shared
+        .db
+        .insert_session(&session)
+        .await
+        .map_err(internal_status)?;
+    issue_refresh_token(shared.db.as_ref(), cookies, &token.id, session.id)
+        .await
+        .map_err(internal_status)?;
 
-        let octocrab = Octocrab::builder()
-            .personal_token(token.access_token().secret().clone())
-            .build()
-            .unwrap();
+    // The active channel is the one stored with the user, which switching
+    // channels keeps current.
+    let user = resolve_user(shared.db.as_ref(), &token.id, None)
+        .await
+        .map_err(internal_status)?;
+    // Provider tokens aren't kept past the sign-in.
+    let claims = Claims::new(&user.id, &user.channel, "", Some(session.id));
+    let jwt = claims.encode(&shared.keys).map_err(internal_status)?;
 
-        let cur_user = octocrab.current().user().await.unwrap();
+    Ok((user, claims, jwt))
+}
+
+/// `POST /auth/refresh`: renews the session before the access token runs
+/// out, so users aren't sent back to their provider.
+pub async fn refresh(
+    cookies: Cookies,
+    State(shared): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let (user, _, jwt) = refresh_session(&shared, &cookies, client_ip(&headers).as_deref()).await?;
+    set_session_cookies(&cookies, &user, &jwt);
+
+    Ok(api::json_response(
+        StatusCode::OK,
+        serde_json::json!({
+            "user": user.id,
+            "channel": user.channel,
+            "access_token": jwt,
+            "expires_in": JWT_MAX_AGES,
+        }),
+    ))
+}
 
-        println!("user id: {:?}", cur_user.login);
+/// `POST /auth/logout`: signs the session out, found from the access token
+/// or, once that ran out, the refresh token, and clears the cookies.
+pub async fn logout(
+    cookies: Cookies,
+    State(shared): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let db = shared.db.as_ref();
+
+    let from_jwt = jwt_from_header(&headers)
+        .or_else(|_| jwt_from_cookie(&cookies))
+        .and_then(|jwt| decode_claims(&shared.keys, &jwt))
+        .ok()
+        .and_then(|claims| claims.jti);
+    let from_refresh = match cookies.get(REFRESH_COOKIE) {
+        Some(cookie) => db
+            .find_refresh_token(&refresh::hash(cookie.value()))
+            .await
+            .map_err(internal_status)?
+            .map(|token| token.session),
+        None => None,
+    };
+
+    if let Some(id) = from_jwt.or(from_refresh) {
+        if let Some(session) = db.find_session(&id).await.map_err(internal_status)? {
+            println!("{} signed out of session {}", session.user, session.id);
+            revoke_session(db, &session)
+                .await
+                .map_err(internal_status)?;
+        }
+    }
 
-        return Ok((cur_user.login, token.access_token().secret().to_owned()));
+    for name in ["user", "channel", "auth_token"] {
+        cookies.remove(Cookie::build(name, "").path("/").finish());
     }
+    cookies.remove(Cookie::build(REFRESH_COOKIE, "").path("/auth").finish());
 
-    Err(StatusCode::UNAUTHORIZED)
+    Ok(api::response(StatusCode::NO_CONTENT, String::new()))
+}
+
+/// `GET /.well-known/jwks.json`: the public keys session tokens are signed
+/// with, for proxies and other services verifying them.
+pub async fn jwks(State(shared): State<Arc<AppState>>) -> Response {
+    let mut res = api::json_response(StatusCode::OK, shared.keys.jwks());
+    res.headers_mut().insert(
+        header::CACHE_CONTROL,
+        HeaderValue::from_static("public, max-age=300"),
+    );
+    res
+}
+
+/// Sets the cookies the frontend reads the session from.
+pub fn set_session_cookies(cookies: &Cookies, user: &User, jwt: &str) {
+    let cookie_opts = format!(
+        "Secure; HttpOnly; SameSite=None; Path=/; Max-Age={}",
+        JWT_MAX_AGES
+    );
+
+    cookies.add(Cookie::parse(format!("user={}; {}", user.id, cookie_opts)).unwrap());
+    cookies.add(Cookie::parse(format!("channel={}; {}", user.channel, cookie_opts)).unwrap());
+    cookies.add(Cookie::parse(format!("auth_token={}; {}", jwt, cookie_opts)).unwrap());
 }
 
 async fn auth_next<B>(
     mut req: Request<B>,
     next: Next<B>,
     user: User,
+    claims: Claims,
     jwt: &str,
     cookies: &Cookies,
 ) -> Response {
     println!("auth_next: {:?}", user);
-    let id = user.id.to_owned();
-    let channel = user.channel.to_owned();
+
+    // Set before the handler runs, so one that switches channels can
+    // replace them.
+    set_session_cookies(cookies, &user, jwt);
 
     req.extensions_mut().insert(user);
+    req.extensions_mut().insert(claims);
 
     let mut res = next.run(req).await;
 
-    res.headers_mut()
-        .insert(header::AUTHORIZATION, HeaderValue::from_str(jwt).unwrap());
+    if !res.headers().contains_key(header::AUTHORIZATION) {
+        res.headers_mut()
+            .insert(header::AUTHORIZATION, HeaderValue::from_str(jwt).unwrap());
+    }
 
-    let cookie_opts = format!(
-        "Secure; HttpOnly; SameSite=None; Path=/; Max-Age={}",
-        JWT_MAX_AGES
-    );
+    res
+}
 
-    cookies.add(Cookie::parse(format!("user={}; {}", id, cookie_opts)).unwrap());
-    cookies.add(Cookie::parse(format!("channel={}; {}", channel, cookie_opts)).unwrap());
-    cookies.add(Cookie::parse(format!("auth_token={}; {}", jwt, cookie_opts)).unwrap());
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{
+        ChannelStore, IdentityStore, MemberStore, Memory, RefreshTokenStore, SessionStore,
+        UserStore,
+    };
 
-    res
+    #[test]
+    fn test_login_attempt_without_secret() {
+        let keys = KeyRing::without_secret();
+        let attempt = LoginAttempt {
+            provider: "github".to_owned(),
+            state: "state".to_owned(),
+            verifier: "verifier".to_owned(),
+            nonce: "nonce".to_owned(),
+            link: None,
+            exp: (chrono::Utc::now().timestamp() + LOGIN_MAX_AGE) as usize,
+        };
+
+        let jwt = attempt.encode(&keys).unwrap();
+        let decoded = LoginAttempt::decode(&keys, &jwt).unwrap();
+        assert_eq!(decoded.provider, "github");
+        assert_eq!(decoded.state, "state");
+
+        // A session token isn't a login attempt.
+        let session = Claims::new("21kyu", "home", "token", None)
+            .encode(&keys)
+            .unwrap();
+        assert!(LoginAttempt::decode(&keys, &session).is_err());
+        let any = keys.decode::<serde_json::Value>(SESSION_AUDIENCE, &jwt);
+        assert!(any.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_user_channels() {
+        let db = Memory::default();
+
+        let user = resolve_user(&db, "21kyu", None).await.unwrap();
+        assert_eq!(user.channel, "21kyu");
+        assert!(db.find_member("21kyu", "21kyu").await.unwrap().is_some());
+
+        // A token for a channel the user isn't in doesn't get them in.
+        let user = resolve_user(&db, "21kyu", Some("work")).await.unwrap();
+        assert_eq!(user.channel, "21kyu");
+
+        db.insert_channel(&Channel {
+            channel: "work".to_owned(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        db.insert_member(&Member::new("21kyu", "work", Role::Editor))
+            .await
+            .unwrap();
+        let user = resolve_user(&db, "21kyu", Some("work")).await.unwrap();
+        assert_eq!(user.channel, "work");
+
+        let mut personal = db.find_channel("21kyu").await.unwrap().unwrap();
+        personal.archived_at = Some(scylla::frame::value::Timestamp(chrono::Duration::zero()));
+        db.insert_channel(&personal).await.unwrap();
+        let user = resolve_user(&db, "21kyu", Some("21kyu")).await.unwrap();
+        assert_eq!(user.channel, "work");
+    }
+
+    #[tokio::test]
+    async fn test_resolve_user_keeps_legacy_channel() {
+        let db = Memory::default();
+        db.insert_user(&User {
+            id: "csj200045".to_owned(),
+            channel: "home".to_owned(),
+            tz: None,
+        })
+        .await
+        .unwrap();
+
+        let user = resolve_user(&db, "csj200045", None).await.unwrap();
+
+        assert_eq!(user.channel, "home");
+        let member = db.find_member("csj200045", "home").await.unwrap().unwrap();
+        assert_eq!(member.role.as_deref(), Some("editor"));
+
+        db.insert_user(&User {
+            id: "21kyu".to_owned(),
+            channel: "21kyu".to_owned(),
+            tz: None,
+        })
+        .await
+        .unwrap();
+        resolve_user(&db, "21kyu", None).await.unwrap();
+        let member = db.find_member("21kyu", "21kyu").await.unwrap().unwrap();
+        assert_eq!(member.role.as_deref(), Some("owner"));
+    }
+
+    fn profile(subject: &str, login: &str) -> Profile {
+        Profile {
+            subject: subject.to_owned(),
+            login: login.to_owned(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sign_in_links_identities() {
+        let db = Memory::default();
+        db.insert_user(&User {
+            id: "21kyu".to_owned(),
+            channel: "21kyu".to_owned(),
+            tz: None,
+        })
+        .await
+        .unwrap();
+
+        // Users from before identities keep their GitHub login as id.
+        let github = profile("1234", "21kyu");
+        assert_eq!(
+            sign_in(&db, "github", &github, None).await.unwrap(),
+            "21kyu"
+        );
+        assert_eq!(
+            sign_in(&db, "github", &github, None).await.unwrap(),
+            "21kyu"
+        );
+
+        // Someone else with the same GitLab login gets an id of their own.
+        let gitlab = profile("99", "21kyu");
+        let id = sign_in(&db, "gitlab", &gitlab, None).await.unwrap();
+        assert!(id.starts_with("21kyu-"));
+        assert_eq!(sign_in(&db, "gitlab", &gitlab, None).await.unwrap(), id);
+
+        // Linked accounts can't be linked to another user.
+        assert_eq!(
+            sign_in(&db, "gitlab", &gitlab, Some("21kyu")).await,
+            Err(StatusCode::CONFLICT)
+        );
+        let google = profile("abc", "kyu@example.com");
+        assert_eq!(
+            sign_in(&db, "google", &google, Some("21kyu"))
+                .await
+                .unwrap(),
+            "21kyu"
+        );
+        assert_eq!(db.find_identities_by_user("21kyu").await.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_user_id_hint() {
+        assert_eq!(user_id_hint("21kyu"), "21kyu");
+        assert_eq!(user_id_hint("jane.doe@example.com"), "jane-doe");
+        assert_eq!(user_id_hint("@@"), "user");
+    }
+
+    #[tokio::test]
+    async fn test_redeem_refresh_token() {
+        let db = Memory::default();
+        let session = Uuid::new_v4();
+        let (first, token) = RefreshToken::issue("21kyu", session);
+        db.insert_refresh_token(&token).await.unwrap();
+        let (second, token) = RefreshToken::issue("21kyu", session);
+        db.insert_refresh_token(&token).await.unwrap();
+
+        let redeemed = redeem_refresh_token(&db, &first).await.unwrap();
+        assert_eq!((redeemed.id.as_str(), redeemed.session), ("21kyu", session));
+        assert_eq!(
+            redeem_refresh_token(&db, "unknown").await,
+            Err(StatusCode::UNAUTHORIZED)
+        );
+
+        // Reusing a token revokes the tokens rotated from it too.
+        assert_eq!(
+            redeem_refresh_token(&db, &first).await,
+            Err(StatusCode::UNAUTHORIZED)
+        );
+        assert_eq!(
+            redeem_refresh_token(&db, &second).await,
+            Err(StatusCode::UNAUTHORIZED)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_check_session() {
+        let db = Memory::default();
+        let session = Session::new("21kyu", "curl/8.0", None);
+        db.insert_session(&session).await.unwrap();
+
+        let claims = Claims::new("21kyu", "21kyu", "", Some(session.id));
+        assert!(check_session(&db, &claims, Some("10.0.0.1")).await.is_ok());
+        let found = db.find_session(&session.id).await.unwrap().unwrap();
+        assert_eq!(found.ip.as_deref(), Some("10.0.0.1"));
+
+        // Another user's token can't borrow the session.
+        let other = Claims::new("csj200045", "home", "", Some(session.id));
+        assert_eq!(
+            check_session(&db, &other, None).await,
+            Err(StatusCode::UNAUTHORIZED)
+        );
+
+        revoke_session(&db, &session).await.unwrap();
+        assert_eq!(
+            check_session(&db, &claims, None).await,
+            Err(StatusCode::UNAUTHORIZED)
+        );
+        let legacy = Claims::new("21kyu", "21kyu", "", None);
+        assert!(check_session(&db, &legacy, None).await.is_ok());
+    }
 }
@@ -0,0 +1,464 @@
+use std::collections::HashSet;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, Utc};
+use clap::ValueEnum;
+use scylla::frame::value::Timestamp;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::db::{SchedFilter, Store};
+use crate::recur::RRule;
+use crate::sched::Sched;
+use crate::tz;
+
+const PAGE_SIZE: i32 = 500;
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Csv,
+    Jsonl,
+}
+
+impl Format {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Format::Csv => "text/csv; charset=utf-8",
+            Format::Jsonl => "application/x-ndjson",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Jsonl => "jsonl",
+        }
+    }
+}
+
+/// A `ks.s` row as it is written to and read from files. Dates and times are
+/// the stored ones, UTC for timed schedules. Only `id`, `sched` and `date_at`
+/// are needed to import, `channel` defaults to the channel imported into and
+/// `sid` and `create_at` are generated when missing.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct Row {
+    #[serde(default)]
+    pub channel: Option<String>,
+    pub id: String,
+    pub sched: String,
+    pub date_at: String,
+    #[serde(default)]
+    pub start_time: Option<String>,
+    #[serde(default)]
+    pub end_time: Option<String>,
+    #[serde(default)]
+    pub all_day: Option<bool>,
+    /// Comma separated `YYYY-MM-DD` dates.
+    #[serde(default)]
+    pub exdates: Option<String>,
+    #[serde(default)]
+    pub rrule: Option<String>,
+    #[serde(default)]
+    pub sid: Option<Uuid>,
+    /// RFC 3339 timestamp.
+    #[serde(default)]
+    pub create_at: Option<String>,
+    #[serde(default)]
+    pub uid: Option<String>,
+    /// IANA time zone a series repeats in, like `Asia/Seoul`.
+    #[serde(default)]
+    pub tz: Option<String>,
+}
+
+impl From<&Sched> for Row {
+    fn from(sched: &Sched) -> Self {
+        let create_at =
+            DateTime::<Utc>::from_timestamp_millis(sched.create_at.0.num_milliseconds())
+                .unwrap_or_default();
+
+        Self {
+            channel: Some(sched.channel.to_owned()),
+            id: sched.id.to_owned(),
+            sched: sched.sched.to_owned(),
+            date_at: sched.date_at.format(DATE_FORMAT).to_string(),
+            start_time: sched.start_time.map(|t| t.to_string()),
+            end_time: sched.end_time.map(|t| t.to_string()),
+            all_day: Some(sched.all_day),
+            exdates: Some(
+                sched
+                    .exdates
+                    .iter()
+                    .map(|d| d.format(DATE_FORMAT).to_string())
+                    .collect::<Vec<_>>()
+                    .join(","),
+            )
+            .filter(|s| !s.is_empty()),
+            rrule: sched.rrule.to_owned(),
+            sid: Some(sched.sid),
+            create_at: Some(create_at.to_rfc3339_opts(chrono::SecondsFormat::Millis, true)),
+            uid: sched.uid.to_owned(),
+            tz: sched.tz.to_owned(),
+        }
+    }
+}
+
+/// Strict `YYYY-MM-DD`, chrono alone would take `2024-9-2` as well.
+fn parse_date(field: &str, value: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(value.trim(), DATE_FORMAT)
+        .ok()
+        .filter(|date| date.format(DATE_FORMAT).to_string() == value.trim())
+        .ok_or_else(|| anyhow!("{} must be YYYY-MM-DD, got {:?}", field, value))
+}
+
+fn parse_time(field: &str, value: &Option<String>) -> Result<Option<NaiveTime>> {
+    let value = match value.as_deref().map(str::trim) {
+        Some(value) if !value.is_empty() => value,
+        _ => return Ok(None),
+    };
+    NaiveTime::parse_from_str(value, "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(value, "%H:%M"))
+        .map(Some)
+        .map_err(|_| anyhow!("{} must be HH:MM or HH:MM:SS, got {:?}", field, value))
+}
+
+/// Owners are GitHub logins.
+fn check_owner(id: &str) -> Result<()> {
+    if id.is_empty() || id.len() > 39 {
+        return Err(anyhow!("owner must be 1 to 39 characters"));
+    }
+    if !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(anyhow!("invalid owner {:?}", id));
+    }
+    Ok(())
+}
+
+impl Row {
+    /// Validates the row for import into `channel`, without looking at the
+    /// store.
+    pub fn to_sched(&self, channel: &str) -> Result<Sched> {
+        match self.channel.as_deref().map(str::trim) {
+            None | Some("") => {}
+            Some(c) if c == channel => {}
+            Some(c) => return Err(anyhow!("row is for channel {}, not {}", c, channel)),
+        }
+
+        let id = self.id.trim();
+        check_owner(id)?;
+
+        if self.sched.trim().is_empty() {
+            return Err(anyhow!("empty schedule"));
+        }
+
+        let mut sched = Sched::new(
+            channel,
+            id,
+            &self.sched,
+            parse_date("date_at", &self.date_at)?,
+        );
+
+        sched.start_time = parse_time("start_time", &self.start_time)?;
+        sched.end_time = parse_time("end_time", &self.end_time)?;
+        sched.all_day = self.all_day.unwrap_or(sched.start_time.is_none());
+        match (sched.all_day, sched.start_time, sched.end_time) {
+            (true, None, None) | (false, Some(_), _) => {}
+            (true, _, _) => return Err(anyhow!("all-day schedules have no time")),
+            (false, None, _) => return Err(anyhow!("missing start time")),
+        }
+
+        if let Some(rrule) = self.rrule.as_deref().filter(|r| !r.trim().is_empty()) {
+            sched.rrule = Some(rrule.parse::<RRule>()?.to_string());
+        }
+        if let Some(exdates) = &self.exdates {
+            sched.exdates = exdates
+                .split(',')
+                .filter(|d| !d.trim().is_empty())
+                .map(|d| parse_date("exdates", d))
+                .collect::<Result<_>>()?;
+        }
+
+        if let Some(sid) = self.sid {
+            sched.sid = sid;
+        }
+        if let Some(create_at) = self.create_at.as_deref().filter(|c| !c.trim().is_empty()) {
+            let create_at = DateTime::parse_from_rfc3339(create_at.trim())
+                .map_err(|_| anyhow!("create_at must be RFC 3339, got {:?}", create_at))?;
+            sched.create_at = Timestamp(Duration::milliseconds(create_at.timestamp_millis()));
+        }
+        sched.uid = self.uid.to_owned().filter(|uid| !uid.is_empty());
+        // Single schedules have no zone of their own, see `Sched::tz`.
+        if let Some(tz) = self.tz.as_deref().filter(|tz| !tz.trim().is_empty()) {
+            let tz = tz::parse_tz(tz.trim())?;
+            sched.tz = sched.rrule.as_ref().map(|_| tz.name().to_owned());
+        }
+
+        Ok(sched)
+    }
+}
+
+/// Every stored row of a channel, past ones included.
+pub async fn dump(db: &dyn Store, channel: &str) -> Result<Vec<Sched>> {
+    let mut filter = SchedFilter {
+        from: Some(NaiveDate::MIN),
+        limit: Some(PAGE_SIZE),
+        ..Default::default()
+    };
+
+    let mut scheds = vec![];
+    loop {
+        let page = db.find_sched_by_channel(channel, &filter).await?;
+        scheds.extend(page.scheds);
+        match page.next {
+            Some(next) => filter.cursor = Some(next),
+            None => break,
+        }
+    }
+    Ok(scheds)
+}
+
+pub fn write(scheds: &[Sched], format: Format) -> Result<String> {
+    match format {
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(vec![]);
+            for sched in scheds {
+                writer.serialize(Row::from(sched))?;
+            }
+            Ok(String::from_utf8(writer.into_inner()?)?)
+        }
+        Format::Jsonl => {
+            let mut out = String::new();
+            for sched in scheds {
+                out.push_str(&serde_json::to_string(&Row::from(sched))?);
+                out.push('\n');
+            }
+            Ok(out)
+        }
+    }
+}
+
+/// Reads the rows of a file with the line each starts on. A row that can't
+/// be read is an error of its own and doesn't stop the others.
+pub fn read(text: &str, format: Format) -> Result<Vec<(u64, Result<Row>)>> {
+    match format {
+        Format::Csv => {
+            let mut reader = csv::ReaderBuilder::new()
+                .trim(csv::Trim::All)
+                .from_reader(text.as_bytes());
+            reader.headers()?;
+            Ok(reader
+                .deserialize::<Row>()
+                .enumerate()
+                .map(|(i, row)| match row {
+                    Ok(row) => (i as u64 + 2, Ok(row)),
+                    Err(err) => (
+                        err.position().map(|p| p.line()).unwrap_or(i as u64 + 2),
+                        Err(anyhow!("{}", err)),
+                    ),
+                })
+                .collect())
+        }
+        Format::Jsonl => Ok(text
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| (i as u64 + 1, serde_json::from_str(line).map_err(Into::into)))
+            .collect()),
+    }
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct RowReport {
+    pub line: u64,
+    pub sid: Option<Uuid>,
+    pub sched: Option<String>,
+    /// `valid` when the row would be imported, `imported`, `duplicate` when
+    /// its `sid` is taken, or `invalid`.
+    pub status: &'static str,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub struct Report {
+    pub channel: String,
+    pub dry_run: bool,
+    pub imported: usize,
+    pub duplicates: usize,
+    pub failed: usize,
+    pub rows: Vec<RowReport>,
+}
+
+/// Validates every row, whose owner has to be a member of the channel, and
+/// unless it is a dry run or a row is invalid, writes the new ones. Nothing
+/// is written when any row fails, so a file can be fixed and imported again
+/// as a whole.
+pub async fn import(
+    db: &dyn Store,
+    channel: &str,
+    rows: Vec<(u64, Result<Row>)>,
+    dry_run: bool,
+) -> Result<Report> {
+    let mut report = Report {
+        channel: channel.to_owned(),
+        dry_run,
+        ..Default::default()
+    };
+    let mut valid = vec![];
+    let mut seen = HashSet::new();
+
+    for (line, row) in rows {
+        let sched = match row.and_then(|row| row.to_sched(channel)) {
+            Ok(sched) if db.find_member(&sched.id, channel).await?.is_none() => Err(anyhow!(
+                "owner {} is not a member of channel {}",
+                sched.id,
+                channel
+            )),
+            sched => sched,
+        };
+        let sched = match sched {
+            Ok(sched) => sched,
+            Err(err) => {
+                report.failed += 1;
+                report.rows.push(RowReport {
+                    line,
+                    status: "invalid",
+                    error: Some(err.to_string()),
+                    ..Default::default()
+                });
+                continue;
+            }
+        };
+
+        let duplicate =
+            !seen.insert(sched.sid) || db.find_sched_by_id(channel, &sched.sid).await?.is_some();
+
+        report.rows.push(RowReport {
+            line,
+            sid: Some(sched.sid),
+            sched: Some(sched.sched.to_owned()),
+            status: if duplicate { "duplicate" } else { "valid" },
+            error: None,
+        });
+        if duplicate {
+            report.duplicates += 1;
+        } else {
+            valid.push((report.rows.len() - 1, sched));
+        }
+    }
+
+    if dry_run || report.failed > 0 {
+        return Ok(report);
+    }
+
+    for (i, sched) in valid {
+        db.insert_sched(&sched).await?;
+        report.rows[i].status = "imported";
+        report.imported += 1;
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{MemberStore, Memory};
+    use crate::member::{Member, Role};
+
+    const CSV: &str = "channel,id,sched,date_at,start_time,end_time,all_day,exdates,rrule,tz\n\
+                       home,21kyu,dentist,2024-03-04,01:30,02:00,,,,\n\
+                       ,csj200045,midterm,2024-04-15,,,true,2024-04-22,FREQ=WEEKLY;COUNT=2,\n\
+                       home,21kyu,standup,2024-03-05,00:00,00:15,,,FREQ=DAILY;COUNT=5,Asia/Seoul\n";
+
+    async fn store() -> Memory {
+        let db = Memory::default();
+        for id in ["21kyu", "csj200045"] {
+            db.insert_member(&Member::new(id, "home", Role::Editor))
+                .await
+                .unwrap();
+        }
+        db
+    }
+
+    #[test]
+    fn test_row_validation() {
+        let row = |date_at: &str, id: &str, channel: &str| Row {
+            channel: Some(channel.to_owned()),
+            id: id.to_owned(),
+            sched: "x".to_owned(),
+            date_at: date_at.to_owned(),
+            ..Default::default()
+        };
+
+        assert!(row("2024-03-04", "21kyu", "home").to_sched("home").is_ok());
+        assert!(row("2024/03/04", "21kyu", "home").to_sched("home").is_err());
+        assert!(row("2024-3-4", "21kyu", "home").to_sched("home").is_err());
+        assert!(row("2024-02-30", "21kyu", "home").to_sched("home").is_err());
+        assert!(row("2024-03-04", "", "home").to_sched("home").is_err());
+        assert!(row("2024-03-04", "a b", "home").to_sched("home").is_err());
+        assert!(row("2024-03-04", "21kyu", "work").to_sched("home").is_err());
+
+        let mut series = row("2024-03-04", "21kyu", "home");
+        series.rrule = Some("FREQ=DAILY".to_owned());
+        series.tz = Some("Asia/Seoul".to_owned());
+        assert_eq!(
+            series.to_sched("home").unwrap().tz.as_deref(),
+            Some("Asia/Seoul")
+        );
+        series.tz = Some("Mars/Olympus".to_owned());
+        assert!(series.to_sched("home").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_import_dry_run_then_export() {
+        let db = store().await;
+
+        let report = import(&db, "home", read(CSV, Format::Csv).unwrap(), true)
+            .await
+            .unwrap();
+        assert_eq!((report.failed, report.imported), (0, 0));
+        assert!(dump(&db, "home").await.unwrap().is_empty());
+
+        let report = import(&db, "home", read(CSV, Format::Csv).unwrap(), false)
+            .await
+            .unwrap();
+        assert_eq!(report.imported, 3);
+
+        let scheds = dump(&db, "home").await.unwrap();
+        assert_eq!(scheds.len(), 3);
+        let named = |name: &str| scheds.iter().position(|s| s.sched == name).unwrap();
+        let (midterm, standup) = (&scheds[named("midterm")], &scheds[named("standup")]);
+        assert_eq!(midterm.rrule.as_deref(), Some("FREQ=WEEKLY;COUNT=2"));
+        assert_eq!(midterm.tz, None);
+        assert_eq!(standup.tz.as_deref(), Some("Asia/Seoul"));
+
+        let jsonl = write(&scheds, Format::Jsonl).unwrap();
+        let rows = read(&jsonl, Format::Jsonl).unwrap();
+        let report = import(&db, "home", rows, false).await.unwrap();
+        assert_eq!((report.imported, report.duplicates), (0, 3));
+
+        let csv = write(&scheds, Format::Csv).unwrap();
+        let reread = read(&csv, Format::Csv).unwrap();
+        for (i, (_, row)) in reread.iter().enumerate() {
+            assert_eq!(row.as_ref().unwrap().to_sched("home").unwrap(), scheds[i]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_import_writes_nothing_on_invalid_row() {
+        let db = store().await;
+        let csv = format!(
+            "{}home,21kyu,typo,2024-13-01,,,,,,\nhome,stranger,hi,2024-03-04,,,,,,\n",
+            CSV
+        );
+
+        let rows = read(&csv, Format::Csv).unwrap();
+        let report = import(&db, "home", rows, false).await.unwrap();
+
+        assert_eq!((report.failed, report.imported), (2, 0));
+        assert_eq!(report.rows[3].line, 5);
+        assert!(report.rows[4].error.as_ref().unwrap().contains("member"));
+        assert!(dump(&db, "home").await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,801 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use axum::body::{boxed, Body};
+use axum::extract::{Path, State};
+use axum::http::{header, HeaderMap, Method, StatusCode};
+use axum::response::Response;
+use axum::Extension;
+use chrono::{NaiveDate, NaiveDateTime, TimeZone};
+use chrono_tz::Tz;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use quick_xml::escape::escape;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::name::ResolveResult;
+use quick_xml::NsReader;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::api::{self, internal_error, ApiError};
+use crate::member::Role;
+use crate::perm::Access;
+use crate::sched::Sched;
+use crate::user::User;
+use crate::{ical, recur, tz, AppState};
+
+const DAV: &str = "DAV:";
+const CALDAV: &str = "urn:ietf:params:xml:ns:caldav";
+const CALSERVER: &str = "http://calendarserver.org/ns/";
+
+/// The principal of whoever is logged in, which is also the entry point
+/// clients are configured with.
+pub const ROOT: &str = "/dav/";
+/// Holds one calendar collection per channel.
+pub const HOME: &str = "/dav/calendars/";
+
+const ALLOW: &str = "OPTIONS, GET, PUT, DELETE, PROPFIND, REPORT";
+
+/// Characters left as they are in hrefs, besides letters and digits.
+const SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~')
+    .remove(b'@');
+
+/// What a PROPFIND or REPORT body asks for.
+#[derive(Debug, Default, PartialEq)]
+struct DavRequest {
+    /// Local name of the root element, like `propfind` or `calendar-multiget`.
+    kind: String,
+    /// Requested properties as namespace and local name, empty for all.
+    props: Vec<(String, String)>,
+    hrefs: Vec<String>,
+    /// `time-range` of a calendar-query, in UTC.
+    start: Option<NaiveDateTime>,
+    end: Option<NaiveDateTime>,
+}
+
+fn parse_utc(value: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value.trim_end_matches('Z'), "%Y%m%dT%H%M%S").ok()
+}
+
+fn read_element(req: &mut DavRequest, stack: &[String], ns: ResolveResult, e: &BytesStart) {
+    let name = String::from_utf8_lossy(e.local_name().as_ref()).into_owned();
+    let ns = match ns {
+        ResolveResult::Bound(ns) => String::from_utf8_lossy(ns.as_ref()).into_owned(),
+        _ => String::new(),
+    };
+
+    if stack.is_empty() {
+        req.kind = name;
+        return;
+    }
+    // Only the `prop` right under the root lists properties, the one in a
+    // calendar-query filter doesn't.
+    if stack.len() == 2 && stack[1] == "prop" {
+        req.props.push((ns, name));
+        return;
+    }
+    if name == "time-range" {
+        for attr in e.attributes().flatten() {
+            let value = attr.unescape_value().unwrap_or_default();
+            match attr.key.local_name().as_ref() {
+                b"start" => req.start = parse_utc(&value),
+                b"end" => req.end = parse_utc(&value),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// An empty body asks for all properties, like `<allprop/>` does.
+fn parse_request(body: &str) -> Result<DavRequest> {
+    let mut req = DavRequest {
+        kind: "propfind".to_owned(),
+        ..Default::default()
+    };
+    if body.trim().is_empty() {
+        return Ok(req);
+    }
+
+    let mut reader = NsReader::from_str(body);
+    reader.trim_text(true);
+    let mut stack: Vec<String> = vec![];
+
+    loop {
+        match reader.read_resolved_event()? {
+            (ns, Event::Start(e)) => {
+                read_element(&mut req, &stack, ns, &e);
+                stack.push(String::from_utf8_lossy(e.local_name().as_ref()).into_owned());
+            }
+            (ns, Event::Empty(e)) => read_element(&mut req, &stack, ns, &e),
+            (_, Event::End(_)) => {
+                stack.pop();
+            }
+            (_, Event::Text(text)) if stack.last().is_some_and(|name| name == "href") => {
+                req.hrefs.push(text.unescape()?.into_owned());
+            }
+            (_, Event::Eof) => break,
+            _ => {}
+        }
+    }
+
+    Ok(req)
+}
+
+struct Prop {
+    ns: &'static str,
+    name: &'static str,
+    /// Inner XML, escaped already.
+    value: String,
+}
+
+fn prop(ns: &'static str, name: &'static str, value: impl Into<String>) -> Prop {
+    Prop {
+        ns,
+        name,
+        value: value.into(),
+    }
+}
+
+fn prefix(ns: &str) -> &'static str {
+    match ns {
+        CALDAV => "c",
+        CALSERVER => "cs",
+        _ => "d",
+    }
+}
+
+fn href(path: &str) -> String {
+    format!("<d:href>{}</d:href>", escape(path))
+}
+
+/// One `response` of a multistatus. Properties the request asked for but
+/// the resource doesn't have are listed as not found. Without a list, all
+/// properties but the calendar data are returned.
+fn response_xml(path: &str, props: &[Prop], req: &DavRequest) -> String {
+    let mut found = String::new();
+    let mut missing = String::new();
+
+    if req.props.is_empty() {
+        for p in props.iter().filter(|p| p.name != "calendar-data") {
+            found.push_str(&prop_xml(p));
+        }
+    } else {
+        for (ns, name) in &req.props {
+            match props.iter().find(|p| p.ns == ns && p.name == name) {
+                Some(p) => found.push_str(&prop_xml(p)),
+                None => {
+                    missing.push_str(&format!("<x:{} xmlns:x=\"{}\"/>", escape(name), escape(ns)))
+                }
+            }
+        }
+    }
+
+    let mut xml = format!("<d:response>{}", href(path));
+    if !found.is_empty() {
+        xml.push_str(&format!(
+            "<d:propstat><d:prop>{}</d:prop><d:status>HTTP/1.1 200 OK</d:status></d:propstat>",
+            found
+        ));
+    }
+    if !missing.is_empty() {
+        xml.push_str(&format!(
+            "<d:propstat><d:prop>{}</d:prop><d:status>HTTP/1.1 404 Not Found</d:status></d:propstat>",
+            missing
+        ));
+    }
+    xml.push_str("</d:response>");
+    xml
+}
+
+fn prop_xml(p: &Prop) -> String {
+    let prefix = prefix(p.ns);
+    if p.value.is_empty() {
+        format!("<{}:{}/>", prefix, p.name)
+    } else {
+        format!("<{0}:{1}>{2}</{0}:{1}>", prefix, p.name, p.value)
+    }
+}
+
+fn not_found_xml(path: &str) -> String {
+    format!(
+        "<d:response>{}<d:status>HTTP/1.1 404 Not Found</d:status></d:response>",
+        href(path)
+    )
+}
+
+fn multistatus(responses: &[String]) -> Response {
+    let body = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<d:multistatus xmlns:d=\"{}\" xmlns:c=\"{}\" xmlns:cs=\"{}\">{}</d:multistatus>",
+        DAV,
+        CALDAV,
+        CALSERVER,
+        responses.concat()
+    );
+
+    Response::builder()
+        .status(StatusCode::MULTI_STATUS)
+        .header(header::CONTENT_TYPE, "application/xml; charset=utf-8")
+        .body(boxed(Body::from(body)))
+        .unwrap()
+}
+
+fn options() -> Response {
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("DAV", "1, 3, calendar-access")
+        .header(header::ALLOW, ALLOW)
+        .body(boxed(Body::empty()))
+        .unwrap()
+}
+
+fn not_allowed(method: &Method) -> ApiError {
+    (
+        StatusCode::METHOD_NOT_ALLOWED,
+        format!("{} is not supported here", method),
+    )
+}
+
+fn bad_request(err: anyhow::Error) -> ApiError {
+    (StatusCode::BAD_REQUEST, err.to_string())
+}
+
+/// PROPFIND defaults to an infinite depth, which is served like depth 1.
+fn depth(headers: &HeaderMap) -> u32 {
+    match headers.get("Depth").and_then(|d| d.to_str().ok()) {
+        Some("0") => 0,
+        _ => 1,
+    }
+}
+
+fn etag(data: &str) -> String {
+    let hash = Sha256::digest(data.as_bytes());
+    let hex = hash[..16]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    format!("\"{}\"", hex)
+}
+
+/// The channels shown as calendars, all the user's but archived ones.
+async fn channels(state: &AppState, user: &User) -> Result<Vec<Access>, ApiError> {
+    let members = state
+        .db
+        .find_members_by_user(&user.id)
+        .await
+        .map_err(internal_error)?;
+
+    let mut channels = vec![];
+    for member in members {
+        let access = Access::load(state, user, &member.channel).await?;
+        if !access.channel.is_archived() {
+            channels.push(access);
+        }
+    }
+    Ok(channels)
+}
+
+fn collection_path(channel: &str) -> String {
+    format!("{}{}/", HOME, utf8_percent_encode(channel, SEGMENT))
+}
+
+/// Resources are named after the UID, which is what clients do for the
+/// events they create. The name maps back to the `sid` like an imported
+/// UID does, see `ical::import_sid`.
+fn resource_name(sched: &Sched) -> String {
+    let uid = ical::uid(sched);
+    let name = if ical::import_sid(&uid) == sched.sid {
+        uid
+    } else {
+        sched.sid.to_string()
+    };
+    format!("{}.ics", name)
+}
+
+fn resource_sid(name: &str) -> Uuid {
+    ical::import_sid(name.strip_suffix(".ics").unwrap_or(name))
+}
+
+/// A schedule as a calendar object resource.
+struct Resource {
+    path: String,
+    sched: Sched,
+    data: String,
+    etag: String,
+}
+
+fn resource_of(channel: &str, sched: Sched, tz: Tz) -> Resource {
+    let data = ical::calendar(channel, std::slice::from_ref(&sched), tz);
+    Resource {
+        path: format!(
+            "{}{}",
+            collection_path(channel),
+            utf8_percent_encode(&resource_name(&sched), SEGMENT)
+        ),
+        etag: etag(&data),
+        sched,
+        data,
+    }
+}
+
+fn resource_props(resource: &Resource) -> Vec<Prop> {
+    vec![
+        prop(DAV, "getetag", escape(&resource.etag)),
+        prop(
+            DAV,
+            "getcontenttype",
+            "text/calendar; charset=utf-8; component=VEVENT",
+        ),
+        prop(DAV, "resourcetype", ""),
+        prop(CALDAV, "calendar-data", escape(&resource.data)),
+    ]
+}
+
+async fn resources(state: &AppState, channel: &str, tz: Tz) -> Result<Vec<Resource>, ApiError> {
+    Ok(api::calendar_scheds(state, channel)
+        .await
+        .map_err(internal_error)?
+        .into_iter()
+        .map(|sched| resource_of(channel, sched, tz))
+        .collect())
+}
+
+fn principal_props(user: &User) -> Vec<Prop> {
+    vec![
+        prop(DAV, "current-user-principal", href(ROOT)),
+        prop(DAV, "principal-URL", href(ROOT)),
+        prop(CALDAV, "calendar-home-set", href(HOME)),
+        prop(DAV, "displayname", escape(&user.id)),
+    ]
+}
+
+/// Viewers and archived channels get a read-only calendar. Editors may
+/// write, though only their own events.
+fn calendar_props(access: &Access, resources: &[Resource]) -> Vec<Prop> {
+    let channel = &access.channel;
+    let ctag = etag(
+        &resources
+            .iter()
+            .map(|r| r.etag.as_str())
+            .collect::<String>(),
+    );
+
+    let privileges: &[&str] = if channel.is_archived() || access.role() < Role::Editor {
+        &["read"]
+    } else {
+        &["read", "write", "write-content", "bind", "unbind"]
+    };
+
+    vec![
+        prop(DAV, "resourcetype", "<d:collection/><c:calendar/>"),
+        prop(DAV, "displayname", escape(channel.name())),
+        prop(
+            CALDAV,
+            "supported-calendar-component-set",
+            "<c:comp name=\"VEVENT\"/>",
+        ),
+        prop(DAV, "current-user-principal", href(ROOT)),
+        prop(
+            DAV,
+            "current-user-privilege-set",
+            privileges
+                .iter()
+                .map(|p| format!("<d:privilege><d:{}/></d:privilege>", p))
+                .collect::<String>(),
+        ),
+        prop(DAV, "getetag", escape(&ctag)),
+        prop(CALSERVER, "getctag", escape(&ctag)),
+    ]
+}
+
+/// Redirects service discovery (RFC 6764) to the principal.
+pub async fn well_known() -> Response {
+    Response::builder()
+        .status(StatusCode::MOVED_PERMANENTLY)
+        .header(header::LOCATION, ROOT)
+        .body(boxed(Body::empty()))
+        .unwrap()
+}
+
+pub async fn root(
+    method: Method,
+    headers: HeaderMap,
+    Extension(user): Extension<User>,
+    body: String,
+) -> Result<Response, ApiError> {
+    match method.as_str() {
+        "OPTIONS" => Ok(options()),
+        "PROPFIND" => {
+            let req = parse_request(&body).map_err(bad_request)?;
+            let mut props = principal_props(&user);
+            props.push(prop(DAV, "resourcetype", "<d:collection/><d:principal/>"));
+
+            let mut responses = vec![response_xml(ROOT, &props, &req)];
+            if depth(&headers) > 0 {
+                responses.push(response_xml(HOME, &home_props(&user), &req));
+            }
+            Ok(multistatus(&responses))
+        }
+        _ => Err(not_allowed(&method)),
+    }
+}
+
+fn home_props(user: &User) -> Vec<Prop> {
+    let mut props = principal_props(user);
+    props.push(prop(DAV, "resourcetype", "<d:collection/>"));
+    props
+}
+
+pub async fn home(
+    method: Method,
+    headers: HeaderMap,
+    Extension(user): Extension<User>,
+    State(state): State<Arc<AppState>>,
+    body: String,
+) -> Result<Response, ApiError> {
+    match method.as_str() {
+        "OPTIONS" => Ok(options()),
+        "PROPFIND" => {
+            let req = parse_request(&body).map_err(bad_request)?;
+
+            let mut responses = vec![response_xml(HOME, &home_props(&user), &req)];
+            if depth(&headers) > 0 {
+                for access in channels(&state, &user).await? {
+                    let channel = &access.channel.channel;
+                    let tz = api::viewer_tz(&state, &user, channel).await;
+                    let resources = resources(&state, channel, tz).await?;
+                    responses.push(response_xml(
+                        &collection_path(channel),
+                        &calendar_props(&access, &resources),
+                        &req,
+                    ));
+                }
+            }
+            Ok(multistatus(&responses))
+        }
+        _ => Err(not_allowed(&method)),
+    }
+}
+
+/// Whether any occurrence of a stored schedule falls on the dates in `tz`
+/// of a calendar-query time range. Clients narrow it down to the exact times.
+fn in_range(sched: &Sched, tz: Tz, start: Option<NaiveDate>, end: Option<NaiveDate>) -> bool {
+    let from = start.unwrap_or(NaiveDate::MIN);
+    let to = end.unwrap_or(NaiveDate::MAX);
+
+    match sched.rrule {
+        Some(_) => recur::expand_stored(sched, tz, from, to)
+            .map(|occurrences| !occurrences.is_empty())
+            .unwrap_or(false),
+        None => {
+            let local = tz::to_local(sched, tz);
+            local.date_at >= from && local.date_at <= to
+        }
+    }
+}
+
+pub async fn collection(
+    Path(channel): Path<String>,
+    method: Method,
+    headers: HeaderMap,
+    Extension(user): Extension<User>,
+    State(state): State<Arc<AppState>>,
+    body: String,
+) -> Result<Response, ApiError> {
+    let access = Access::load(&state, &user, &channel).await?;
+
+    let tz = api::viewer_tz(&state, &user, &channel).await;
+    let path = collection_path(&channel);
+
+    match method.as_str() {
+        "OPTIONS" => Ok(options()),
+        "PROPFIND" => {
+            let req = parse_request(&body).map_err(bad_request)?;
+            let resources = resources(&state, &channel, tz).await?;
+
+            let mut responses = vec![response_xml(
+                &path,
+                &calendar_props(&access, &resources),
+                &req,
+            )];
+            if depth(&headers) > 0 {
+                for resource in &resources {
+                    responses.push(response_xml(
+                        &resource.path,
+                        &resource_props(resource),
+                        &req,
+                    ));
+                }
+            }
+            Ok(multistatus(&responses))
+        }
+        "REPORT" => {
+            let req = parse_request(&body).map_err(bad_request)?;
+
+            let responses = match req.kind.as_str() {
+                "calendar-query" => {
+                    let date = |dt: Option<NaiveDateTime>| {
+                        dt.map(|dt| tz.from_utc_datetime(&dt).date_naive())
+                    };
+                    let (start, end) = (date(req.start), date(req.end));
+
+                    resources(&state, &channel, tz)
+                        .await?
+                        .iter()
+                        .filter(|r| in_range(&r.sched, tz, start, end))
+                        .map(|r| response_xml(&r.path, &resource_props(r), &req))
+                        .collect::<Vec<_>>()
+                }
+                "calendar-multiget" => {
+                    let mut responses = vec![];
+                    for path in &req.hrefs {
+                        let name = path.rsplit('/').next().unwrap_or_default();
+                        let name = percent_decode_str(name).decode_utf8_lossy();
+                        let found = state
+                            .db
+                            .find_sched_by_id(&channel, &resource_sid(&name))
+                            .await
+                            .map_err(internal_error)?;
+
+                        responses.push(match found {
+                            Some(sched) => {
+                                let r = resource_of(&channel, sched, tz);
+                                response_xml(&r.path, &resource_props(&r), &req)
+                            }
+                            None => not_found_xml(path),
+                        });
+                    }
+                    responses
+                }
+                kind => {
+                    return Err((
+                        StatusCode::FORBIDDEN,
+                        format!("unsupported report {}", kind),
+                    ))
+                }
+            };
+            Ok(multistatus(&responses))
+        }
+        _ => Err(not_allowed(&method)),
+    }
+}
+
+fn header_str(headers: &HeaderMap, name: header::HeaderName) -> Option<&str> {
+    headers.get(name).and_then(|h| h.to_str().ok())
+}
+
+/// Honors If-Match and If-None-Match, so that clients don't overwrite
+/// changes they haven't seen yet.
+fn check_preconditions(headers: &HeaderMap, etag: Option<&str>) -> Result<(), ApiError> {
+    let matches = |tags: &str| {
+        etag.is_some_and(|etag| tags.trim() == "*" || tags.split(',').any(|t| t.trim() == etag))
+    };
+
+    if header_str(headers, header::IF_MATCH).is_some_and(|tags| !matches(tags))
+        || header_str(headers, header::IF_NONE_MATCH).is_some_and(matches)
+    {
+        return Err((
+            StatusCode::PRECONDITION_FAILED,
+            "the resource has changed".to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+fn calendar_data(resource: &Resource, status: StatusCode, with_body: bool) -> Response {
+    let body = if with_body {
+        Body::from(resource.data.to_owned())
+    } else {
+        Body::empty()
+    };
+
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "text/calendar; charset=utf-8")
+        .header(header::ETAG, resource.etag.as_str())
+        .body(boxed(body))
+        .unwrap()
+}
+
+pub async fn resource(
+    Path((channel, name)): Path<(String, String)>,
+    method: Method,
+    headers: HeaderMap,
+    Extension(user): Extension<User>,
+    State(state): State<Arc<AppState>>,
+    body: String,
+) -> Result<Response, ApiError> {
+    let access = Access::load(&state, &user, &channel).await?;
+
+    let tz = api::viewer_tz(&state, &user, &channel).await;
+    let sid = resource_sid(&name);
+    let found = state
+        .db
+        .find_sched_by_id(&channel, &sid)
+        .await
+        .map_err(internal_error)?
+        .map(|sched| resource_of(&channel, sched, tz));
+
+    match method.as_str() {
+        "OPTIONS" => Ok(options()),
+        "GET" | "HEAD" => match &found {
+            Some(resource) => Ok(calendar_data(
+                resource,
+                StatusCode::OK,
+                method == Method::GET,
+            )),
+            None => Err((StatusCode::NOT_FOUND, format!("{} not found", name))),
+        },
+        "PROPFIND" => {
+            let req = parse_request(&body).map_err(bad_request)?;
+            match &found {
+                Some(resource) => Ok(multistatus(&[response_xml(
+                    &resource.path,
+                    &resource_props(resource),
+                    &req,
+                )])),
+                None => Err((StatusCode::NOT_FOUND, format!("{} not found", name))),
+            }
+        }
+        "PUT" => {
+            access.require(Role::Editor)?;
+            api::check_open(&access.channel)?;
+            if let Some(prev) = &found {
+                access.require_manage(&prev.sched.id)?;
+            }
+            check_preconditions(&headers, found.as_ref().map(|r| r.etag.as_str()))?;
+
+            let events = ical::parse_components(&body, "VEVENT").map_err(bad_request)?;
+            // Changed occurrences of a series come as more VEVENTs with a
+            // RECURRENCE-ID, which schedules can't hold, so only the series
+            // itself is kept.
+            let props = events
+                .iter()
+                .find(|props| !props.iter().any(|p| p.name == "RECURRENCE-ID"))
+                .ok_or_else(|| (StatusCode::BAD_REQUEST, "no VEVENT".to_owned()))?;
+
+            let local =
+                ical::sched_from_event(props, &channel, &user.id, tz).map_err(bad_request)?;
+            let uid = ical::uid(&local);
+            let mut next = tz::to_utc(&local, tz);
+            next.sid = sid;
+            next.uid = (uid != sid.to_string()).then_some(uid);
+
+            let status = match &found {
+                Some(prev) => {
+                    next.id = prev.sched.id.to_owned();
+                    next.create_at = prev.sched.create_at;
+                    state
+                        .db
+                        .update_sched(&prev.sched, &next)
+                        .await
+                        .map_err(internal_error)?;
+                    StatusCode::NO_CONTENT
+                }
+                None => {
+                    state.db.insert_sched(&next).await.map_err(internal_error)?;
+                    StatusCode::CREATED
+                }
+            };
+
+            Ok(calendar_data(
+                &resource_of(&channel, next, tz),
+                status,
+                false,
+            ))
+        }
+        "DELETE" => {
+            access.require(Role::Editor)?;
+            api::check_open(&access.channel)?;
+            let resource =
+                found.ok_or_else(|| (StatusCode::NOT_FOUND, format!("{} not found", name)))?;
+            access.require_manage(&resource.sched.id)?;
+            check_preconditions(&headers, Some(&resource.etag))?;
+
+            state
+                .db
+                .delete_sched(&resource.sched)
+                .await
+                .map_err(internal_error)?;
+
+            Ok(api::response(StatusCode::NO_CONTENT, String::new()))
+        }
+        _ => Err(not_allowed(&method)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_propfind() {
+        let body = r#"<?xml version="1.0" encoding="utf-8"?>
+            <d:propfind xmlns:d="DAV:" xmlns:cs="http://calendarserver.org/ns/">
+              <d:prop><d:displayname/><cs:getctag/><d:quota-used-bytes/></d:prop>
+            </d:propfind>"#;
+
+        let req = parse_request(body).unwrap();
+
+        assert_eq!(req.kind, "propfind");
+        assert_eq!(
+            req.props,
+            vec![
+                (DAV.to_owned(), "displayname".to_owned()),
+                (CALSERVER.to_owned(), "getctag".to_owned()),
+                (DAV.to_owned(), "quota-used-bytes".to_owned()),
+            ]
+        );
+
+        let xml = response_xml(
+            "/dav/calendars/home/",
+            &[
+                prop(DAV, "displayname", "home"),
+                prop(CALSERVER, "getctag", "\"1\""),
+            ],
+            &req,
+        );
+        assert!(xml.contains("<d:prop><d:displayname>home</d:displayname><cs:getctag>\"1\"</cs:getctag></d:prop><d:status>HTTP/1.1 200 OK"));
+        assert!(xml.contains(
+            "<x:quota-used-bytes xmlns:x=\"DAV:\"/></d:prop><d:status>HTTP/1.1 404 Not Found"
+        ));
+    }
+
+    #[test]
+    fn test_parse_reports() {
+        let query = r#"<c:calendar-query xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav">
+              <d:prop><d:getetag/></d:prop>
+              <c:filter><c:comp-filter name="VCALENDAR"><c:comp-filter name="VEVENT">
+                <c:time-range start="20230701T000000Z" end="20230801T000000Z"/>
+              </c:comp-filter></c:comp-filter></c:filter>
+            </c:calendar-query>"#;
+
+        let req = parse_request(query).unwrap();
+        assert_eq!(req.kind, "calendar-query");
+        assert_eq!(req.props, vec![(DAV.to_owned(), "getetag".to_owned())]);
+        assert_eq!(req.start, parse_utc("20230701T000000Z"));
+        assert_eq!(req.end, parse_utc("20230801T000000Z"));
+
+        let multiget = r#"<c:calendar-multiget xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav">
+              <d:prop><d:getetag/><c:calendar-data/></d:prop>
+              <d:href>/dav/calendars/home/picnic%40example.com.ics</d:href>
+            </c:calendar-multiget>"#;
+
+        let req = parse_request(multiget).unwrap();
+        assert_eq!(req.kind, "calendar-multiget");
+        assert_eq!(
+            req.hrefs,
+            vec!["/dav/calendars/home/picnic%40example.com.ics"]
+        );
+    }
+
+    #[test]
+    fn test_resource_names_map_to_sid() {
+        let mut imported = Sched::new("home", "21kyu", "봄소풍", NaiveDate::default());
+        imported.uid = Some("picnic@example.com".to_owned());
+        imported.sid = ical::import_sid("picnic@example.com");
+        let native = Sched::new("home", "21kyu", "회의", NaiveDate::default());
+
+        assert_eq!(resource_name(&imported), "picnic@example.com.ics");
+        assert_eq!(resource_sid(&resource_name(&imported)), imported.sid);
+        assert_eq!(resource_sid(&resource_name(&native)), native.sid);
+    }
+
+    #[test]
+    fn test_check_preconditions() {
+        let mut headers = HeaderMap::new();
+        assert!(check_preconditions(&headers, None).is_ok());
+
+        headers.insert(header::IF_NONE_MATCH, "*".parse().unwrap());
+        assert!(check_preconditions(&headers, None).is_ok());
+        assert!(check_preconditions(&headers, Some("\"a\"")).is_err());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::IF_MATCH, "\"a\"".parse().unwrap());
+        assert!(check_preconditions(&headers, Some("\"a\"")).is_ok());
+        assert!(check_preconditions(&headers, Some("\"b\"")).is_err());
+        assert!(check_preconditions(&headers, None).is_err());
+    }
+}
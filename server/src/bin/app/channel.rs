@@ -0,0 +1,27 @@
+use scylla::frame::value::Timestamp;
+use scylla::FromRow;
+
+/// `channel` is the id schedules are stored under and never changes, `name`
+/// is what the channel is shown as and can be renamed.
+#[derive(Debug, Default, Clone, PartialEq, FromRow)]
+pub struct Channel {
+    pub channel: String,
+    pub tz: Option<String>,
+    /// Hash of the secret in the calendar feed URL of the channel, see
+    /// `refresh::hash`. `None` when no feed has been issued or it was revoked.
+    pub feed_token: Option<String>,
+    pub name: Option<String>,
+    /// Archived channels keep their schedules but can't be switched to or
+    /// written to.
+    pub archived_at: Option<Timestamp>,
+}
+
+impl Channel {
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.channel)
+    }
+
+    pub fn is_archived(&self) -> bool {
+        self.archived_at.is_some()
+    }
+}
@@ -0,0 +1,485 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use uuid::Uuid;
+
+use super::{
+    same_key, ChannelStore, IdentityStore, InviteStore, MemberStore, PersonalTokenStore,
+    RefreshTokenStore, SchedFilter, SchedPage, ScheduleStore, SessionStore, UserStore,
+};
+use crate::{
+    channel::Channel, identity::Identity, invite::Invite, member::Member, pat::PersonalToken,
+    refresh::RefreshToken, sched::Sched, session::Session, user::User,
+};
+
+/// Keeps everything in process memory, for local development and tests.
+#[derive(Default)]
+pub struct Memory {
+    users: RwLock<HashMap<String, User>>,
+    channels: RwLock<HashMap<String, Channel>>,
+    members: RwLock<Vec<Member>>,
+    invites: RwLock<Vec<Invite>>,
+    identities: RwLock<Vec<Identity>>,
+    refresh_tokens: RwLock<HashMap<String, RefreshToken>>,
+    sessions: RwLock<HashMap<Uuid, Session>>,
+    personal_tokens: RwLock<HashMap<String, PersonalToken>>,
+    scheds: RwLock<Vec<Sched>>,
+}
+
+#[async_trait]
+impl UserStore for Memory {
+    async fn find_user_by_id(&self, id: &str) -> Result<Option<User>> {
+        Ok(self.users.read().unwrap().get(id).cloned())
+    }
+
+    async fn insert_user(&self, user: &User) -> Result<()> {
+        self.users
+            .write()
+            .unwrap()
+            .insert(user.id.to_owned(), user.clone());
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ChannelStore for Memory {
+    async fn find_channel(&self, channel: &str) -> Result<Option<Channel>> {
+        Ok(self.channels.read().unwrap().get(channel).cloned())
+    }
+
+    async fn insert_channel(&self, channel: &Channel) -> Result<()> {
+        self.channels
+            .write()
+            .unwrap()
+            .insert(channel.channel.to_owned(), channel.clone());
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl MemberStore for Memory {
+    async fn find_member(&self, id: &str, channel: &str) -> Result<Option<Member>> {
+        Ok(self
+            .members
+            .read()
+            .unwrap()
+            .iter()
+            .find(|m| m.id == id && m.channel == channel)
+            .cloned())
+    }
+
+    async fn find_members_by_user(&self, id: &str) -> Result<Vec<Member>> {
+        Ok(self
+            .members
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|m| m.id == id)
+            .cloned()
+            .collect())
+    }
+
+    async fn find_members_by_channel(&self, channel: &str) -> Result<Vec<Member>> {
+        Ok(self
+            .members
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|m| m.channel == channel)
+            .cloned()
+            .collect())
+    }
+
+    async fn insert_member(&self, member: &Member) -> Result<()> {
+        let mut members = self.members.write().unwrap();
+        members.retain(|m| !(m.id == member.id && m.channel == member.channel));
+        members.push(member.clone());
+        Ok(())
+    }
+
+    async fn delete_member(&self, member: &Member) -> Result<()> {
+        self.members
+            .write()
+            .unwrap()
+            .retain(|m| !(m.id == member.id && m.channel == member.channel));
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl InviteStore for Memory {
+    async fn find_invite(&self, channel: &str, id: &Uuid) -> Result<Option<Invite>> {
+        Ok(self
+            .invites
+            .read()
+            .unwrap()
+            .iter()
+            .find(|i| i.channel == channel && &i.id == id)
+            .cloned())
+    }
+
+    async fn find_invites_by_channel(&self, channel: &str) -> Result<Vec<Invite>> {
+        Ok(self
+            .invites
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|i| i.channel == channel)
+            .cloned()
+            .collect())
+    }
+
+    async fn insert_invite(&self, invite: &Invite) -> Result<()> {
+        self.invites.write().unwrap().push(invite.clone());
+        Ok(())
+    }
+
+    async fn delete_invite(&self, invite: &Invite) -> Result<()> {
+        self.invites
+            .write()
+            .unwrap()
+            .retain(|i| !(i.channel == invite.channel && i.id == invite.id));
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl IdentityStore for Memory {
+    async fn find_identity(&self, provider: &str, subject: &str) -> Result<Option<Identity>> {
+        Ok(self
+            .identities
+            .read()
+            .unwrap()
+            .iter()
+            .find(|i| i.provider == provider && i.subject == subject)
+            .cloned())
+    }
+
+    async fn find_identities_by_user(&self, id: &str) -> Result<Vec<Identity>> {
+        Ok(self
+            .identities
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|i| i.id == id)
+            .cloned()
+            .collect())
+    }
+
+    async fn insert_identity(&self, identity: &Identity) -> Result<()> {
+        let mut identities = self.identities.write().unwrap();
+        identities.retain(|i| !(i.provider == identity.provider && i.subject == identity.subject));
+        identities.push(identity.clone());
+        Ok(())
+    }
+
+    async fn delete_identity(&self, identity: &Identity) -> Result<()> {
+        self.identities
+            .write()
+            .unwrap()
+            .retain(|i| !(i.provider == identity.provider && i.subject == identity.subject));
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RefreshTokenStore for Memory {
+    async fn find_refresh_token(&self, hash: &str) -> Result<Option<RefreshToken>> {
+        Ok(self.refresh_tokens.read().unwrap().get(hash).cloned())
+    }
+
+    async fn insert_refresh_token(&self, token: &RefreshToken) -> Result<()> {
+        self.refresh_tokens
+            .write()
+            .unwrap()
+            .insert(token.hash.to_owned(), token.clone());
+        Ok(())
+    }
+
+    async fn delete_refresh_tokens_by_session(&self, session: &Uuid) -> Result<()> {
+        self.refresh_tokens
+            .write()
+            .unwrap()
+            .retain(|_, t| &t.session != session);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SessionStore for Memory {
+    async fn find_session(&self, id: &Uuid) -> Result<Option<Session>> {
+        Ok(self.sessions.read().unwrap().get(id).cloned())
+    }
+
+    async fn find_sessions_by_user(&self, user: &str) -> Result<Vec<Session>> {
+        let mut sessions = self
+            .sessions
+            .read()
+            .unwrap()
+            .values()
+            .filter(|s| s.user == user)
+            .cloned()
+            .collect::<Vec<_>>();
+        sessions.sort_by_key(|s| std::cmp::Reverse(s.last_seen_at.0));
+        Ok(sessions)
+    }
+
+    async fn insert_session(&self, session: &Session) -> Result<()> {
+        self.sessions
+            .write()
+            .unwrap()
+            .insert(session.id, session.clone());
+        Ok(())
+    }
+
+    async fn delete_session(&self, session: &Session) -> Result<()> {
+        self.sessions.write().unwrap().remove(&session.id);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl PersonalTokenStore for Memory {
+    async fn find_personal_token(&self, hash: &str) -> Result<Option<PersonalToken>> {
+        Ok(self.personal_tokens.read().unwrap().get(hash).cloned())
+    }
+
+    async fn find_personal_tokens_by_user(&self, id: &str) -> Result<Vec<PersonalToken>> {
+        let mut tokens = self
+            .personal_tokens
+            .read()
+            .unwrap()
+            .values()
+            .filter(|t| t.id == id)
+            .cloned()
+            .collect::<Vec<_>>();
+        tokens.sort_by_key(|t| t.created_at.0);
+        Ok(tokens)
+    }
+
+    async fn insert_personal_token(&self, token: &PersonalToken) -> Result<()> {
+        self.personal_tokens
+            .write()
+            .unwrap()
+            .insert(token.hash.to_owned(), token.clone());
+        Ok(())
+    }
+
+    async fn delete_personal_token(&self, token: &PersonalToken) -> Result<()> {
+        self.personal_tokens.write().unwrap().remove(&token.hash);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ScheduleStore for Memory {
+    async fn find_sched_by_channel(
+        &self,
+        channel: &str,
+        filter: &SchedFilter,
+    ) -> Result<SchedPage> {
+        let offset = filter.offset()?;
+        let mut scheds = self
+            .scheds
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|s| s.channel == channel && filter.matches(s))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        scheds.sort_by_key(|s| (s.date_at, s.id.to_owned(), s.create_at.0));
+        let scheds = scheds
+            .into_iter()
+            .skip(offset)
+            .take(filter.page_size().max(0) as usize)
+            .collect::<Vec<_>>();
+
+        Ok(SchedPage {
+            next: filter.next_offset(offset, scheds.len()),
+            scheds,
+        })
+    }
+
+    async fn find_recurring_by_channel(&self, channel: &str) -> Result<Vec<Sched>> {
+        Ok(self
+            .scheds
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|s| s.channel == channel && s.rrule.is_some())
+            .cloned()
+            .collect())
+    }
+
+    async fn find_sched_by_id(&self, channel: &str, sid: &Uuid) -> Result<Option<Sched>> {
+        Ok(self
+            .scheds
+            .read()
+            .unwrap()
+            .iter()
+            .find(|s| s.channel == channel && &s.sid == sid)
+            .cloned())
+    }
+
+    async fn insert_sched(&self, sched: &Sched) -> Result<()> {
+        let mut scheds = self.scheds.write().unwrap();
+
+        // Same primary key overwrites, like an upsert in ks.s.
+        scheds.retain(|s| !same_key(s, sched));
+        scheds.push(sched.clone());
+
+        Ok(())
+    }
+
+    async fn update_sched(&self, prev: &Sched, next: &Sched) -> Result<()> {
+        let mut scheds = self.scheds.write().unwrap();
+
+        scheds.retain(|s| !same_key(s, prev) && !same_key(s, next));
+        scheds.push(next.clone());
+
+        Ok(())
+    }
+
+    async fn delete_sched(&self, sched: &Sched) -> Result<()> {
+        self.scheds.write().unwrap().retain(|s| !same_key(s, sched));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, Utc};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_user_roundtrip() {
+        let store = Memory::default();
+        let user = User {
+            id: "21kyu".to_owned(),
+            channel: "home".to_owned(),
+            tz: Some("Asia/Seoul".to_owned()),
+        };
+
+        assert!(store.find_user_by_id("21kyu").await.unwrap().is_none());
+
+        store.insert_user(&user).await.unwrap();
+
+        let found = store.find_user_by_id("21kyu").await.unwrap().unwrap();
+        assert_eq!(found.channel, "home");
+        assert_eq!(found.tz.as_deref(), Some("Asia/Seoul"));
+    }
+
+    #[tokio::test]
+    async fn test_update_sched() {
+        let store = Memory::default();
+        let today = Utc::now().date_naive();
+        let prev = Sched::new("home", "21kyu", "봄소풍", today);
+
+        store.insert_sched(&prev).await.unwrap();
+
+        let renamed = Sched {
+            sched: "가을소풍".to_owned(),
+            ..prev.clone()
+        };
+        store.update_sched(&prev, &renamed).await.unwrap();
+        let filter = SchedFilter::default();
+        let page = store.find_sched_by_channel("home", &filter).await.unwrap();
+        assert_eq!(page.scheds, vec![renamed.clone()]);
+
+        let moved = Sched {
+            date_at: today + Duration::days(1),
+            ..renamed.clone()
+        };
+        store.update_sched(&renamed, &moved).await.unwrap();
+        let page = store.find_sched_by_channel("home", &filter).await.unwrap();
+        assert_eq!(page.scheds, vec![moved]);
+    }
+
+    #[tokio::test]
+    async fn test_find_sched_by_channel_with_filter() {
+        let store = Memory::default();
+        let today = Utc::now().date_naive();
+
+        let past = Sched::new("home", "21kyu", "지난 일정", today - Duration::days(10));
+        let mine = Sched::new("home", "21kyu", "봄소풍", today + Duration::days(3));
+        let theirs = Sched::new("home", "csj200045", "회의", today + Duration::days(4));
+        let far = Sched::new("home", "21kyu", "여행", today + Duration::days(30));
+
+        for sched in [&past, &mine, &theirs, &far] {
+            store.insert_sched(sched).await.unwrap();
+        }
+
+        let next_week = SchedFilter {
+            from: Some(today - Duration::days(14)),
+            to: Some(today + Duration::days(7)),
+            ..Default::default()
+        };
+        let page = store
+            .find_sched_by_channel("home", &next_week)
+            .await
+            .unwrap();
+        assert_eq!(page.scheds, vec![past.clone(), mine.clone(), theirs]);
+        assert_eq!(page.next, None);
+
+        let only_mine = SchedFilter {
+            user: Some("21kyu".to_owned()),
+            limit: Some(1),
+            ..next_week
+        };
+        let page = store
+            .find_sched_by_channel("home", &only_mine)
+            .await
+            .unwrap();
+        assert_eq!(page.scheds, vec![past]);
+
+        let next_page = SchedFilter {
+            cursor: page.next,
+            ..only_mine
+        };
+        let page = store
+            .find_sched_by_channel("home", &next_page)
+            .await
+            .unwrap();
+        assert_eq!(page.scheds, vec![mine]);
+    }
+
+    #[tokio::test]
+    async fn test_find_sched_by_channel_skips_past_and_other_channels() {
+        let store = Memory::default();
+        let today = Utc::now().date_naive();
+
+        let later = Sched::new("home", "21kyu", "봄소풍", today + Duration::days(3));
+        let sooner = Sched::new("home", "csj200045", "회의", today);
+        let past = Sched::new("home", "21kyu", "지난 일정", today - Duration::days(1));
+        let other = Sched::new("work", "21kyu", "출장", today);
+
+        for sched in [&later, &sooner, &past, &other] {
+            store.insert_sched(sched).await.unwrap();
+        }
+
+        let page = store
+            .find_sched_by_channel("home", &SchedFilter::default())
+            .await
+            .unwrap();
+        assert_eq!(page.scheds, vec![sooner.clone(), later.clone()]);
+
+        let mut moved = sooner.clone();
+        moved.date_at = today + Duration::days(7);
+        store.update_sched(&sooner, &moved).await.unwrap();
+
+        let found = store.find_sched_by_id("home", &sooner.sid).await.unwrap();
+        assert_eq!(found, Some(moved.clone()));
+
+        store.delete_sched(&moved).await.unwrap();
+        assert!(store
+            .find_sched_by_id("home", &sooner.sid)
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
@@ -0,0 +1,228 @@
+mod memory;
+mod scylla;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use base64::{engine::general_purpose, Engine as _};
+use chrono::NaiveDate;
+use clap::ValueEnum;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    channel::Channel, identity::Identity, invite::Invite, member::Member, pat::PersonalToken,
+    refresh::RefreshToken, sched::Sched, session::Session, user::User,
+};
+
+pub use self::memory::Memory;
+pub use self::scylla::Scylla;
+#[cfg(feature = "sqlite")]
+pub use self::sqlite::Sqlite;
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Scylla,
+    Memory,
+    Sqlite,
+}
+
+#[async_trait]
+pub trait UserStore: Send + Sync {
+    async fn find_user_by_id(&self, id: &str) -> Result<Option<User>>;
+    async fn insert_user(&self, user: &User) -> Result<()>;
+}
+
+pub const DEFAULT_PAGE_SIZE: i32 = 50;
+
+/// Whether two schedules share the primary key `(channel, date_at, id,
+/// create_at)`, so an update can overwrite the row in place.
+pub fn same_key(a: &Sched, b: &Sched) -> bool {
+    (a.channel.as_str(), a.date_at, a.id.as_str(), a.create_at)
+        == (b.channel.as_str(), b.date_at, b.id.as_str(), b.create_at)
+}
+
+/// Narrows a channel listing. `from` and `to` are inclusive and default to
+/// today and unbounded, `user` matches the schedule owner. `limit` is the
+/// page size and `cursor` the `next` value of the previous page.
+#[derive(Deserialize, Debug, Default, Clone, PartialEq, Eq)]
+pub struct SchedFilter {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub user: Option<String>,
+    pub limit: Option<i32>,
+    pub cursor: Option<String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SchedPage {
+    pub scheds: Vec<Sched>,
+    pub next: Option<String>,
+}
+
+pub fn encode_cursor(state: &[u8]) -> String {
+    general_purpose::URL_SAFE_NO_PAD.encode(state)
+}
+
+pub fn decode_cursor(cursor: &str) -> Result<Vec<u8>> {
+    general_purpose::URL_SAFE_NO_PAD
+        .decode(cursor)
+        .map_err(|e| anyhow::anyhow!("invalid cursor: {}", e))
+}
+
+impl SchedFilter {
+    pub fn page_size(&self) -> i32 {
+        self.limit.unwrap_or(DEFAULT_PAGE_SIZE)
+    }
+
+    /// Index of the first row of the page, for stores that page by offset.
+    pub fn offset(&self) -> Result<usize> {
+        match &self.cursor {
+            Some(cursor) => {
+                let bytes: [u8; 8] = decode_cursor(cursor)?
+                    .try_into()
+                    .map_err(|_| anyhow::anyhow!("invalid cursor"))?;
+                Ok(u64::from_be_bytes(bytes) as usize)
+            }
+            None => Ok(0),
+        }
+    }
+
+    /// Cursor for the page after one starting at `offset` with `len` rows.
+    pub fn next_offset(&self, offset: usize, len: usize) -> Option<String> {
+        if len < self.page_size() as usize {
+            return None;
+        }
+        Some(encode_cursor(&((offset + len) as u64).to_be_bytes()))
+    }
+
+    pub fn start(&self) -> NaiveDate {
+        self.from.unwrap_or_else(|| chrono::Utc::now().date_naive())
+    }
+
+    pub fn matches(&self, sched: &Sched) -> bool {
+        sched.date_at >= self.start()
+            && self.to.is_none_or(|to| sched.date_at <= to)
+            && self.user.as_ref().is_none_or(|user| &sched.id == user)
+    }
+}
+
+#[async_trait]
+pub trait ScheduleStore: Send + Sync {
+    /// Returns the schedules of the channel matching `filter`, ordered like
+    /// the `(date_at, id, create_at)` clustering key of `ks.s`.
+    async fn find_sched_by_channel(
+        &self,
+        channel: &str,
+        filter: &SchedFilter,
+    ) -> Result<SchedPage>;
+    /// Returns every series of the channel, whatever its first date. Their
+    /// occurrences are expanded by the caller.
+    async fn find_recurring_by_channel(&self, channel: &str) -> Result<Vec<Sched>>;
+    async fn find_sched_by_id(&self, channel: &str, sid: &Uuid) -> Result<Option<Sched>>;
+    async fn insert_sched(&self, sched: &Sched) -> Result<()>;
+    /// Replaces `prev` with `next`. They share a `sid`, but the primary key
+    /// may differ when the date or owner changed.
+    async fn update_sched(&self, prev: &Sched, next: &Sched) -> Result<()>;
+    async fn delete_sched(&self, sched: &Sched) -> Result<()>;
+}
+
+#[async_trait]
+pub trait ChannelStore: Send + Sync {
+    async fn find_channel(&self, channel: &str) -> Result<Option<Channel>>;
+    async fn insert_channel(&self, channel: &Channel) -> Result<()>;
+}
+
+#[async_trait]
+pub trait MemberStore: Send + Sync {
+    async fn find_member(&self, id: &str, channel: &str) -> Result<Option<Member>>;
+    async fn find_members_by_user(&self, id: &str) -> Result<Vec<Member>>;
+    async fn find_members_by_channel(&self, channel: &str) -> Result<Vec<Member>>;
+    async fn insert_member(&self, member: &Member) -> Result<()>;
+    async fn delete_member(&self, member: &Member) -> Result<()>;
+}
+
+#[async_trait]
+pub trait InviteStore: Send + Sync {
+    async fn find_invite(&self, channel: &str, id: &Uuid) -> Result<Option<Invite>>;
+    async fn find_invites_by_channel(&self, channel: &str) -> Result<Vec<Invite>>;
+    async fn insert_invite(&self, invite: &Invite) -> Result<()>;
+    async fn delete_invite(&self, invite: &Invite) -> Result<()>;
+}
+
+#[async_trait]
+pub trait IdentityStore: Send + Sync {
+    async fn find_identity(&self, provider: &str, subject: &str) -> Result<Option<Identity>>;
+    async fn find_identities_by_user(&self, id: &str) -> Result<Vec<Identity>>;
+    async fn insert_identity(&self, identity: &Identity) -> Result<()>;
+    async fn delete_identity(&self, identity: &Identity) -> Result<()>;
+}
+
+#[async_trait]
+pub trait RefreshTokenStore: Send + Sync {
+    async fn find_refresh_token(&self, hash: &str) -> Result<Option<RefreshToken>>;
+    /// Also marks a token used, as rows are replaced by hash.
+    async fn insert_refresh_token(&self, token: &RefreshToken) -> Result<()>;
+    async fn delete_refresh_tokens_by_session(&self, session: &Uuid) -> Result<()>;
+}
+
+#[async_trait]
+pub trait SessionStore: Send + Sync {
+    async fn find_session(&self, id: &Uuid) -> Result<Option<Session>>;
+    async fn find_sessions_by_user(&self, user: &str) -> Result<Vec<Session>>;
+    async fn insert_session(&self, session: &Session) -> Result<()>;
+    async fn delete_session(&self, session: &Session) -> Result<()>;
+}
+
+#[async_trait]
+pub trait PersonalTokenStore: Send + Sync {
+    async fn find_personal_token(&self, hash: &str) -> Result<Option<PersonalToken>>;
+    async fn find_personal_tokens_by_user(&self, id: &str) -> Result<Vec<PersonalToken>>;
+    async fn insert_personal_token(&self, token: &PersonalToken) -> Result<()>;
+    async fn delete_personal_token(&self, token: &PersonalToken) -> Result<()>;
+}
+
+pub trait Store:
+    UserStore
+    + ScheduleStore
+    + ChannelStore
+    + MemberStore
+    + InviteStore
+    + IdentityStore
+    + RefreshTokenStore
+    + SessionStore
+    + PersonalTokenStore
+{
+}
+
+impl<T> Store for T where
+    T: UserStore
+        + ScheduleStore
+        + ChannelStore
+        + MemberStore
+        + InviteStore
+        + IdentityStore
+        + RefreshTokenStore
+        + SessionStore
+        + PersonalTokenStore
+{
+}
+
+pub async fn connect(backend: Backend, sqlite_path: &str) -> Result<Arc<dyn Store>> {
+    eprintln!("Using {:?} backend", backend);
+
+    match backend {
+        Backend::Scylla => Ok(Arc::new(Scylla::new().await?)),
+        Backend::Memory => Ok(Arc::new(Memory::default())),
+        #[cfg(feature = "sqlite")]
+        Backend::Sqlite => Ok(Arc::new(Sqlite::open(sqlite_path)?)),
+        #[cfg(not(feature = "sqlite"))]
+        Backend::Sqlite => Err(anyhow::anyhow!(
+            "cannot open {}: built without the sqlite feature",
+            sqlite_path
+        )),
+    }
+}
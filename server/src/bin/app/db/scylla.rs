@@ -0,0 +1,864 @@
+use std::collections::BTreeMap;
+use std::env;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::NaiveDate;
+use scylla::batch::Batch;
+use scylla::frame::value::{SerializedValues, Timestamp, ValueList};
+use scylla::{Bytes, IntoTypedRows, Session, SessionBuilder};
+use uuid::Uuid;
+
+use super::{
+    decode_cursor, encode_cursor, same_key, ChannelStore, IdentityStore, InviteStore, MemberStore,
+    PersonalTokenStore, RefreshTokenStore, SchedFilter, SchedPage, ScheduleStore, SessionStore,
+    UserStore,
+};
+use crate::channel::Channel;
+use crate::identity::Identity;
+use crate::invite::Invite;
+use crate::member::{legacy_roles, Member};
+use crate::pat::PersonalToken;
+use crate::refresh::RefreshToken;
+use crate::sched::{time_to_cql, Sched};
+use crate::session::Session as DeviceSession;
+use crate::user::User;
+
+pub struct Scylla {
+    pub session: Session,
+}
+
+impl Scylla {
+    pub async fn new() -> Result<Self> {
+        let uri = env::var("SCYLLA_URI").unwrap_or_else(|_| "db:9042".to_string());
+
+        println!("Connecting at {}", uri);
+
+        let session: Session = SessionBuilder::new().known_node(uri).build().await?;
+
+        session.query("CREATE KEYSPACE IF NOT EXISTS ks WITH REPLICATION = {'class' : 'SimpleStrategy', 'replication_factor' : 1}", &[]).await?;
+
+        session
+            .query(
+                "CREATE TABLE IF NOT EXISTS ks.u (id text primary key, channel text, tz text)",
+                &[],
+            )
+            .await?;
+
+        add_columns(&session, "ks.u", &[("tz", "text")]).await;
+
+        session
+            .query(
+                "CREATE TABLE IF NOT EXISTS ks.c (channel text primary key, tz text, feed_token text, name text, archived_at timestamp)",
+                &[],
+            )
+            .await?;
+
+        add_columns(
+            &session,
+            "ks.c",
+            &[
+                ("feed_token", "text"),
+                ("name", "text"),
+                ("archived_at", "timestamp"),
+            ],
+        )
+        .await;
+
+        session
+            .query(
+                "CREATE TABLE IF NOT EXISTS ks.m (id text, channel text, role text, PRIMARY KEY (id, channel))",
+                &[],
+            )
+            .await?;
+
+        add_columns(&session, "ks.m", &[("role", "text")]).await;
+
+        session
+            .query("CREATE INDEX IF NOT EXISTS ON ks.m (channel)", &[])
+            .await?;
+
+        session
+            .query(
+                "CREATE TABLE IF NOT EXISTS ks.i (channel text, id uuid, created_by text, expires_at timestamp, single_use boolean,
+                PRIMARY KEY (channel, id))",
+                &[],
+            )
+            .await?;
+
+        session
+            .query(
+                "CREATE TABLE IF NOT EXISTS ks.l (provider text, subject text, id text, login text,
+                PRIMARY KEY ((provider, subject)))",
+                &[],
+            )
+            .await?;
+
+        session
+            .query("CREATE INDEX IF NOT EXISTS ON ks.l (id)", &[])
+            .await?;
+
+        session
+            .query(
+                "CREATE TABLE IF NOT EXISTS ks.r (hash text primary key, session uuid, id text, expires_at timestamp, used boolean)",
+                &[],
+            )
+            .await?;
+
+        session
+            .query("CREATE INDEX IF NOT EXISTS ON ks.r (session)", &[])
+            .await?;
+
+        session
+            .query(
+                "CREATE TABLE IF NOT EXISTS ks.d (id uuid primary key, user text, device text, ip text,
+                created_at timestamp, last_seen_at timestamp)",
+                &[],
+            )
+            .await?;
+
+        session
+            .query("CREATE INDEX IF NOT EXISTS ON ks.d (user)", &[])
+            .await?;
+
+        session
+            .query(
+                "CREATE TABLE IF NOT EXISTS ks.t (hash text primary key, id text, tid uuid, name text, scope text,
+                channel text, created_at timestamp, expires_at timestamp, last_used_at timestamp)",
+                &[],
+            )
+            .await?;
+
+        session
+            .query("CREATE INDEX IF NOT EXISTS ON ks.t (id)", &[])
+            .await?;
+
+        session
+            .query("CREATE TABLE IF NOT EXISTS ks.s (channel text, id text, sched text, date_at date, create_at timestamp, sid uuid,
+                start_time time, end_time time, all_day boolean, rrule text, exdates list<date>, recurring boolean, uid text, tz text,
+                PRIMARY KEY (channel, date_at, id, create_at))", &[])
+            .await?;
+
+        add_columns(
+            &session,
+            "ks.s",
+            &[
+                ("sid", "uuid"),
+                ("start_time", "time"),
+                ("end_time", "time"),
+                ("all_day", "boolean"),
+                ("rrule", "text"),
+                ("exdates", "list<date>"),
+                ("recurring", "boolean"),
+                ("uid", "text"),
+                ("tz", "text"),
+            ],
+        )
+        .await;
+
+        session
+            .query("CREATE INDEX IF NOT EXISTS ON ks.s ((channel), sid)", &[])
+            .await?;
+
+        session
+            .query(
+                "CREATE TABLE IF NOT EXISTS ks.v (migration text primary key, applied_at timestamp)",
+                &[],
+            )
+            .await?;
+
+        run_once(&session, "backfill_sid", backfill_sid(&session)).await?;
+        run_once(&session, "backfill_roles", backfill_roles(&session)).await?;
+
+        Ok(Self { session })
+    }
+}
+
+/// Brings tables created by older versions up to date. Adding a column that
+/// already exists fails, so errors are ignored.
+async fn add_columns(session: &Session, table: &str, columns: &[(&str, &str)]) {
+    for (name, ty) in columns {
+        let _ = session
+            .query(format!("ALTER TABLE {} ADD {} {}", table, name, ty), &[])
+            .await;
+    }
+}
+
+/// Runs a data migration unless `ks.v` records it as done. Migrations that
+/// scan whole tables would otherwise slow down every start.
+async fn run_once(
+    session: &Session,
+    name: &str,
+    migration: impl std::future::Future<Output = Result<()>>,
+) -> Result<()> {
+    let done = session
+        .query("SELECT migration FROM ks.v WHERE migration = ?", (name,))
+        .await?
+        .rows
+        .is_some_and(|rows| !rows.is_empty());
+    if done {
+        return Ok(());
+    }
+
+    println!("migrating: {}", name);
+    migration.await?;
+    session
+        .query(
+            "INSERT INTO ks.v (migration, applied_at) VALUES (?, toTimestamp(now()))",
+            (name,),
+        )
+        .await?;
+
+    Ok(())
+}
+
+async fn backfill_sid(session: &Session) -> Result<()> {
+    let rows = session
+        .query("SELECT channel, date_at, id, create_at, sid FROM ks.s", &[])
+        .await?
+        .rows
+        .unwrap_or_default();
+
+    let prepared = session
+        .prepare("UPDATE ks.s SET sid = ? WHERE channel = ? AND date_at = ? AND id = ? AND create_at = ?")
+        .await?;
+
+    for row in rows.into_typed::<(String, NaiveDate, String, Timestamp, Option<Uuid>)>() {
+        let (channel, date_at, id, create_at, sid) = row?;
+        if sid.is_none() {
+            println!("backfill sid: {} {} {}", channel, date_at, id);
+            session
+                .execute(&prepared, (Uuid::new_v4(), channel, date_at, id, create_at))
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Gives memberships from before roles an explicit one; see
+/// `member::legacy_roles`.
+async fn backfill_roles(session: &Session) -> Result<()> {
+    let rows = session
+        .query("SELECT id, channel, role FROM ks.m", &[])
+        .await?
+        .rows
+        .unwrap_or_default();
+
+    let mut channels: BTreeMap<String, Vec<Member>> = BTreeMap::new();
+    for member in rows.into_typed::<Member>() {
+        let member = member?;
+        channels
+            .entry(member.channel.clone())
+            .or_default()
+            .push(member);
+    }
+
+    let prepared = session
+        .prepare("UPDATE ks.m SET role = ? WHERE id = ? AND channel = ?")
+        .await?;
+
+    for members in channels.values() {
+        for member in legacy_roles(members) {
+            println!(
+                "backfill role: {} {} {}",
+                member.channel,
+                member.id,
+                member.role()
+            );
+            session
+                .execute(
+                    &prepared,
+                    (member.role.as_deref(), &member.id, &member.channel),
+                )
+                .await?;
+        }
+    }
+
+    Ok(())
+}
+
+fn insert_values(sched: &Sched) -> impl ValueList + '_ {
+    (
+        sched.channel.as_str(),
+        sched.id.as_str(),
+        sched.sched.as_str(),
+        sched.date_at,
+        sched.create_at,
+        sched.sid,
+        time_to_cql(sched.start_time),
+        time_to_cql(sched.end_time),
+        sched.all_day,
+        sched.rrule.as_deref(),
+        &sched.exdates,
+        sched.rrule.is_some(),
+        sched.uid.as_deref(),
+        sched.tz.as_deref(),
+    )
+}
+
+fn key_values(sched: &Sched) -> impl ValueList + '_ {
+    (
+        sched.channel.as_str(),
+        sched.date_at,
+        sched.id.as_str(),
+        sched.create_at,
+    )
+}
+
+const SCHED_COLUMNS: &str =
+    "channel, id, sched, date_at, create_at, sid, start_time, end_time, all_day, rrule, exdates, uid, tz";
+// `recurring` mirrors whether `rrule` is set, since a null check can't be
+// filtered on.
+const INSERT_SCHED: &str = "INSERT INTO ks.s (channel, id, sched, date_at, create_at, sid, start_time, end_time, all_day, rrule, exdates, recurring, uid, tz) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
+const DELETE_SCHED: &str =
+    "DELETE FROM ks.s WHERE channel = ? AND date_at = ? AND id = ? AND create_at = ?";
+
+#[async_trait]
+impl UserStore for Scylla {
+    async fn find_user_by_id(&self, id: &str) -> Result<Option<User>> {
+        if let Some(rows) = self
+            .session
+            .query("SELECT id, channel, tz FROM ks.u WHERE id = ?", (id,))
+            .await?
+            .rows
+        {
+            Ok(rows.into_typed::<User>().next().transpose()?)
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn insert_user(&self, user: &User) -> Result<()> {
+        let prepared = self
+            .session
+            .prepare("INSERT INTO ks.u (id, channel, tz) VALUES (?, ?, ?)")
+            .await?;
+
+        self.session
+            .execute(
+                &prepared,
+                (user.id.as_str(), user.channel.as_str(), user.tz.as_deref()),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ChannelStore for Scylla {
+    async fn find_channel(&self, channel: &str) -> Result<Option<Channel>> {
+        let q = "SELECT channel, tz, feed_token, name, archived_at FROM ks.c WHERE channel = ?";
+        match self.session.query(q, (channel,)).await?.rows {
+            Some(rows) => Ok(rows.into_typed::<Channel>().next().transpose()?),
+            _ => Ok(None),
+        }
+    }
+
+    async fn insert_channel(&self, channel: &Channel) -> Result<()> {
+        let prepared = self
+            .session
+            .prepare(
+                "INSERT INTO ks.c (channel, tz, feed_token, name, archived_at) VALUES (?, ?, ?, ?, ?)",
+            )
+            .await?;
+
+        self.session
+            .execute(
+                &prepared,
+                (
+                    channel.channel.as_str(),
+                    channel.tz.as_deref(),
+                    channel.feed_token.as_deref(),
+                    channel.name.as_deref(),
+                    channel.archived_at,
+                ),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl MemberStore for Scylla {
+    async fn find_member(&self, id: &str, channel: &str) -> Result<Option<Member>> {
+        let q = "SELECT id, channel, role FROM ks.m WHERE id = ? AND channel = ?";
+        match self.session.query(q, (id, channel)).await?.rows {
+            Some(rows) => Ok(rows.into_typed::<Member>().next().transpose()?),
+            _ => Ok(None),
+        }
+    }
+
+    async fn find_members_by_user(&self, id: &str) -> Result<Vec<Member>> {
+        let q = "SELECT id, channel, role FROM ks.m WHERE id = ?";
+        match self.session.query(q, (id,)).await?.rows {
+            Some(rows) => Ok(rows.into_typed::<Member>().collect::<Result<_, _>>()?),
+            _ => Ok(vec![]),
+        }
+    }
+
+    async fn find_members_by_channel(&self, channel: &str) -> Result<Vec<Member>> {
+        let q = "SELECT id, channel, role FROM ks.m WHERE channel = ?";
+        match self.session.query(q, (channel,)).await?.rows {
+            Some(rows) => Ok(rows.into_typed::<Member>().collect::<Result<_, _>>()?),
+            _ => Ok(vec![]),
+        }
+    }
+
+    async fn insert_member(&self, member: &Member) -> Result<()> {
+        let prepared = self
+            .session
+            .prepare("INSERT INTO ks.m (id, channel, role) VALUES (?, ?, ?)")
+            .await?;
+
+        self.session
+            .execute(
+                &prepared,
+                (
+                    member.id.as_str(),
+                    member.channel.as_str(),
+                    member.role.as_deref(),
+                ),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_member(&self, member: &Member) -> Result<()> {
+        let prepared = self
+            .session
+            .prepare("DELETE FROM ks.m WHERE id = ? AND channel = ?")
+            .await?;
+
+        self.session
+            .execute(&prepared, (member.id.as_str(), member.channel.as_str()))
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl IdentityStore for Scylla {
+    async fn find_identity(&self, provider: &str, subject: &str) -> Result<Option<Identity>> {
+        let q = "SELECT provider, subject, id, login FROM ks.l WHERE provider = ? AND subject = ?";
+        match self.session.query(q, (provider, subject)).await?.rows {
+            Some(rows) => Ok(rows.into_typed::<Identity>().next().transpose()?),
+            _ => Ok(None),
+        }
+    }
+
+    async fn find_identities_by_user(&self, id: &str) -> Result<Vec<Identity>> {
+        let q = "SELECT provider, subject, id, login FROM ks.l WHERE id = ?";
+        match self.session.query(q, (id,)).await?.rows {
+            Some(rows) => Ok(rows.into_typed::<Identity>().collect::<Result<_, _>>()?),
+            _ => Ok(vec![]),
+        }
+    }
+
+    async fn insert_identity(&self, identity: &Identity) -> Result<()> {
+        let prepared = self
+            .session
+            .prepare("INSERT INTO ks.l (provider, subject, id, login) VALUES (?, ?, ?, ?)")
+            .await?;
+
+        self.session
+            .execute(
+                &prepared,
+                (
+                    identity.provider.as_str(),
+                    identity.subject.as_str(),
+                    identity.id.as_str(),
+                    identity.login.as_str(),
+                ),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_identity(&self, identity: &Identity) -> Result<()> {
+        let prepared = self
+            .session
+            .prepare("DELETE FROM ks.l WHERE provider = ? AND subject = ?")
+            .await?;
+
+        self.session
+            .execute(
+                &prepared,
+                (identity.provider.as_str(), identity.subject.as_str()),
+            )
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl RefreshTokenStore for Scylla {
+    async fn find_refresh_token(&self, hash: &str) -> Result<Option<RefreshToken>> {
+        let q = "SELECT hash, session, id, expires_at, used FROM ks.r WHERE hash = ?";
+        match self.session.query(q, (hash,)).await?.rows {
+            Some(rows) => Ok(rows.into_typed::<RefreshToken>().next().transpose()?),
+            _ => Ok(None),
+        }
+    }
+
+    async fn insert_refresh_token(&self, token: &RefreshToken) -> Result<()> {
+        // Expired tokens drop out by themselves.
+        let ttl = (token.expires_at.0.num_seconds() - chrono::Utc::now().timestamp()).max(1);
+        let prepared = self
+            .session
+            .prepare("INSERT INTO ks.r (hash, session, id, expires_at, used) VALUES (?, ?, ?, ?, ?) USING TTL ?")
+            .await?;
+
+        self.session
+            .execute(
+                &prepared,
+                (
+                    token.hash.as_str(),
+                    token.session,
+                    token.id.as_str(),
+                    token.expires_at,
+                    token.used,
+                    ttl as i32,
+                ),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_refresh_tokens_by_session(&self, session: &Uuid) -> Result<()> {
+        let q = "SELECT hash FROM ks.r WHERE session = ?";
+        let hashes = match self.session.query(q, (session,)).await?.rows {
+            Some(rows) => rows
+                .into_typed::<(String,)>()
+                .collect::<Result<Vec<_>, _>>()?,
+            _ => vec![],
+        };
+
+        let prepared = self
+            .session
+            .prepare("DELETE FROM ks.r WHERE hash = ?")
+            .await?;
+        for (hash,) in hashes {
+            self.session.execute(&prepared, (hash,)).await?;
+        }
+
+        Ok(())
+    }
+}
+
+const SESSION_COLUMNS: &str = "id, user, device, ip, created_at, last_seen_at";
+
+#[async_trait]
+impl SessionStore for Scylla {
+    async fn find_session(&self, id: &Uuid) -> Result<Option<DeviceSession>> {
+        let q = format!("SELECT {} FROM ks.d WHERE id = ?", SESSION_COLUMNS);
+        match self.session.query(q, (id,)).await?.rows {
+            Some(rows) => Ok(rows.into_typed::<DeviceSession>().next().transpose()?),
+            _ => Ok(None),
+        }
+    }
+
+    async fn find_sessions_by_user(&self, user: &str) -> Result<Vec<DeviceSession>> {
+        let q = format!("SELECT {} FROM ks.d WHERE user = ?", SESSION_COLUMNS);
+        let mut sessions = match self.session.query(q, (user,)).await?.rows {
+            Some(rows) => rows
+                .into_typed::<DeviceSession>()
+                .collect::<Result<Vec<_>, _>>()?,
+            _ => vec![],
+        };
+        sessions.sort_by_key(|s| std::cmp::Reverse(s.last_seen_at.0));
+
+        Ok(sessions)
+    }
+
+    async fn insert_session(&self, session: &DeviceSession) -> Result<()> {
+        let prepared = self
+            .session
+            .prepare(format!(
+                "INSERT INTO ks.d ({}) VALUES (?, ?, ?, ?, ?, ?)",
+                SESSION_COLUMNS
+            ))
+            .await?;
+
+        self.session
+            .execute(
+                &prepared,
+                (
+                    session.id,
+                    session.user.as_str(),
+                    session.device.as_str(),
+                    session.ip.as_deref(),
+                    session.created_at,
+                    session.last_seen_at,
+                ),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_session(&self, session: &DeviceSession) -> Result<()> {
+        let prepared = self
+            .session
+            .prepare("DELETE FROM ks.d WHERE id = ?")
+            .await?;
+
+        self.session.execute(&prepared, (session.id,)).await?;
+
+        Ok(())
+    }
+}
+
+const PERSONAL_TOKEN_COLUMNS: &str =
+    "hash, id, tid, name, scope, channel, created_at, expires_at, last_used_at";
+
+#[async_trait]
+impl PersonalTokenStore for Scylla {
+    async fn find_personal_token(&self, hash: &str) -> Result<Option<PersonalToken>> {
+        let q = format!("SELECT {} FROM ks.t WHERE hash = ?", PERSONAL_TOKEN_COLUMNS);
+        match self.session.query(q, (hash,)).await?.rows {
+            Some(rows) => Ok(rows.into_typed::<PersonalToken>().next().transpose()?),
+            _ => Ok(None),
+        }
+    }
+
+    async fn find_personal_tokens_by_user(&self, id: &str) -> Result<Vec<PersonalToken>> {
+        let q = format!("SELECT {} FROM ks.t WHERE id = ?", PERSONAL_TOKEN_COLUMNS);
+        let mut tokens = match self.session.query(q, (id,)).await?.rows {
+            Some(rows) => rows
+                .into_typed::<PersonalToken>()
+                .collect::<Result<Vec<_>, _>>()?,
+            _ => vec![],
+        };
+        tokens.sort_by_key(|t| t.created_at.0);
+
+        Ok(tokens)
+    }
+
+    async fn insert_personal_token(&self, token: &PersonalToken) -> Result<()> {
+        // Expired tokens drop out by themselves.
+        let ttl = (token.expires_at.0.num_seconds() - chrono::Utc::now().timestamp()).max(1);
+        let prepared = self
+            .session
+            .prepare(format!(
+                "INSERT INTO ks.t ({}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?) USING TTL ?",
+                PERSONAL_TOKEN_COLUMNS
+            ))
+            .await?;
+
+        self.session
+            .execute(
+                &prepared,
+                (
+                    token.hash.as_str(),
+                    token.id.as_str(),
+                    token.tid,
+                    token.name.as_str(),
+                    token.scope.as_str(),
+                    token.channel.as_deref(),
+                    token.created_at,
+                    token.expires_at,
+                    token.last_used_at,
+                    ttl as i32,
+                ),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_personal_token(&self, token: &PersonalToken) -> Result<()> {
+        let prepared = self
+            .session
+            .prepare("DELETE FROM ks.t WHERE hash = ?")
+            .await?;
+
+        self.session
+            .execute(&prepared, (token.hash.as_str(),))
+            .await?;
+
+        Ok(())
+    }
+}
+
+const INVITE_COLUMNS: &str = "channel, id, created_by, expires_at, single_use";
+
+#[async_trait]
+impl InviteStore for Scylla {
+    async fn find_invite(&self, channel: &str, id: &Uuid) -> Result<Option<Invite>> {
+        let q = format!(
+            "SELECT {} FROM ks.i WHERE channel = ? AND id = ?",
+            INVITE_COLUMNS
+        );
+        match self.session.query(q, (channel, id)).await?.rows {
+            Some(rows) => Ok(rows.into_typed::<Invite>().next().transpose()?),
+            _ => Ok(None),
+        }
+    }
+
+    async fn find_invites_by_channel(&self, channel: &str) -> Result<Vec<Invite>> {
+        let q = format!("SELECT {} FROM ks.i WHERE channel = ?", INVITE_COLUMNS);
+        match self.session.query(q, (channel,)).await?.rows {
+            Some(rows) => Ok(rows.into_typed::<Invite>().collect::<Result<_, _>>()?),
+            _ => Ok(vec![]),
+        }
+    }
+
+    async fn insert_invite(&self, invite: &Invite) -> Result<()> {
+        let prepared = self
+            .session
+            .prepare(format!(
+                "INSERT INTO ks.i ({}) VALUES (?, ?, ?, ?, ?)",
+                INVITE_COLUMNS
+            ))
+            .await?;
+
+        self.session
+            .execute(
+                &prepared,
+                (
+                    invite.channel.as_str(),
+                    invite.id,
+                    invite.created_by.as_str(),
+                    invite.expires_at,
+                    invite.single_use,
+                ),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_invite(&self, invite: &Invite) -> Result<()> {
+        let prepared = self
+            .session
+            .prepare("DELETE FROM ks.i WHERE channel = ? AND id = ?")
+            .await?;
+
+        self.session
+            .execute(&prepared, (invite.channel.as_str(), invite.id))
+            .await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ScheduleStore for Scylla {
+    async fn find_sched_by_channel(
+        &self,
+        channel: &str,
+        filter: &SchedFilter,
+    ) -> Result<SchedPage> {
+        let mut q = format!(
+            "SELECT {} FROM ks.s WHERE channel = ? AND date_at >= ?",
+            SCHED_COLUMNS
+        );
+        let mut values = SerializedValues::new();
+        values.add_value(&channel)?;
+        values.add_value(&filter.start())?;
+
+        if let Some(to) = filter.to {
+            q.push_str(" AND date_at <= ?");
+            values.add_value(&to)?;
+        }
+        if let Some(user) = &filter.user {
+            q.push_str(" AND id = ?");
+            values.add_value(user)?;
+        }
+        // `id` follows the `date_at` range in the clustering key, so it can
+        // only be filtered, which stays within the channel partition.
+        if filter.user.is_some() {
+            q.push_str(" ALLOW FILTERING");
+        }
+
+        let mut prepared = self.session.prepare(q).await?;
+        prepared.set_page_size(filter.page_size());
+
+        let paging_state = match &filter.cursor {
+            Some(cursor) => Some(Bytes::from(decode_cursor(cursor)?)),
+            None => None,
+        };
+
+        let res = self
+            .session
+            .execute_paged(&prepared, values, paging_state)
+            .await?;
+
+        Ok(SchedPage {
+            next: res.paging_state.as_deref().map(encode_cursor),
+            scheds: match res.rows {
+                Some(rows) => rows.into_typed::<Sched>().collect::<Result<_, _>>()?,
+                _ => vec![],
+            },
+        })
+    }
+
+    async fn find_recurring_by_channel(&self, channel: &str) -> Result<Vec<Sched>> {
+        let q = format!(
+            "SELECT {} FROM ks.s WHERE channel = ? AND recurring = true ALLOW FILTERING",
+            SCHED_COLUMNS
+        );
+        let prepared = self.session.prepare(q).await?;
+        match self.session.execute(&prepared, (channel,)).await?.rows {
+            Some(rows) => Ok(rows.into_typed::<Sched>().collect::<Result<_, _>>()?),
+            _ => Ok(vec![]),
+        }
+    }
+
+    async fn find_sched_by_id(&self, channel: &str, sid: &Uuid) -> Result<Option<Sched>> {
+        let q = format!(
+            "SELECT {} FROM ks.s WHERE channel = ? AND sid = ?",
+            SCHED_COLUMNS
+        );
+        let prepared = self.session.prepare(q).await?;
+        match self.session.execute(&prepared, (channel, sid)).await?.rows {
+            Some(rows) => Ok(rows.into_typed::<Sched>().next().transpose()?),
+            _ => Ok(None),
+        }
+    }
+
+    async fn insert_sched(&self, sched: &Sched) -> Result<()> {
+        let prepared = self.session.prepare(INSERT_SCHED).await?;
+
+        self.session
+            .execute(&prepared, insert_values(sched))
+            .await?;
+
+        Ok(())
+    }
+
+    /// A delete and an insert of the same key in one batch share a write
+    /// timestamp, and the tombstone wins, so an edit that keeps the key is a
+    /// plain insert.
+    async fn update_sched(&self, prev: &Sched, next: &Sched) -> Result<()> {
+        if same_key(prev, next) {
+            return self.insert_sched(next).await;
+        }
+
+        let mut batch = Batch::default();
+        batch.append_statement(self.session.prepare(DELETE_SCHED).await?);
+        batch.append_statement(self.session.prepare(INSERT_SCHED).await?);
+
+        self.session
+            .batch(&batch, (key_values(prev), insert_values(next)))
+            .await?;
+
+        Ok(())
+    }
+
+    async fn delete_sched(&self, sched: &Sched) -> Result<()> {
+        let prepared = self.session.prepare(DELETE_SCHED).await?;
+
+        self.session.execute(&prepared, key_values(sched)).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,868 @@
+use std::sync::Mutex;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{Duration, NaiveDate, NaiveTime};
+use rusqlite::types::Type;
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use scylla::frame::value::Timestamp;
+use uuid::Uuid;
+
+use super::{
+    ChannelStore, IdentityStore, InviteStore, MemberStore, PersonalTokenStore, RefreshTokenStore,
+    SchedFilter, SchedPage, ScheduleStore, SessionStore, UserStore,
+};
+use crate::{
+    channel::Channel, identity::Identity, invite::Invite, member::Member, pat::PersonalToken,
+    refresh::RefreshToken, sched::Sched, session::Session, user::User,
+};
+
+/// Single file backend mirroring the `ks.u`, `ks.c`, `ks.m`, `ks.i`, `ks.l`,
+/// `ks.r`, `ks.d`, `ks.t` and `ks.s` tables.
+pub struct Sqlite {
+    conn: Mutex<Connection>,
+}
+
+impl Sqlite {
+    pub fn open(path: &str) -> Result<Self> {
+        eprintln!("Opening {}", path);
+
+        Self::with_connection(Connection::open(path)?)
+    }
+
+    pub fn with_connection(conn: Connection) -> Result<Self> {
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS u (id TEXT PRIMARY KEY, channel TEXT NOT NULL);
+            CREATE TABLE IF NOT EXISTS c (channel TEXT PRIMARY KEY, tz TEXT);
+            CREATE TABLE IF NOT EXISTS s (channel TEXT NOT NULL, id TEXT NOT NULL, sched TEXT NOT NULL,
+                date_at TEXT NOT NULL, create_at INTEGER NOT NULL, sid TEXT NOT NULL,
+                PRIMARY KEY (channel, date_at, id, create_at));
+            CREATE INDEX IF NOT EXISTS s_sid ON s (channel, sid);
+            CREATE TABLE IF NOT EXISTS m (id TEXT NOT NULL, channel TEXT NOT NULL,
+                PRIMARY KEY (id, channel));
+            CREATE INDEX IF NOT EXISTS m_channel ON m (channel);
+            CREATE TABLE IF NOT EXISTS i (channel TEXT NOT NULL, id TEXT NOT NULL,
+                created_by TEXT NOT NULL, expires_at INTEGER NOT NULL, single_use INTEGER NOT NULL,
+                PRIMARY KEY (channel, id));
+            CREATE TABLE IF NOT EXISTS l (provider TEXT NOT NULL, subject TEXT NOT NULL,
+                id TEXT NOT NULL, login TEXT NOT NULL, PRIMARY KEY (provider, subject));
+            CREATE INDEX IF NOT EXISTS l_id ON l (id);
+            CREATE TABLE IF NOT EXISTS r (hash TEXT PRIMARY KEY, session TEXT NOT NULL,
+                id TEXT NOT NULL, expires_at INTEGER NOT NULL, used INTEGER NOT NULL);
+            CREATE INDEX IF NOT EXISTS r_session ON r (session);
+            CREATE TABLE IF NOT EXISTS d (id TEXT PRIMARY KEY, user TEXT NOT NULL,
+                device TEXT NOT NULL, ip TEXT, created_at INTEGER NOT NULL,
+                last_seen_at INTEGER NOT NULL);
+            CREATE INDEX IF NOT EXISTS d_user ON d (user);
+            CREATE TABLE IF NOT EXISTS t (hash TEXT PRIMARY KEY, id TEXT NOT NULL,
+                tid TEXT NOT NULL, name TEXT NOT NULL, scope TEXT NOT NULL, channel TEXT,
+                created_at INTEGER NOT NULL, expires_at INTEGER NOT NULL, last_used_at INTEGER);
+            CREATE INDEX IF NOT EXISTS t_id ON t (id);",
+        )?;
+
+        // Adding a column that already exists fails, so errors are ignored.
+        for (table, column) in [
+            ("u", "tz TEXT"),
+            ("c", "feed_token TEXT"),
+            ("c", "name TEXT"),
+            ("c", "archived_at INTEGER"),
+            ("m", "role TEXT"),
+            ("s", "start_time TEXT"),
+            ("s", "end_time TEXT"),
+            ("s", "all_day INTEGER"),
+            ("s", "rrule TEXT"),
+            ("s", "exdates TEXT"),
+            ("s", "uid TEXT"),
+            ("s", "tz TEXT"),
+        ] {
+            let _ = conn.execute(&format!("ALTER TABLE {} ADD COLUMN {}", table, column), []);
+        }
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+}
+
+const SCHED_COLUMNS: &str =
+    "channel, id, sched, date_at, create_at, sid, start_time, end_time, all_day, rrule, exdates, uid, tz";
+
+/// Exception dates are kept as comma separated ISO dates.
+fn exdates_to_sql(exdates: &[NaiveDate]) -> Option<String> {
+    if exdates.is_empty() {
+        return None;
+    }
+    Some(
+        exdates
+            .iter()
+            .map(|d| d.to_string())
+            .collect::<Vec<_>>()
+            .join(","),
+    )
+}
+
+fn exdates_from_sql(exdates: Option<String>) -> rusqlite::Result<Vec<NaiveDate>> {
+    exdates
+        .iter()
+        .flat_map(|s| s.split(','))
+        .map(|d| {
+            d.parse()
+                .map_err(|e| rusqlite::Error::FromSqlConversionFailure(10, Type::Text, Box::new(e)))
+        })
+        .collect()
+}
+
+fn sched_from_row(row: &Row) -> rusqlite::Result<Sched> {
+    let start_time: Option<NaiveTime> = row.get(6)?;
+    Ok(Sched {
+        channel: row.get(0)?,
+        id: row.get(1)?,
+        sched: row.get(2)?,
+        date_at: row.get::<_, NaiveDate>(3)?,
+        create_at: Timestamp(Duration::milliseconds(row.get(4)?)),
+        sid: row
+            .get::<_, String>(5)?
+            .parse()
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(5, Type::Text, Box::new(e)))?,
+        start_time,
+        end_time: row.get(7)?,
+        all_day: row
+            .get::<_, Option<bool>>(8)?
+            .unwrap_or(start_time.is_none()),
+        rrule: row.get(9)?,
+        exdates: exdates_from_sql(row.get(10)?)?,
+        uid: row.get(11)?,
+        tz: row.get(12)?,
+    })
+}
+
+#[async_trait]
+impl UserStore for Sqlite {
+    async fn find_user_by_id(&self, id: &str) -> Result<Option<User>> {
+        let conn = self.conn.lock().unwrap();
+        let user = conn
+            .query_row(
+                "SELECT id, channel, tz FROM u WHERE id = ?1",
+                params![id],
+                |row| {
+                    Ok(User {
+                        id: row.get(0)?,
+                        channel: row.get(1)?,
+                        tz: row.get(2)?,
+                    })
+                },
+            )
+            .optional()?;
+
+        Ok(user)
+    }
+
+    async fn insert_user(&self, user: &User) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO u (id, channel, tz) VALUES (?1, ?2, ?3)",
+            params![user.id, user.channel, user.tz],
+        )?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ChannelStore for Sqlite {
+    async fn find_channel(&self, channel: &str) -> Result<Option<Channel>> {
+        let conn = self.conn.lock().unwrap();
+        let channel = conn
+            .query_row(
+                "SELECT channel, tz, feed_token, name, archived_at FROM c WHERE channel = ?1",
+                params![channel],
+                |row| {
+                    Ok(Channel {
+                        channel: row.get(0)?,
+                        tz: row.get(1)?,
+                        feed_token: row.get(2)?,
+                        name: row.get(3)?,
+                        archived_at: row
+                            .get::<_, Option<i64>>(4)?
+                            .map(|ms| Timestamp(Duration::milliseconds(ms))),
+                    })
+                },
+            )
+            .optional()?;
+
+        Ok(channel)
+    }
+
+    async fn insert_channel(&self, channel: &Channel) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO c (channel, tz, feed_token, name, archived_at)
+            VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                channel.channel,
+                channel.tz,
+                channel.feed_token,
+                channel.name,
+                channel.archived_at.map(|t| t.0.num_milliseconds())
+            ],
+        )?;
+
+        Ok(())
+    }
+}
+
+fn member_from_row(row: &Row) -> rusqlite::Result<Member> {
+    Ok(Member {
+        id: row.get(0)?,
+        channel: row.get(1)?,
+        role: row.get(2)?,
+    })
+}
+
+#[async_trait]
+impl MemberStore for Sqlite {
+    async fn find_member(&self, id: &str, channel: &str) -> Result<Option<Member>> {
+        let conn = self.conn.lock().unwrap();
+        let member = conn
+            .query_row(
+                "SELECT id, channel, role FROM m WHERE id = ?1 AND channel = ?2",
+                params![id, channel],
+                member_from_row,
+            )
+            .optional()?;
+
+        Ok(member)
+    }
+
+    async fn find_members_by_user(&self, id: &str) -> Result<Vec<Member>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt =
+            conn.prepare("SELECT id, channel, role FROM m WHERE id = ?1 ORDER BY channel")?;
+        let members = stmt
+            .query_map(params![id], member_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(members)
+    }
+
+    async fn find_members_by_channel(&self, channel: &str) -> Result<Vec<Member>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt =
+            conn.prepare("SELECT id, channel, role FROM m WHERE channel = ?1 ORDER BY id")?;
+        let members = stmt
+            .query_map(params![channel], member_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(members)
+    }
+
+    async fn insert_member(&self, member: &Member) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO m (id, channel, role) VALUES (?1, ?2, ?3)",
+            params![member.id, member.channel, member.role],
+        )?;
+
+        Ok(())
+    }
+
+    async fn delete_member(&self, member: &Member) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "DELETE FROM m WHERE id = ?1 AND channel = ?2",
+            params![member.id, member.channel],
+        )?;
+
+        Ok(())
+    }
+}
+
+const INVITE_COLUMNS: &str = "channel, id, created_by, expires_at, single_use";
+
+fn invite_from_row(row: &Row) -> rusqlite::Result<Invite> {
+    Ok(Invite {
+        channel: row.get(0)?,
+        id: row
+            .get::<_, String>(1)?
+            .parse()
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(1, Type::Text, Box::new(e)))?,
+        created_by: row.get(2)?,
+        expires_at: Timestamp(Duration::milliseconds(row.get(3)?)),
+        single_use: row.get(4)?,
+    })
+}
+
+#[async_trait]
+impl InviteStore for Sqlite {
+    async fn find_invite(&self, channel: &str, id: &Uuid) -> Result<Option<Invite>> {
+        let conn = self.conn.lock().unwrap();
+        let invite = conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM i WHERE channel = ?1 AND id = ?2",
+                    INVITE_COLUMNS
+                ),
+                params![channel, id.to_string()],
+                invite_from_row,
+            )
+            .optional()?;
+
+        Ok(invite)
+    }
+
+    async fn find_invites_by_channel(&self, channel: &str) -> Result<Vec<Invite>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM i WHERE channel = ?1 ORDER BY expires_at",
+            INVITE_COLUMNS
+        ))?;
+        let invites = stmt
+            .query_map(params![channel], invite_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(invites)
+    }
+
+    async fn insert_invite(&self, invite: &Invite) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            &format!(
+                "INSERT OR REPLACE INTO i ({}) VALUES (?1, ?2, ?3, ?4, ?5)",
+                INVITE_COLUMNS
+            ),
+            params![
+                invite.channel,
+                invite.id.to_string(),
+                invite.created_by,
+                invite.expires_at.0.num_milliseconds(),
+                invite.single_use
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    async fn delete_invite(&self, invite: &Invite) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "DELETE FROM i WHERE channel = ?1 AND id = ?2",
+            params![invite.channel, invite.id.to_string()],
+        )?;
+
+        Ok(())
+    }
+}
+
+fn identity_from_row(row: &Row) -> rusqlite::Result<Identity> {
+    Ok(Identity {
+        provider: row.get(0)?,
+        subject: row.get(1)?,
+        id: row.get(2)?,
+        login: row.get(3)?,
+    })
+}
+
+#[async_trait]
+impl IdentityStore for Sqlite {
+    async fn find_identity(&self, provider: &str, subject: &str) -> Result<Option<Identity>> {
+        let conn = self.conn.lock().unwrap();
+        let identity = conn
+            .query_row(
+                "SELECT provider, subject, id, login FROM l WHERE provider = ?1 AND subject = ?2",
+                params![provider, subject],
+                identity_from_row,
+            )
+            .optional()?;
+
+        Ok(identity)
+    }
+
+    async fn find_identities_by_user(&self, id: &str) -> Result<Vec<Identity>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT provider, subject, id, login FROM l WHERE id = ?1 ORDER BY provider",
+        )?;
+        let identities = stmt
+            .query_map(params![id], identity_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(identities)
+    }
+
+    async fn insert_identity(&self, identity: &Identity) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO l (provider, subject, id, login) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                identity.provider,
+                identity.subject,
+                identity.id,
+                identity.login
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    async fn delete_identity(&self, identity: &Identity) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "DELETE FROM l WHERE provider = ?1 AND subject = ?2",
+            params![identity.provider, identity.subject],
+        )?;
+
+        Ok(())
+    }
+}
+
+fn refresh_token_from_row(row: &Row) -> rusqlite::Result<RefreshToken> {
+    Ok(RefreshToken {
+        hash: row.get(0)?,
+        session: row
+            .get::<_, String>(1)?
+            .parse()
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(1, Type::Text, Box::new(e)))?,
+        id: row.get(2)?,
+        expires_at: Timestamp(Duration::milliseconds(row.get(3)?)),
+        used: row.get(4)?,
+    })
+}
+
+#[async_trait]
+impl RefreshTokenStore for Sqlite {
+    async fn find_refresh_token(&self, hash: &str) -> Result<Option<RefreshToken>> {
+        let conn = self.conn.lock().unwrap();
+        let token = conn
+            .query_row(
+                "SELECT hash, session, id, expires_at, used FROM r WHERE hash = ?1",
+                params![hash],
+                refresh_token_from_row,
+            )
+            .optional()?;
+
+        Ok(token)
+    }
+
+    async fn insert_refresh_token(&self, token: &RefreshToken) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO r (hash, session, id, expires_at, used) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                token.hash,
+                token.session.to_string(),
+                token.id,
+                token.expires_at.0.num_milliseconds(),
+                token.used
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    async fn delete_refresh_tokens_by_session(&self, session: &Uuid) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "DELETE FROM r WHERE session = ?1",
+            params![session.to_string()],
+        )?;
+
+        Ok(())
+    }
+}
+
+const SESSION_COLUMNS: &str = "id, user, device, ip, created_at, last_seen_at";
+
+fn session_from_row(row: &Row) -> rusqlite::Result<Session> {
+    Ok(Session {
+        id: row
+            .get::<_, String>(0)?
+            .parse()
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(e)))?,
+        user: row.get(1)?,
+        device: row.get(2)?,
+        ip: row.get(3)?,
+        created_at: Timestamp(Duration::milliseconds(row.get(4)?)),
+        last_seen_at: Timestamp(Duration::milliseconds(row.get(5)?)),
+    })
+}
+
+#[async_trait]
+impl SessionStore for Sqlite {
+    async fn find_session(&self, id: &Uuid) -> Result<Option<Session>> {
+        let conn = self.conn.lock().unwrap();
+        let session = conn
+            .query_row(
+                &format!("SELECT {} FROM d WHERE id = ?1", SESSION_COLUMNS),
+                params![id.to_string()],
+                session_from_row,
+            )
+            .optional()?;
+
+        Ok(session)
+    }
+
+    async fn find_sessions_by_user(&self, user: &str) -> Result<Vec<Session>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM d WHERE user = ?1 ORDER BY last_seen_at DESC",
+            SESSION_COLUMNS
+        ))?;
+        let sessions = stmt
+            .query_map(params![user], session_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(sessions)
+    }
+
+    async fn insert_session(&self, session: &Session) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            &format!(
+                "INSERT OR REPLACE INTO d ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                SESSION_COLUMNS
+            ),
+            params![
+                session.id.to_string(),
+                session.user,
+                session.device,
+                session.ip,
+                session.created_at.0.num_milliseconds(),
+                session.last_seen_at.0.num_milliseconds()
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    async fn delete_session(&self, session: &Session) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "DELETE FROM d WHERE id = ?1",
+            params![session.id.to_string()],
+        )?;
+
+        Ok(())
+    }
+}
+
+const PERSONAL_TOKEN_COLUMNS: &str =
+    "hash, id, tid, name, scope, channel, created_at, expires_at, last_used_at";
+
+fn personal_token_from_row(row: &Row) -> rusqlite::Result<PersonalToken> {
+    Ok(PersonalToken {
+        hash: row.get(0)?,
+        id: row.get(1)?,
+        tid: row
+            .get::<_, String>(2)?
+            .parse()
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(2, Type::Text, Box::new(e)))?,
+        name: row.get(3)?,
+        scope: row.get(4)?,
+        channel: row.get(5)?,
+        created_at: Timestamp(Duration::milliseconds(row.get(6)?)),
+        expires_at: Timestamp(Duration::milliseconds(row.get(7)?)),
+        last_used_at: row
+            .get::<_, Option<i64>>(8)?
+            .map(|ms| Timestamp(Duration::milliseconds(ms))),
+    })
+}
+
+#[async_trait]
+impl PersonalTokenStore for Sqlite {
+    async fn find_personal_token(&self, hash: &str) -> Result<Option<PersonalToken>> {
+        let conn = self.conn.lock().unwrap();
+        let token = conn
+            .query_row(
+                &format!("SELECT {} FROM t WHERE hash = ?1", PERSONAL_TOKEN_COLUMNS),
+                params![hash],
+                personal_token_from_row,
+            )
+            .optional()?;
+
+        Ok(token)
+    }
+
+    async fn find_personal_tokens_by_user(&self, id: &str) -> Result<Vec<PersonalToken>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM t WHERE id = ?1 ORDER BY created_at",
+            PERSONAL_TOKEN_COLUMNS
+        ))?;
+        let tokens = stmt
+            .query_map(params![id], personal_token_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(tokens)
+    }
+
+    async fn insert_personal_token(&self, token: &PersonalToken) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            &format!(
+                "INSERT OR REPLACE INTO t ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                PERSONAL_TOKEN_COLUMNS
+            ),
+            params![
+                token.hash,
+                token.id,
+                token.tid.to_string(),
+                token.name,
+                token.scope,
+                token.channel,
+                token.created_at.0.num_milliseconds(),
+                token.expires_at.0.num_milliseconds(),
+                token.last_used_at.map(|at| at.0.num_milliseconds())
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    async fn delete_personal_token(&self, token: &PersonalToken) -> Result<()> {
+        self.conn
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM t WHERE hash = ?1", params![token.hash])?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ScheduleStore for Sqlite {
+    async fn find_sched_by_channel(
+        &self,
+        channel: &str,
+        filter: &SchedFilter,
+    ) -> Result<SchedPage> {
+        let offset = filter.offset()?;
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM s
+            WHERE channel = ?1 AND date_at >= ?2 AND (?3 IS NULL OR date_at <= ?3)
+                AND (?4 IS NULL OR id = ?4)
+            ORDER BY date_at, id, create_at LIMIT ?5 OFFSET ?6",
+            SCHED_COLUMNS
+        ))?;
+        let scheds = stmt
+            .query_map(
+                params![
+                    channel,
+                    filter.start(),
+                    filter.to,
+                    filter.user,
+                    filter.page_size(),
+                    offset as i64
+                ],
+                sched_from_row,
+            )?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(SchedPage {
+            next: filter.next_offset(offset, scheds.len()),
+            scheds,
+        })
+    }
+
+    async fn find_recurring_by_channel(&self, channel: &str) -> Result<Vec<Sched>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM s WHERE channel = ?1 AND rrule IS NOT NULL",
+            SCHED_COLUMNS
+        ))?;
+        let scheds = stmt
+            .query_map(params![channel], sched_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        Ok(scheds)
+    }
+
+    async fn find_sched_by_id(&self, channel: &str, sid: &Uuid) -> Result<Option<Sched>> {
+        let conn = self.conn.lock().unwrap();
+        let sched = conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM s WHERE channel = ?1 AND sid = ?2",
+                    SCHED_COLUMNS
+                ),
+                params![channel, sid.to_string()],
+                sched_from_row,
+            )
+            .optional()?;
+
+        Ok(sched)
+    }
+
+    async fn insert_sched(&self, sched: &Sched) -> Result<()> {
+        insert(&self.conn.lock().unwrap(), sched)
+    }
+
+    async fn update_sched(&self, prev: &Sched, next: &Sched) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        delete(&tx, prev)?;
+        insert(&tx, next)?;
+
+        Ok(tx.commit()?)
+    }
+
+    async fn delete_sched(&self, sched: &Sched) -> Result<()> {
+        delete(&self.conn.lock().unwrap(), sched)
+    }
+}
+
+fn insert(conn: &Connection, sched: &Sched) -> Result<()> {
+    conn.execute(
+        &format!(
+            "INSERT OR REPLACE INTO s ({}) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            SCHED_COLUMNS
+        ),
+        params![
+            sched.channel,
+            sched.id,
+            sched.sched,
+            sched.date_at,
+            sched.create_at.0.num_milliseconds(),
+            sched.sid.to_string(),
+            sched.start_time,
+            sched.end_time,
+            sched.all_day,
+            sched.rrule,
+            exdates_to_sql(&sched.exdates),
+            sched.uid,
+            sched.tz
+        ],
+    )?;
+
+    Ok(())
+}
+
+fn delete(conn: &Connection, sched: &Sched) -> Result<()> {
+    conn.execute(
+        "DELETE FROM s WHERE channel = ?1 AND date_at = ?2 AND id = ?3 AND create_at = ?4",
+        params![
+            sched.channel,
+            sched.date_at,
+            sched.id,
+            sched.create_at.0.num_milliseconds()
+        ],
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sched_roundtrip() {
+        let store = Sqlite::with_connection(Connection::open_in_memory().unwrap()).unwrap();
+        let sched = Sched::new("home", "21kyu", "봄소풍", Utc::now().date_naive());
+
+        store.insert_sched(&sched).await.unwrap();
+
+        let filter = SchedFilter::default();
+        let page = store.find_sched_by_channel("home", &filter).await.unwrap();
+        assert_eq!(page.scheds, vec![sched]);
+        assert!(store
+            .find_sched_by_channel("work", &filter)
+            .await
+            .unwrap()
+            .scheds
+            .is_empty());
+
+        let filter = SchedFilter {
+            user: Some("csj200045".to_owned()),
+            ..Default::default()
+        };
+        assert!(store
+            .find_sched_by_channel("home", &filter)
+            .await
+            .unwrap()
+            .scheds
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_update_sched() {
+        let store = Sqlite::with_connection(Connection::open_in_memory().unwrap()).unwrap();
+        let today = Utc::now().date_naive();
+        let prev = Sched::new("home", "21kyu", "봄소풍", today);
+
+        store.insert_sched(&prev).await.unwrap();
+
+        let renamed = Sched {
+            sched: "가을소풍".to_owned(),
+            ..prev.clone()
+        };
+        store.update_sched(&prev, &renamed).await.unwrap();
+        let filter = SchedFilter::default();
+        let page = store.find_sched_by_channel("home", &filter).await.unwrap();
+        assert_eq!(page.scheds, vec![renamed.clone()]);
+
+        let moved = Sched {
+            date_at: today + Duration::days(1),
+            ..renamed.clone()
+        };
+        store.update_sched(&renamed, &moved).await.unwrap();
+        let page = store.find_sched_by_channel("home", &filter).await.unwrap();
+        assert_eq!(page.scheds, vec![moved]);
+    }
+
+    #[tokio::test]
+    async fn test_sched_without_all_day() {
+        let store = Sqlite::with_connection(Connection::open_in_memory().unwrap()).unwrap();
+        let mut sched = Sched::new("home", "21kyu", "회의", Utc::now().date_naive());
+        sched.start_time = NaiveTime::from_hms_opt(1, 30, 0);
+        sched.all_day = false;
+
+        store.insert_sched(&sched).await.unwrap();
+        // Rows written before the column existed have no all-day flag.
+        store
+            .conn
+            .lock()
+            .unwrap()
+            .execute("UPDATE s SET all_day = NULL", [])
+            .unwrap();
+
+        let found = store.find_sched_by_id("home", &sched.sid).await.unwrap();
+        assert_eq!(found, Some(sched));
+    }
+
+    #[tokio::test]
+    async fn test_recurring_roundtrip() {
+        let store = Sqlite::with_connection(Connection::open_in_memory().unwrap()).unwrap();
+        let today = Utc::now().date_naive();
+        let mut series = Sched::new("home", "21kyu", "스터디", today);
+        series.rrule = Some("FREQ=WEEKLY;INTERVAL=2".to_owned());
+        series.exdates = vec![today + Duration::days(14), today + Duration::days(28)];
+
+        store.insert_sched(&series).await.unwrap();
+        store
+            .insert_sched(&Sched::new("home", "csj200045", "봄소풍", today))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.find_recurring_by_channel("home").await.unwrap(),
+            vec![series]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_member_roundtrip() {
+        let store = Sqlite::with_connection(Connection::open_in_memory().unwrap()).unwrap();
+        let member =
+            |id: &str, channel: &str| Member::new(id, channel, crate::member::Role::Editor);
+
+        store.insert_member(&member("21kyu", "home")).await.unwrap();
+        store.insert_member(&member("21kyu", "work")).await.unwrap();
+        store
+            .insert_member(&member("csj200045", "home"))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.find_members_by_user("21kyu").await.unwrap(),
+            vec![member("21kyu", "home"), member("21kyu", "work")]
+        );
+        assert_eq!(
+            store.find_members_by_channel("home").await.unwrap().len(),
+            2
+        );
+
+        store.delete_member(&member("21kyu", "work")).await.unwrap();
+        assert!(store.find_member("21kyu", "work").await.unwrap().is_none());
+    }
+}
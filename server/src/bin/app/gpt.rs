@@ -1,111 +1,486 @@
 use anyhow::{anyhow, Result};
+use chrono::{NaiveDate, NaiveTime};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Message {
-    role: String,
-    content: String,
-}
+use crate::api::SchedPatch;
+use crate::llm::{LanguageModel, Message};
+use crate::recur::RRule;
+use crate::rules;
+use crate::sched::{Sched, SchedTime};
+use crate::tz;
+use crate::user::User;
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Choice {
-    finish_reason: String,
-    message: Message,
+/// The schedule the model extracted from a natural language request.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SchedPayload {
+    pub title: String,
+    pub date: NaiveDate,
+    pub owner: String,
+    pub channel: String,
+    #[serde(default)]
+    pub start_time: Option<NaiveTime>,
+    #[serde(default)]
+    pub end_time: Option<NaiveTime>,
+    #[serde(default)]
+    pub duration: Option<i64>,
+    #[serde(default)]
+    pub all_day: Option<bool>,
+    #[serde(default)]
+    pub rrule: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct OpenAiResponse {
-    id: String,
-    object: String,
-    created: i64,
-    model: String,
-    choices: Vec<Choice>,
+impl SchedPayload {
+    /// Rejects payloads that would write on behalf of someone else or into
+    /// a channel other than the one the user is authenticated for.
+    pub fn validate(&self, user: &User) -> Result<()> {
+        if self.title.trim().is_empty() {
+            return Err(anyhow!("empty schedule title"));
+        }
+        if self.owner != user.id {
+            return Err(anyhow!("owner {} does not match user {}", self.owner, user.id));
+        }
+        if self.channel != user.channel {
+            return Err(anyhow!(
+                "channel {} does not match user channel {}",
+                self.channel,
+                user.channel
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn time(&self) -> Result<SchedTime> {
+        SchedTime::resolve(self.start_time, self.end_time, self.duration, self.all_day)
+    }
+
+    /// The recurrence rule in its stored form, if the schedule repeats.
+    pub fn rrule(&self) -> Result<Option<String>> {
+        match self.rrule.as_deref().map(str::trim) {
+            None | Some("") => Ok(None),
+            Some(rule) => Ok(Some(rule.parse::<RRule>()?.to_string())),
+        }
+    }
 }
 
-pub async fn request_gpt_api(key: &str, query: &str) -> Result<String> {
-    let client = reqwest::Client::new();
-    let resp = client
-        .post("https://api.openai.com/v1/chat/completions")
-        .header("Authorization", format!("Bearer {}", key))
-        .header("Content-Type", "application/json")
-        .body(
-            json!({
-                "model": "gpt-3.5-turbo",
-                "max_tokens": 300,
-                "temperature": 0.2,
-                "messages": [
-                    {
-                        "role": "system",
-                        "content": "Complete the ScyllaDB query that answers the question.
-
-                        Schema:
-                        CREATE TABLE IF NOT EXISTS ks.s (channel text, id text, sched text, date_at date, create_at timestamp, PRIMARY KEY (channel, date_at, id, create_at))
-                        
-                        channel: The name of the channel.
-                        id: The user's name.
-                        sched: What kind of schedule is registered.
-                        date_at: The date at which the schedule will be registered. If there is no specific mention of the year, please specify the current year(2023).
-                        create_at: The time the query is registered, you can use toTimestamp(now()).
-                        
-                        Just give me the string query that was created. You shouldn't output a description or anything else.
-                        
-                        Example answer:
-                        INSERT INTO ks.s (channel, id, sched, date_at, create_at) VALUES (?, ?, ?, ?, ?)"
-                    },
-                    {
-                        "role": "user",
-                        "content": query
-                    }
-                ]
-            })
-            .to_string(),
-        )
-        .send()
-        .await
-        .unwrap();
+/// An existing schedule as the user described it: words of its title, and
+/// its date and start time when mentioned.
+#[derive(Serialize, Deserialize, Debug, Default, Clone, PartialEq)]
+pub struct Target {
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub date: Option<NaiveDate>,
+    #[serde(default)]
+    pub start_time: Option<NaiveTime>,
+}
 
-    println!("resp: {:?}", resp);
+impl Target {
+    /// Words worth matching against schedule titles.
+    pub fn words(&self) -> Vec<String> {
+        self.title
+            .split_whitespace()
+            .map(|word| word.to_lowercase())
+            .filter(|word| !matches!(word.as_str(), "my" | "the" | "a" | "an" | "our" | "일정"))
+            .collect()
+    }
 
-    if resp.status() != 200 {
-        return Err(anyhow!("status code: {}", resp.status()));
+    pub fn is_empty(&self) -> bool {
+        self.words().is_empty() && self.date.is_none() && self.start_time.is_none()
     }
+}
+
+/// A question about the channel's calendar from `from` to `to`, both
+/// included, about one member's schedules or everyone's.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Question {
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    #[serde(default)]
+    pub user: Option<String>,
+    /// Asks when they are free rather than what is on.
+    #[serde(default)]
+    pub free: bool,
+}
+
+/// What a natural language request asks for. Replies without an `intent`
+/// are a schedule to create.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "intent", rename_all = "lowercase")]
+pub enum Intent {
+    Create(SchedPayload),
+    Update {
+        target: Target,
+        #[serde(default)]
+        changes: SchedPatch,
+    },
+    Delete {
+        target: Target,
+    },
+    Query(Question),
+}
+
+/// Asks the model what `query` wants done. `members` are the ids of the
+/// channel's members a question may be about.
+pub async fn extract_intent(
+    model: &dyn LanguageModel,
+    user: &User,
+    members: &[String],
+    tz: Tz,
+    query: &str,
+) -> Result<Intent> {
+    let today = tz::today(tz);
+    let system = format!("Work out whether the user wants to create a new schedule, update an existing one, delete one or ask about the calendar, and answer with a JSON object whose \"intent\" field is \"create\", \"update\", \"delete\" or \"query\".
+
+                        To create, give the schedule in these fields:
+                        title: What kind of schedule is registered.
+                        date: The date of the schedule in YYYY-MM-DD format. Today is {today} in the {tz} time zone. If there is no specific mention of the year, use the current year.
+                        owner: Always \"{owner}\".
+                        channel: Always \"{channel}\".
+                        start_time: The time the schedule starts in HH:MM format in the {tz} time zone, or null if no time is mentioned.
+                        end_time: The time the schedule ends in HH:MM format, or null if not mentioned.
+                        duration: How long the schedule takes in minutes, or null if not mentioned or an end time is given.
+                        all_day: true if no time is mentioned, otherwise false.
+                        rrule: If the schedule repeats, an RFC 5545 recurrence rule without the \"RRULE:\" prefix, otherwise null. Use FREQ (DAILY, WEEKLY, MONTHLY or YEARLY), INTERVAL, BYDAY, BYMONTHDAY, BYMONTH, COUNT and UNTIL only. For example \"every other Tuesday\" is FREQ=WEEKLY;INTERVAL=2;BYDAY=TU and \"the last Friday of every month\" is FREQ=MONTHLY;BYDAY=-1FR. date is then the first occurrence.
+
+                        To update or delete, describe the existing schedule in a \"target\" object with title (the words the user calls it by), date (YYYY-MM-DD, or null if not mentioned) and start_time (HH:MM, or null if not mentioned). To update, also give only what changes in a \"changes\" object, using sched for a new title and date_at, start_time, end_time, duration, all_day and rrule in the formats above.
 
-    let resp = resp.json::<OpenAiResponse>().await.unwrap();
+                        To ask about the calendar, give from and to: the first and last date the question is about in YYYY-MM-DD format, or the coming seven days if no time is mentioned. user: the member the question is about, one of {members} (\"{owner}\" if they ask about themselves), or null for everyone. free: true if they ask when someone is free, otherwise false.
 
-    println!("resp: {:?}", resp);
+                        Just give me the JSON object. You shouldn't output a description or anything else.
 
-    let query = extract_query(&resp);
+                        Example answers:
+                        {{\"intent\": \"create\", \"title\": \"스탠드업\", \"date\": \"{today}\", \"owner\": \"{owner}\", \"channel\": \"{channel}\", \"start_time\": \"10:30\", \"end_time\": null, \"duration\": 15, \"all_day\": false, \"rrule\": \"FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR\"}}
+                        {{\"intent\": \"update\", \"target\": {{\"title\": \"봄소풍\", \"date\": null, \"start_time\": null}}, \"changes\": {{\"date_at\": \"{today}\"}}}}
+                        {{\"intent\": \"delete\", \"target\": {{\"title\": \"dentist\", \"date\": \"{today}\", \"start_time\": null}}}}
+                        {{\"intent\": \"query\", \"from\": \"{today}\", \"to\": \"{today}\", \"user\": null, \"free\": false}}",
+        owner = user.id,
+        channel = user.channel,
+        members = serde_json::json!(members),
+    );
 
-    if query.starts_with("INSERT") {
-        Ok(query)
-    } else {
-        Err(anyhow!("support only INSERT query"))
+    let content = model
+        .complete(&[Message::system(system), Message::user(query)])
+        .await?;
+
+    parse_intent(&content)
+}
+
+/// Reads what `query` wants done with the model, or with the built-in rules
+/// when there is no model or it fails.
+pub async fn read_intent(
+    model: Option<&dyn LanguageModel>,
+    user: &User,
+    members: &[String],
+    tz: Tz,
+    query: &str,
+) -> Result<Intent> {
+    if let Some(model) = model {
+        match extract_intent(model, user, members, tz, query).await {
+            Ok(intent) => return Ok(intent),
+            Err(err) => println!("err: {:?}, falling back to rules", err),
+        }
     }
+    rules::extract_intent(user, members, tz::today(tz), query)
 }
 
-fn extract_query(resp: &OpenAiResponse) -> String {
-    let mut query = String::new();
+/// Asks the model to answer `query` from the schedules it is about.
+pub async fn answer_question(
+    model: &dyn LanguageModel,
+    tz: Tz,
+    query: &str,
+    question: &Question,
+    scheds: &[Sched],
+) -> Result<String> {
+    let today = tz::today(tz);
+    let scheds = scheds
+        .iter()
+        .map(|sched| {
+            serde_json::json!({
+                "title": sched.sched,
+                "owner": sched.id,
+                "date": sched.date_at,
+                "start_time": sched.start_time,
+                "end_time": sched.end_time,
+                "all_day": sched.all_day,
+            })
+            .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let system = format!("Answer the user's question about their team calendar in the language of the question, in a few short sentences.
+
+                        Today is {today} in the {tz} time zone. The question is about {from} to {to}{who}. These are all the schedules then, one JSON object per line:
+                        {scheds}
 
-    for choice in &resp.choices {
-        if choice.finish_reason == "stop" {
-            query = choice.message.content.to_owned();
-            break;
+                        Use only these schedules. A day or time without schedules is free. Give dates and times, not JSON.",
+        from = question.from,
+        to = question.to,
+        who = question
+            .user
+            .as_ref()
+            .map(|user| format!(" for {}", user))
+            .unwrap_or_default(),
+    );
+
+    let content = model
+        .complete(&[Message::system(system), Message::user(query)])
+        .await?;
+    match content.trim() {
+        "" => Err(anyhow!("empty answer")),
+        answer => Ok(answer.to_owned()),
+    }
+}
+
+/// Answers `query` with the model, or with a template when there is no
+/// model or it fails.
+pub async fn read_answer(
+    model: Option<&dyn LanguageModel>,
+    tz: Tz,
+    query: &str,
+    question: &Question,
+    scheds: &[Sched],
+) -> String {
+    if let Some(model) = model {
+        match answer_question(model, tz, query, question, scheds).await {
+            Ok(answer) => return answer,
+            Err(err) => println!("err: {:?}, falling back to a template", err),
         }
     }
+    rules::answer(query, question, scheds)
+}
+
+/// The JSON object in the model's answer, which may be wrapped in prose or
+/// a code block.
+fn json_object(content: &str) -> Result<serde_json::Value> {
+    let start = content.find('{');
+    let end = content.rfind('}');
 
-    query
+    match (start, end) {
+        (Some(start), Some(end)) if start < end => serde_json::from_str(&content[start..=end])
+            .map_err(|e| anyhow!("invalid schedule payload: {}", e)),
+        _ => Err(anyhow!("no schedule payload in response")),
+    }
+}
+
+fn parse_intent(content: &str) -> Result<Intent> {
+    let mut object = json_object(content)?;
+    if let Some(object) = object.as_object_mut() {
+        object.entry("intent").or_insert_with(|| "create".into());
+    }
+    serde_json::from_value(object).map_err(|e| anyhow!("invalid schedule payload: {}", e))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::llm::Fake;
+
+    fn user() -> User {
+        User {
+            id: "21kyu".to_owned(),
+            channel: "home".to_owned(),
+            tz: None,
+        }
+    }
+
+    fn parse_payload(content: &str) -> Result<SchedPayload> {
+        match parse_intent(content)? {
+            Intent::Create(payload) => Ok(payload),
+            intent => Err(anyhow!("not a new schedule: {:?}", intent)),
+        }
+    }
+
+    #[test]
+    fn test_parse_payload() {
+        let content = "```json\n{\"title\": \"봄소풍\", \"date\": \"2023-06-30\", \"owner\": \"21kyu\", \"channel\": \"home\"}\n```";
+
+        let payload = parse_payload(content).unwrap();
+
+        assert_eq!(payload.title, "봄소풍");
+        assert_eq!(payload.date, NaiveDate::from_ymd_opt(2023, 6, 30).unwrap());
+        assert!(payload.validate(&user()).is_ok());
+        assert!(payload.time().unwrap().all_day);
+    }
+
+    #[test]
+    fn test_parse_payload_with_time() {
+        let content = "{\"title\": \"스탠드업\", \"date\": \"2023-06-30\", \"owner\": \"21kyu\", \"channel\": \"home\", \"start_time\": \"10:30\", \"end_time\": null, \"duration\": 15, \"all_day\": false}";
+
+        let time = parse_payload(content).unwrap().time().unwrap();
+
+        assert_eq!(time.start, NaiveTime::from_hms_opt(10, 30, 0));
+        assert_eq!(time.end, NaiveTime::from_hms_opt(10, 45, 0));
+    }
+
+    #[test]
+    fn test_parse_payload_with_rrule() {
+        let content = "{\"title\": \"스터디\", \"date\": \"2023-07-04\", \"owner\": \"21kyu\", \"channel\": \"home\", \"rrule\": \"RRULE:FREQ=WEEKLY;INTERVAL=2;BYDAY=TU\"}";
+
+        let payload = parse_payload(content).unwrap();
+
+        assert_eq!(
+            payload.rrule().unwrap().as_deref(),
+            Some("FREQ=WEEKLY;INTERVAL=2;BYDAY=TU")
+        );
+
+        let payload = SchedPayload {
+            rrule: Some("FREQ=SECONDLY".to_owned()),
+            ..payload
+        };
+        assert!(payload.rrule().is_err());
+    }
+
+    #[test]
+    fn test_parse_intent() {
+        let content = "{\"intent\": \"update\", \"target\": {\"title\": \"봄소풍\", \"date\": null, \"start_time\": null}, \"changes\": {\"date_at\": \"2023-07-02\"}}";
+
+        assert_eq!(
+            parse_intent(content).unwrap(),
+            Intent::Update {
+                target: Target {
+                    title: "봄소풍".to_owned(),
+                    ..Target::default()
+                },
+                changes: SchedPatch {
+                    date_at: NaiveDate::from_ymd_opt(2023, 7, 2),
+                    ..SchedPatch::default()
+                },
+            }
+        );
+
+        let content = "{\"intent\": \"delete\", \"target\": {\"title\": \"my dentist\", \"date\": \"2023-06-30\"}}";
+
+        match parse_intent(content).unwrap() {
+            Intent::Delete { target } => {
+                assert_eq!(target.words(), ["dentist"]);
+                assert_eq!(target.date, NaiveDate::from_ymd_opt(2023, 6, 30));
+            }
+            intent => panic!("unexpected intent {:?}", intent),
+        }
+
+        assert!(parse_intent("{\"intent\": \"drop\", \"target\": {}}").is_err());
+
+        let content = "{\"intent\": \"query\", \"from\": \"2023-07-01\", \"to\": \"2023-07-31\", \"user\": \"21kyu\", \"free\": true}";
+
+        assert_eq!(
+            parse_intent(content).unwrap(),
+            Intent::Query(Question {
+                from: NaiveDate::from_ymd_opt(2023, 7, 1).unwrap(),
+                to: NaiveDate::from_ymd_opt(2023, 7, 31).unwrap(),
+                user: Some("21kyu".to_owned()),
+                free: true,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_extract_intent() {
+        let model = Fake::new(&["{\"title\": \"봄소풍\", \"date\": \"2023-06-30\", \"owner\": \"21kyu\", \"channel\": \"home\"}"]);
+
+        let intent = extract_intent(
+            &model,
+            &user(),
+            &[],
+            chrono_tz::Asia::Seoul,
+            "6월 30일 봄소풍",
+        )
+        .await
+        .unwrap();
+        assert!(matches!(intent, Intent::Create(payload) if payload.title == "봄소풍"));
+
+        {
+            let requests = model.requests.lock().unwrap();
+            let messages = &requests[0];
+            assert!(messages[0].content.contains("Always \"21kyu\""));
+            assert!(messages[0].content.contains("Asia/Seoul"));
+            assert_eq!(messages[1], Message::user("6월 30일 봄소풍"));
+        }
+
+        assert!(
+            extract_intent(&model, &user(), &[], chrono_tz::Asia::Seoul, "내일")
+                .await
+                .is_err()
+        );
+    }
 
     #[tokio::test]
-    async fn test_request_gpt_api() {
-        let key = "open ai key";
-        let query = "6월 30일에 봄소풍이라는 일정을 등록해주세요. (channel: home, id: 21kyu)";
+    async fn test_read_answer() {
+        let date = NaiveDate::from_ymd_opt(2023, 7, 4).unwrap();
+        let scheds = [Sched::new("home", "21kyu", "치과", date)];
+        let question = Question {
+            from: date,
+            to: date,
+            user: None,
+            free: false,
+        };
+        let model = Fake::new(&["7월 4일에 치과 일정이 있어요.\n"]);
+
+        let answer = read_answer(
+            Some(&model),
+            chrono_tz::Asia::Seoul,
+            "7월 4일에 뭐 있어?",
+            &question,
+            &scheds,
+        )
+        .await;
+        assert_eq!(answer, "7월 4일에 치과 일정이 있어요.");
+
+        {
+            let requests = model.requests.lock().unwrap();
+            assert!(requests[0][0].content.contains("\"title\":\"치과\""));
+        }
+
+        // Out of replies, so the template answers.
+        let answer = read_answer(
+            Some(&model),
+            chrono_tz::Asia::Seoul,
+            "7월 4일에 뭐 있어?",
+            &question,
+            &scheds,
+        )
+        .await;
+        assert_eq!(answer, "7월 4일 일정 1개: 7월 4일 치과");
+    }
+
+    #[test]
+    fn test_parse_payload_rejects_cql() {
+        let content = "INSERT INTO ks.u (id, channel) VALUES ('21kyu', 'admin')";
+
+        assert!(parse_payload(content).is_err());
+    }
+
+    #[test]
+    fn test_validate_rejects_foreign_channel() {
+        let payload = SchedPayload {
+            title: "봄소풍".to_owned(),
+            date: NaiveDate::from_ymd_opt(2023, 6, 30).unwrap(),
+            owner: "21kyu".to_owned(),
+            channel: "other".to_owned(),
+            start_time: None,
+            end_time: None,
+            duration: None,
+            all_day: None,
+            rrule: None,
+        };
+
+        assert!(payload.validate(&user()).is_err());
+    }
+
+    #[test]
+    fn test_validate_rejects_foreign_owner() {
+        let payload = SchedPayload {
+            title: "봄소풍".to_owned(),
+            date: NaiveDate::from_ymd_opt(2023, 6, 30).unwrap(),
+            owner: "csj200045".to_owned(),
+            channel: "home".to_owned(),
+            start_time: None,
+            end_time: None,
+            duration: None,
+            all_day: None,
+            rrule: None,
+        };
 
-        let _ = request_gpt_api(key, query).await;
+        assert!(payload.validate(&user()).is_err());
     }
 }
@@ -0,0 +1,651 @@
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone};
+use chrono_tz::Tz;
+use scylla::frame::value::Timestamp;
+use uuid::Uuid;
+
+use crate::recur::RRule;
+use crate::sched::Sched;
+use crate::tz;
+
+const PRODID: &str = "-//sched-bird//sched-bird//EN";
+/// Content lines longer than this many octets are folded (RFC 5545 3.1).
+const MAX_LINE_OCTETS: usize = 75;
+
+/// Escapes TEXT values (RFC 5545 3.3.11).
+pub fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub fn unescape_text(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match (c, c == '\\') {
+            (_, true) => match chars.next() {
+                Some('n') | Some('N') => unescaped.push('\n'),
+                Some(c) => unescaped.push(c),
+                None => unescaped.push('\\'),
+            },
+            (c, false) => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// The UID a schedule is known by to calendar clients.
+pub fn uid(sched: &Sched) -> String {
+    sched.uid.clone().unwrap_or_else(|| sched.sid.to_string())
+}
+
+/// The `sid` an imported event is stored under. UIDs sched-bird handed out
+/// are sids already, others map to the same name-based uuid every time, so
+/// importing an event twice finds the first copy.
+pub fn import_sid(uid: &str) -> Uuid {
+    uid.parse()
+        .unwrap_or_else(|_| Uuid::new_v5(&Uuid::NAMESPACE_URL, uid.as_bytes()))
+}
+
+/// Writes a content line, folding it without splitting UTF-8 characters.
+fn push_line(out: &mut String, line: &str) {
+    let mut len = 0;
+    for c in line.chars() {
+        if len + c.len_utf8() > MAX_LINE_OCTETS {
+            out.push_str("\r\n ");
+            len = 1;
+        }
+        out.push(c);
+        len += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+fn format_date(date: NaiveDate) -> String {
+    date.format("%Y%m%d").to_string()
+}
+
+fn format_utc(dt: NaiveDateTime) -> String {
+    dt.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+fn format_local(dt: NaiveDateTime) -> String {
+    dt.format("%Y%m%dT%H%M%S").to_string()
+}
+
+fn timestamp(ts: Timestamp) -> NaiveDateTime {
+    DateTime::from_timestamp_millis(ts.0.num_milliseconds())
+        .map(|dt| dt.naive_utc())
+        .unwrap_or_default()
+}
+
+/// Start and end of a timed schedule, with an end before the start taken to
+/// be on the next day.
+fn span(sched: &Sched, start: NaiveTime) -> (NaiveDateTime, Option<NaiveDateTime>) {
+    let start = sched.date_at.and_time(start);
+    let end = sched.end_time.map(|end| {
+        let end = sched.date_at.and_time(end);
+        if end < start {
+            end + Duration::days(1)
+        } else {
+            end
+        }
+    });
+    (start, end)
+}
+
+/// Writes one VEVENT for a stored schedule. Single events are written in
+/// UTC. Series are written in wall-clock time of their own zone, or `tz`,
+/// as their rule repeats on local weekdays and dates.
+fn push_event(out: &mut String, sched: &Sched, tz: Tz) {
+    let tz = tz::zone(sched, tz);
+    push_line(out, "BEGIN:VEVENT");
+    push_line(out, &format!("UID:{}", escape_text(&uid(sched))));
+    push_line(
+        out,
+        &format!("DTSTAMP:{}", format_utc(timestamp(sched.create_at))),
+    );
+    push_line(
+        out,
+        &format!("CREATED:{}", format_utc(timestamp(sched.create_at))),
+    );
+    push_line(out, &format!("SUMMARY:{}", escape_text(&sched.sched)));
+    push_line(
+        out,
+        &format!("X-SCHED-BIRD-OWNER:{}", escape_text(&sched.id)),
+    );
+
+    match (sched.all_day, sched.start_time) {
+        (false, Some(start)) if sched.rrule.is_some() && tz != Tz::UTC => {
+            let local = tz::to_local(sched, tz);
+            let (start, end) = span(&local, local.start_time.unwrap_or(start));
+            push_line(
+                out,
+                &format!("DTSTART;TZID={}:{}", tz.name(), format_local(start)),
+            );
+            if let Some(end) = end {
+                push_line(
+                    out,
+                    &format!("DTEND;TZID={}:{}", tz.name(), format_local(end)),
+                );
+            }
+            if !local.exdates.is_empty() {
+                let exdates = local
+                    .exdates
+                    .iter()
+                    .map(|d| format_local(d.and_time(start.time())))
+                    .collect::<Vec<_>>();
+                push_line(
+                    out,
+                    &format!("EXDATE;TZID={}:{}", tz.name(), exdates.join(",")),
+                );
+            }
+        }
+        (false, Some(start)) => {
+            let (start, end) = span(sched, start);
+            push_line(out, &format!("DTSTART:{}", format_utc(start)));
+            if let Some(end) = end {
+                push_line(out, &format!("DTEND:{}", format_utc(end)));
+            }
+            if !sched.exdates.is_empty() {
+                let exdates = sched
+                    .exdates
+                    .iter()
+                    .map(|d| format_utc(d.and_time(start.time())))
+                    .collect::<Vec<_>>();
+                push_line(out, &format!("EXDATE:{}", exdates.join(",")));
+            }
+        }
+        _ => {
+            push_line(
+                out,
+                &format!("DTSTART;VALUE=DATE:{}", format_date(sched.date_at)),
+            );
+            push_line(
+                out,
+                &format!(
+                    "DTEND;VALUE=DATE:{}",
+                    format_date(sched.date_at + Duration::days(1))
+                ),
+            );
+            if !sched.exdates.is_empty() {
+                let exdates = sched
+                    .exdates
+                    .iter()
+                    .map(|d| format_date(*d))
+                    .collect::<Vec<_>>();
+                push_line(out, &format!("EXDATE;VALUE=DATE:{}", exdates.join(",")));
+            }
+        }
+    }
+
+    if let Some(rrule) = &sched.rrule {
+        push_line(out, &format!("RRULE:{}", rrule));
+    }
+
+    push_line(out, "END:VEVENT");
+}
+
+/// Writes stored schedules of a channel as a VCALENDAR.
+pub fn calendar(channel: &str, scheds: &[Sched], tz: Tz) -> String {
+    let mut out = String::new();
+
+    push_line(&mut out, "BEGIN:VCALENDAR");
+    push_line(&mut out, "VERSION:2.0");
+    push_line(&mut out, &format!("PRODID:{}", PRODID));
+    push_line(&mut out, "CALSCALE:GREGORIAN");
+    push_line(&mut out, &format!("X-WR-CALNAME:{}", escape_text(channel)));
+    push_line(&mut out, &format!("X-WR-TIMEZONE:{}", tz.name()));
+
+    for sched in scheds {
+        push_event(&mut out, sched, tz);
+    }
+
+    push_line(&mut out, "END:VCALENDAR");
+
+    out
+}
+
+/// A content line, `NAME;PARAM=VALUE:value`, with the value still escaped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Property {
+    pub name: String,
+    pub params: Vec<(String, String)>,
+    pub value: String,
+}
+
+impl Property {
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Joins folded lines back together. Both CRLF and bare LF line ends are
+/// accepted, clients aren't strict about it.
+fn unfold(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+    for line in text.split('\n') {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ if line.is_empty() => {}
+            _ => lines.push(line.to_owned()),
+        }
+    }
+    lines
+}
+
+fn parse_property(line: &str) -> Result<Property> {
+    let mut parts = vec![];
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut value = None;
+
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => parts.push(std::mem::take(&mut current)),
+            ':' if !quoted => {
+                parts.push(std::mem::take(&mut current));
+                value = Some(&line[i + 1..]);
+                break;
+            }
+            c => current.push(c),
+        }
+    }
+
+    let value = value.ok_or_else(|| anyhow!("invalid content line {}", line))?;
+    let mut parts = parts.into_iter();
+    let name = parts.next().unwrap_or_default().to_ascii_uppercase();
+    let params = parts
+        .filter_map(|p| {
+            p.split_once('=')
+                .map(|(k, v)| (k.to_ascii_uppercase(), v.to_owned()))
+        })
+        .collect();
+
+    Ok(Property {
+        name,
+        params,
+        value: value.to_owned(),
+    })
+}
+
+/// Returns the properties of each top level component named `name`, like
+/// the VEVENTs of a VCALENDAR. Nested components such as VALARM are skipped.
+pub fn parse_components(text: &str, name: &str) -> Result<Vec<Vec<Property>>> {
+    let mut components = vec![];
+    let mut current: Option<Vec<Property>> = None;
+    let mut depth = 0;
+
+    for line in unfold(text) {
+        let prop = parse_property(&line)?;
+        match prop.name.as_str() {
+            "BEGIN" => {
+                depth += 1;
+                if prop.value.eq_ignore_ascii_case(name) && current.is_none() {
+                    current = Some(vec![]);
+                    depth = 1;
+                }
+            }
+            "END" => {
+                depth -= 1;
+                if depth == 0 && prop.value.eq_ignore_ascii_case(name) {
+                    components.extend(current.take());
+                }
+            }
+            _ => {
+                if let (Some(props), 1) = (current.as_mut(), depth) {
+                    props.push(prop);
+                }
+            }
+        }
+    }
+
+    if current.is_some() {
+        return Err(anyhow!("unterminated {}", name));
+    }
+
+    Ok(components)
+}
+
+/// A DATE or DATE-TIME value, the latter in wall-clock time of the zone the
+/// calendar is imported in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum When {
+    Date(NaiveDate),
+    DateTime(NaiveDateTime),
+}
+
+fn parse_when(value: &str, prop: &Property, tz: Tz) -> Result<When> {
+    let value = value.trim();
+    if prop.param("VALUE") == Some("DATE") || value.len() == 8 {
+        return NaiveDate::parse_from_str(value, "%Y%m%d")
+            .map(When::Date)
+            .map_err(|_| anyhow!("invalid date {}", value));
+    }
+
+    let (local, utc) = match value.strip_suffix('Z') {
+        Some(value) => (value, true),
+        None => (value, false),
+    };
+    let dt = NaiveDateTime::parse_from_str(local, "%Y%m%dT%H%M%S")
+        .map_err(|_| anyhow!("invalid date-time {}", value))?;
+
+    let utc = match (utc, prop.param("TZID")) {
+        (true, _) => dt,
+        (false, Some(tzid)) => tz::local_to_utc(tz::parse_tz(tzid.trim_matches('"'))?, dt),
+        // Floating times are taken as wall-clock time wherever they're read.
+        (false, None) => return Ok(When::DateTime(dt)),
+    };
+
+    Ok(When::DateTime(tz.from_utc_datetime(&utc).naive_local()))
+}
+
+/// Parses `DURATION` values like `PT1H30M`, `P1D` or `P2W`.
+fn parse_duration(value: &str) -> Result<Duration> {
+    let invalid = || anyhow!("invalid duration {}", value);
+    let (negative, rest) = match value.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, value.strip_prefix('+').unwrap_or(value)),
+    };
+    let rest = rest.strip_prefix('P').ok_or_else(invalid)?;
+
+    let mut total = Duration::zero();
+    let mut number = String::new();
+    for c in rest.chars() {
+        match c {
+            '0'..='9' => number.push(c),
+            'T' => {}
+            'W' | 'D' | 'H' | 'M' | 'S' => {
+                let n = number.parse::<i64>().map_err(|_| invalid())?;
+                number.clear();
+                let part = match c {
+                    'W' => Duration::try_weeks(n),
+                    'D' => Duration::try_days(n),
+                    'H' => Duration::try_hours(n),
+                    'M' => Duration::try_minutes(n),
+                    _ => Duration::try_seconds(n),
+                };
+                total = part
+                    .and_then(|part| total.checked_add(&part))
+                    .ok_or_else(invalid)?;
+            }
+            _ => return Err(invalid()),
+        }
+    }
+    if !number.is_empty() {
+        return Err(invalid());
+    }
+
+    Ok(if negative { -total } else { total })
+}
+
+/// Maps the properties of a VEVENT to a schedule in wall-clock time of
+/// `tz`, or for a series of the zone its start names, which it keeps.
+/// Multi-day events and overridden occurrences have no counterpart and are
+/// rejected.
+pub fn sched_from_event(props: &[Property], channel: &str, owner: &str, tz: Tz) -> Result<Sched> {
+    let find = |name: &str| props.iter().find(|p| p.name == name);
+
+    if find("RECURRENCE-ID").is_some() {
+        return Err(anyhow!("changed occurrences of a series are not supported"));
+    }
+
+    let summary = find("SUMMARY")
+        .map(|p| unescape_text(&p.value))
+        .unwrap_or_default();
+    if summary.trim().is_empty() {
+        return Err(anyhow!("missing SUMMARY"));
+    }
+
+    let dtstart = find("DTSTART").ok_or_else(|| anyhow!("missing DTSTART"))?;
+    // A series repeats on the dates of the zone its start is given in.
+    let tz = match (find("RRULE"), dtstart.param("TZID")) {
+        (Some(_), Some(tzid)) => tz::parse_tz(tzid.trim_matches('"'))?,
+        _ => tz,
+    };
+    let start = parse_when(&dtstart.value, dtstart, tz)?;
+    let end = match (find("DTEND"), find("DURATION")) {
+        (Some(dtend), _) => Some(parse_when(&dtend.value, dtend, tz)?),
+        (None, Some(duration)) => {
+            let duration = parse_duration(&duration.value)?;
+            let out_of_range = || anyhow!("DURATION {} is out of range", duration);
+            Some(match start {
+                When::Date(date) => {
+                    When::Date(date.checked_add_signed(duration).ok_or_else(out_of_range)?)
+                }
+                When::DateTime(dt) => {
+                    When::DateTime(dt.checked_add_signed(duration).ok_or_else(out_of_range)?)
+                }
+            })
+        }
+        (None, None) => None,
+    };
+
+    let date_at = match start {
+        When::Date(date) => date,
+        When::DateTime(dt) => dt.date(),
+    };
+    let mut sched = Sched::new(channel, owner, &summary, date_at);
+
+    match (start, end) {
+        (When::Date(_), None) => {}
+        (When::Date(start), Some(When::Date(end))) => {
+            if end > start + Duration::days(1) {
+                return Err(anyhow!("multi-day events are not supported"));
+            }
+        }
+        (When::DateTime(start), end) => {
+            let end = match end {
+                Some(When::DateTime(end)) => Some(end),
+                Some(When::Date(_)) => return Err(anyhow!("DTEND is a date but DTSTART isn't")),
+                None => None,
+            };
+            if end.is_some_and(|end| end < start || end - start >= Duration::days(1)) {
+                return Err(anyhow!("events must end within a day of their start"));
+            }
+            sched.start_time = Some(start.time());
+            sched.end_time = end.filter(|end| *end > start).map(|end| end.time());
+            sched.all_day = false;
+        }
+        (When::Date(_), Some(When::DateTime(_))) => {
+            return Err(anyhow!("DTEND is a date-time but DTSTART isn't"))
+        }
+    }
+
+    if let Some(rrule) = find("RRULE") {
+        sched.rrule = Some(rrule.value.parse::<RRule>()?.to_string());
+        sched.tz = Some(tz.name().to_owned());
+    }
+    for exdate in props.iter().filter(|p| p.name == "EXDATE") {
+        for value in exdate.value.split(',') {
+            sched.exdates.push(match parse_when(value, exdate, tz)? {
+                When::Date(date) => date,
+                When::DateTime(dt) => dt.date(),
+            });
+        }
+    }
+
+    let uid = match find("UID") {
+        Some(uid) => unescape_text(&uid.value),
+        // Without a UID the event is recognized by what it is.
+        None => format!("{}/{}", summary, dtstart.value),
+    };
+    sched.sid = import_sid(&uid);
+    if sched.sid.to_string() != uid {
+        sched.uid = Some(uid);
+    }
+
+    Ok(sched)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_escape_and_fold() {
+        let title = "아주 긴 일정 제목, 세미콜론; 그리고 줄바꿈\n".repeat(4);
+        let mut out = String::new();
+
+        push_line(&mut out, &format!("SUMMARY:{}", escape_text(&title)));
+
+        assert!(out.split("\r\n").all(|line| line.len() <= MAX_LINE_OCTETS));
+        assert!(out.contains("제목\\, 세미콜론\\; 그리고 줄바꿈\\n"));
+        assert_eq!(
+            out.trim_end().replace("\r\n ", ""),
+            format!("SUMMARY:{}", escape_text(&title))
+        );
+    }
+
+    #[test]
+    fn test_calendar_events() {
+        let seoul = tz::parse_tz("Asia/Seoul").unwrap();
+        let all_day = Sched::new("home", "21kyu", "봄소풍", date(2023, 6, 30));
+        let mut timed = Sched::new("home", "21kyu", "스탠드업", date(2023, 6, 30));
+        timed.start_time = NaiveTime::from_hms_opt(23, 30, 0);
+        timed.end_time = NaiveTime::from_hms_opt(0, 30, 0);
+        timed.all_day = false;
+        let mut series = timed.clone();
+        series.rrule = Some("FREQ=WEEKLY;INTERVAL=2;BYDAY=SA".to_owned());
+
+        let ics = calendar("home", &[all_day.clone(), timed, series], seoul);
+
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 3);
+        assert!(ics.contains(&format!("UID:{}\r\n", all_day.sid)));
+        assert!(ics.contains("DTSTART;VALUE=DATE:20230630\r\nDTEND;VALUE=DATE:20230701\r\n"));
+        assert!(ics.contains("DTSTART:20230630T233000Z\r\nDTEND:20230701T003000Z\r\n"));
+        assert!(ics.contains(
+            "DTSTART;TZID=Asia/Seoul:20230701T083000\r\nDTEND;TZID=Asia/Seoul:20230701T093000\r\n"
+        ));
+        assert!(ics.contains("RRULE:FREQ=WEEKLY;INTERVAL=2;BYDAY=SA\r\n"));
+    }
+
+    const CALENDAR: &str = "BEGIN:VCALENDAR\r
+VERSION:2.0\r
+BEGIN:VTIMEZONE\r
+TZID:Asia/Seoul\r
+BEGIN:STANDARD\r
+TZOFFSETFROM:+0900\r
+TZOFFSETTO:+0900\r
+DTSTART:19700101T000000\r
+END:STANDARD\r
+END:VTIMEZONE\r
+BEGIN:VEVENT\r
+UID:picnic@example.com\r
+SUMMARY:봄소풍\\, 한강\r
+DTSTART;VALUE=DATE:20230630\r
+DTEND;VALUE=DATE:20230701\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:standup@example.com\r
+SUMMARY:스탠\r
+ 드업\r
+DTSTART;TZID=Asia/Seoul:20230703T103000\r
+DURATION:PT15M\r
+RRULE:FREQ=WEEKLY;BYDAY=MO,WE\r
+EXDATE;TZID=Asia/Seoul:20230705T103000\r
+BEGIN:VALARM\r
+ACTION:DISPLAY\r
+DESCRIPTION:알림\r
+END:VALARM\r
+END:VEVENT\r
+BEGIN:VEVENT\r
+UID:trip@example.com\r
+SUMMARY:여행\r
+DTSTART;VALUE=DATE:20230801\r
+DTEND;VALUE=DATE:20230805\r
+END:VEVENT\r
+END:VCALENDAR\r
+";
+
+    #[test]
+    fn test_import_events() {
+        let seoul = tz::parse_tz("Asia/Seoul").unwrap();
+        let events = parse_components(CALENDAR, "VEVENT").unwrap();
+        assert_eq!(events.len(), 3);
+
+        let picnic = sched_from_event(&events[0], "home", "21kyu", seoul).unwrap();
+        assert_eq!(picnic.sched, "봄소풍, 한강");
+        assert_eq!(picnic.date_at, date(2023, 6, 30));
+        assert!(picnic.all_day);
+        assert_eq!(picnic.uid.as_deref(), Some("picnic@example.com"));
+        assert_eq!(picnic.sid, import_sid("picnic@example.com"));
+
+        let standup = sched_from_event(&events[1], "home", "21kyu", seoul).unwrap();
+        assert_eq!(standup.sched, "스탠드업");
+        assert_eq!(standup.date_at, date(2023, 7, 3));
+        assert_eq!(standup.start_time, NaiveTime::from_hms_opt(10, 30, 0));
+        assert_eq!(standup.end_time, NaiveTime::from_hms_opt(10, 45, 0));
+        assert_eq!(standup.rrule.as_deref(), Some("FREQ=WEEKLY;BYDAY=MO,WE"));
+        assert_eq!(standup.exdates, vec![date(2023, 7, 5)]);
+
+        assert_eq!(standup.tz.as_deref(), Some("Asia/Seoul"));
+
+        // The series stays in Seoul, where its weekdays are, for anyone.
+        let utc = sched_from_event(&events[1], "home", "21kyu", Tz::UTC).unwrap();
+        assert_eq!(utc.start_time, NaiveTime::from_hms_opt(10, 30, 0));
+        assert_eq!(
+            tz::to_utc(&utc, Tz::UTC).start_time,
+            NaiveTime::from_hms_opt(1, 30, 0)
+        );
+
+        assert!(sched_from_event(&events[2], "home", "21kyu", seoul).is_err());
+
+        let endless = "BEGIN:VEVENT\r\nSUMMARY:끝없음\r\nDTSTART:20230703T103000Z\r\nDURATION:P100000000D\r\nEND:VEVENT\r\n";
+        let events = parse_components(endless, "VEVENT").unwrap();
+        assert!(sched_from_event(&events[0], "home", "21kyu", seoul).is_err());
+    }
+
+    #[test]
+    fn test_export_import_roundtrip() {
+        let seoul = tz::parse_tz("Asia/Seoul").unwrap();
+        let mut timed = Sched::new("home", "21kyu", "스탠드업; 회의", date(2023, 6, 30));
+        timed.start_time = NaiveTime::from_hms_opt(23, 30, 0);
+        timed.end_time = NaiveTime::from_hms_opt(0, 30, 0);
+        timed.all_day = false;
+
+        let ics = calendar("home", &[timed.clone()], seoul);
+        let events = parse_components(&ics, "VEVENT").unwrap();
+        let imported = sched_from_event(&events[0], "home", "21kyu", seoul).unwrap();
+
+        let local = tz::to_local(&timed, seoul);
+        assert_eq!(imported.sid, timed.sid);
+        assert_eq!(imported.uid, None);
+        assert_eq!(imported.sched, local.sched);
+        assert_eq!(imported.date_at, local.date_at);
+        assert_eq!(imported.start_time, local.start_time);
+        assert_eq!(imported.end_time, local.end_time);
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("PT1H30M").unwrap(), Duration::minutes(90));
+        assert_eq!(parse_duration("P1W").unwrap(), Duration::days(7));
+        assert_eq!(parse_duration("-P1D").unwrap(), Duration::days(-1));
+        assert!(parse_duration("1H").is_err());
+        assert!(parse_duration("P9223372036854775807W").is_err());
+        assert!(parse_duration("P99999999999999D").is_err());
+        assert!(parse_duration("P10675199167DT9223372036854775807S").is_err());
+    }
+}
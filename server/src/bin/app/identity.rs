@@ -0,0 +1,12 @@
+use scylla::FromRow;
+
+/// An account at an identity provider linked to a user, as stored in
+/// `ks.l`. `subject` is the provider's stable id for the account, `login`
+/// the name it was shown under when last used.
+#[derive(Debug, Default, Clone, PartialEq, Eq, FromRow)]
+pub struct Identity {
+    pub provider: String,
+    pub subject: String,
+    pub id: String,
+    pub login: String,
+}
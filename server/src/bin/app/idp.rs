@@ -0,0 +1,382 @@
+use std::env;
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use oauth2::basic::{
+    BasicErrorResponse, BasicRevocationErrorResponse, BasicTokenIntrospectionResponse,
+    BasicTokenType,
+};
+use oauth2::{
+    AuthUrl, Client, ClientId, ClientSecret, ExtraTokenFields, RedirectUrl, Scope,
+    StandardRevocableToken, StandardTokenResponse, TokenResponse as _, TokenUrl,
+};
+use octocrab::Octocrab;
+use serde::{Deserialize, Serialize};
+
+/// Where every provider sends the user back to.
+const REDIRECT_URL: &str = "https://sched.sinabro.io/auth";
+
+/// OpenID Connect providers answer the token request with an ID token too.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct IdTokenFields {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id_token: Option<String>,
+}
+
+impl ExtraTokenFields for IdTokenFields {}
+
+pub type TokenResponse = StandardTokenResponse<IdTokenFields, BasicTokenType>;
+
+pub type OAuthClient = Client<
+    BasicErrorResponse,
+    TokenResponse,
+    BasicTokenType,
+    BasicTokenIntrospectionResponse,
+    StandardRevocableToken,
+    BasicRevocationErrorResponse,
+>;
+
+/// Who signed in, as the provider knows them. `subject` never changes for
+/// an account, `login` may.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Profile {
+    pub subject: String,
+    pub login: String,
+}
+
+#[async_trait]
+pub trait IdentityProvider: Send + Sync {
+    /// Names the provider in login URLs and linked identities.
+    fn name(&self) -> &str;
+    fn client(&self) -> &OAuthClient;
+    fn scopes(&self) -> Vec<Scope>;
+    /// OpenID Connect binds the ID token to the login attempt with a nonce.
+    fn uses_nonce(&self) -> bool {
+        false
+    }
+    /// Looks up the account the token was issued for.
+    async fn profile(&self, token: &TokenResponse, nonce: &str) -> Result<Profile>;
+}
+
+fn oauth_client(
+    id: String,
+    secret: String,
+    auth_url: &str,
+    token_url: &str,
+) -> Result<OAuthClient> {
+    Ok(OAuthClient::new(
+        ClientId::new(id),
+        Some(ClientSecret::new(secret)),
+        AuthUrl::new(auth_url.to_owned())?,
+        Some(TokenUrl::new(token_url.to_owned())?),
+    )
+    .set_redirect_uri(RedirectUrl::new(REDIRECT_URL.to_owned())?))
+}
+
+pub struct GitHub {
+    client: OAuthClient,
+}
+
+impl GitHub {
+    pub fn new(id: String, secret: String) -> Result<Self> {
+        Ok(Self {
+            client: oauth_client(
+                id,
+                secret,
+                "https://github.com/login/oauth/authorize",
+                "https://github.com/login/oauth/access_token",
+            )?,
+        })
+    }
+}
+
+#[async_trait]
+impl IdentityProvider for GitHub {
+    fn name(&self) -> &str {
+        "github"
+    }
+
+    fn client(&self) -> &OAuthClient {
+        &self.client
+    }
+
+    fn scopes(&self) -> Vec<Scope> {
+        vec![Scope::new("user".to_owned())]
+    }
+
+    async fn profile(&self, token: &TokenResponse, _nonce: &str) -> Result<Profile> {
+        let octocrab = Octocrab::builder()
+            .personal_token(token.access_token().secret().clone())
+            .build()?;
+        let user = octocrab.current().user().await?;
+
+        Ok(Profile {
+            subject: user.id.to_string(),
+            login: user.login,
+        })
+    }
+}
+
+/// gitlab.com or a self-managed instance at `url`.
+pub struct GitLab {
+    client: OAuthClient,
+    url: String,
+}
+
+impl GitLab {
+    pub fn new(id: String, secret: String, url: &str) -> Result<Self> {
+        let url = url.trim_end_matches('/').to_owned();
+        Ok(Self {
+            client: oauth_client(
+                id,
+                secret,
+                &format!("{}/oauth/authorize", url),
+                &format!("{}/oauth/token", url),
+            )?,
+            url,
+        })
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct GitLabUser {
+    id: u64,
+    username: String,
+}
+
+#[async_trait]
+impl IdentityProvider for GitLab {
+    fn name(&self) -> &str {
+        "gitlab"
+    }
+
+    fn client(&self) -> &OAuthClient {
+        &self.client
+    }
+
+    fn scopes(&self) -> Vec<Scope> {
+        vec![Scope::new("read_user".to_owned())]
+    }
+
+    async fn profile(&self, token: &TokenResponse, _nonce: &str) -> Result<Profile> {
+        let user = reqwest::Client::new()
+            .get(format!("{}/api/v4/user", self.url))
+            .bearer_auth(token.access_token().secret())
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<GitLabUser>()
+            .await?;
+
+        Ok(Profile {
+            subject: user.id.to_string(),
+            login: user.username,
+        })
+    }
+}
+
+/// The parts of `/.well-known/openid-configuration` logins need.
+#[derive(Deserialize, Debug)]
+struct Discovery {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct IdClaims {
+    sub: String,
+    nonce: Option<String>,
+    preferred_username: Option<String>,
+    email: Option<String>,
+}
+
+/// Any OpenID Connect provider, Google Workspace for one, found through
+/// discovery on its issuer URL.
+pub struct Oidc {
+    name: String,
+    client: OAuthClient,
+    client_id: String,
+    issuer: String,
+    jwks_uri: String,
+}
+
+impl Oidc {
+    pub async fn discover(name: &str, issuer: &str, id: String, secret: String) -> Result<Self> {
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            issuer.trim_end_matches('/')
+        );
+        let discovery = reqwest::get(&url)
+            .await?
+            .error_for_status()?
+            .json::<Discovery>()
+            .await?;
+        if discovery.issuer.trim_end_matches('/') != issuer.trim_end_matches('/') {
+            return Err(anyhow!(
+                "{} is for issuer {}, not {}",
+                url,
+                discovery.issuer,
+                issuer
+            ));
+        }
+
+        Ok(Self {
+            name: name.to_owned(),
+            client: oauth_client(
+                id.to_owned(),
+                secret,
+                &discovery.authorization_endpoint,
+                &discovery.token_endpoint,
+            )?,
+            client_id: id,
+            issuer: discovery.issuer,
+            jwks_uri: discovery.jwks_uri,
+        })
+    }
+
+    /// Checks the ID token was signed by the issuer for us and this login
+    /// attempt. Keys are fetched on every login, so rotated keys are
+    /// picked up.
+    async fn validate(&self, id_token: &str, nonce: &str) -> Result<IdClaims> {
+        let header = jsonwebtoken::decode_header(id_token)?;
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            return Err(anyhow!("ID token signed with {:?}", header.alg));
+        }
+
+        let jwks = reqwest::get(&self.jwks_uri)
+            .await?
+            .error_for_status()?
+            .json::<JwkSet>()
+            .await?;
+        let jwk = match &header.kid {
+            Some(kid) => jwks.find(kid),
+            None => jwks.keys.first(),
+        }
+        .ok_or_else(|| anyhow!("no key {:?} at {}", header.kid, self.jwks_uri))?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_audience(&[&self.client_id]);
+        let claims =
+            jsonwebtoken::decode::<IdClaims>(id_token, &DecodingKey::from_jwk(jwk)?, &validation)?
+                .claims;
+
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(anyhow!("ID token nonce doesn't match the login attempt"));
+        }
+        Ok(claims)
+    }
+}
+
+#[async_trait]
+impl IdentityProvider for Oidc {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn client(&self) -> &OAuthClient {
+        &self.client
+    }
+
+    fn scopes(&self) -> Vec<Scope> {
+        ["openid", "profile", "email"]
+            .into_iter()
+            .map(|scope| Scope::new(scope.to_owned()))
+            .collect()
+    }
+
+    fn uses_nonce(&self) -> bool {
+        true
+    }
+
+    async fn profile(&self, token: &TokenResponse, nonce: &str) -> Result<Profile> {
+        let id_token = token
+            .extra_fields()
+            .id_token
+            .as_deref()
+            .ok_or_else(|| anyhow!("{} returned no ID token", self.name))?;
+        let claims = self.validate(id_token, nonce).await?;
+
+        let login = claims
+            .preferred_username
+            .or(claims.email)
+            .unwrap_or_else(|| claims.sub.to_owned());
+        Ok(Profile {
+            subject: claims.sub,
+            login,
+        })
+    }
+}
+
+/// The configured providers. The first one is used when a login doesn't
+/// name one.
+#[derive(Clone)]
+pub struct Providers(Vec<Arc<dyn IdentityProvider>>);
+
+impl Providers {
+    /// GitHub with `GITHUB_CLIENT_ID` and `GITHUB_CLIENT_SECRET`, GitLab
+    /// with `GITLAB_CLIENT_ID`, `GITLAB_CLIENT_SECRET` and optionally
+    /// `GITLAB_URL`, and an OpenID Connect provider with `OIDC_ISSUER`,
+    /// `OIDC_CLIENT_ID`, `OIDC_CLIENT_SECRET` and optionally `OIDC_NAME`.
+    pub async fn from_env() -> Result<Self> {
+        let mut providers: Vec<Arc<dyn IdentityProvider>> = vec![];
+
+        if let Ok(id) = env::var("GITHUB_CLIENT_ID") {
+            providers.push(Arc::new(GitHub::new(
+                id,
+                env::var("GITHUB_CLIENT_SECRET")
+                    .map_err(|_| anyhow!("Missing the GITHUB_CLIENT_SECRET env"))?,
+            )?));
+        }
+
+        if let Ok(id) = env::var("GITLAB_CLIENT_ID") {
+            providers.push(Arc::new(GitLab::new(
+                id,
+                env::var("GITLAB_CLIENT_SECRET")
+                    .map_err(|_| anyhow!("Missing the GITLAB_CLIENT_SECRET env"))?,
+                &env::var("GITLAB_URL").unwrap_or_else(|_| "https://gitlab.com".to_owned()),
+            )?));
+        }
+
+        if let Ok(issuer) = env::var("OIDC_ISSUER") {
+            providers.push(Arc::new(
+                Oidc::discover(
+                    &env::var("OIDC_NAME").unwrap_or_else(|_| "oidc".to_owned()),
+                    &issuer,
+                    env::var("OIDC_CLIENT_ID")
+                        .map_err(|_| anyhow!("Missing the OIDC_CLIENT_ID env"))?,
+                    env::var("OIDC_CLIENT_SECRET")
+                        .map_err(|_| anyhow!("Missing the OIDC_CLIENT_SECRET env"))?,
+                )
+                .await?,
+            ));
+        }
+
+        if providers.is_empty() {
+            return Err(anyhow!(
+                "No identity provider configured, set GITHUB_CLIENT_ID, GITLAB_CLIENT_ID or OIDC_ISSUER"
+            ));
+        }
+        Ok(Self(providers))
+    }
+
+    pub fn get(&self, name: Option<&str>) -> Option<&dyn IdentityProvider> {
+        match name {
+            Some(name) => self.0.iter().find(|p| p.name() == name),
+            None => self.0.first(),
+        }
+        .map(|p| p.as_ref())
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.0.iter().map(|p| p.name()).collect()
+    }
+}
@@ -0,0 +1,126 @@
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use scylla::frame::value::Timestamp;
+use scylla::FromRow;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::keys::KeyRing;
+
+/// A pending invitation to a channel, as stored in `ks.i`. Revoking deletes
+/// it, and so does accepting a single-use one.
+#[derive(Debug, Clone, PartialEq, FromRow)]
+pub struct Invite {
+    pub channel: String,
+    pub id: Uuid,
+    pub created_by: String,
+    pub expires_at: Timestamp,
+    pub single_use: bool,
+}
+
+impl Invite {
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.0.num_milliseconds() <= Utc::now().timestamp_millis()
+    }
+}
+
+/// What an invite link carries. The link is only good while the invite it
+/// names is still stored.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Claims {
+    pub channel: String,
+    pub id: Uuid,
+    pub exp: i64,
+}
+
+/// The `aud` of invite links, which are shared in the open and must never
+/// pass for session tokens.
+const AUDIENCE: &str = "sched-bird:invite";
+
+/// Invite links are JWTs signed with the session keys, for an audience of
+/// their own.
+pub fn sign(keys: &KeyRing, invite: &Invite) -> Result<String> {
+    keys.encode(
+        AUDIENCE,
+        &Claims {
+            channel: invite.channel.to_owned(),
+            id: invite.id,
+            exp: invite.expires_at.0.num_seconds(),
+        },
+    )
+}
+
+pub fn verify(keys: &KeyRing, token: &str) -> Result<Claims> {
+    keys.decode(AUDIENCE, token)
+        .map_err(|e| anyhow!("invalid invite: {}", e))
+}
+
+pub fn invite_path(token: &str) -> String {
+    format!("/invites/{}", token)
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{engine::general_purpose, Engine as _};
+    use chrono::Duration;
+
+    use super::*;
+
+    fn invite(expires_in: Duration) -> Invite {
+        Invite {
+            channel: "home".to_owned(),
+            id: Uuid::new_v4(),
+            created_by: "21kyu".to_owned(),
+            expires_at: Timestamp(Duration::milliseconds(
+                (Utc::now() + expires_in).timestamp_millis(),
+            )),
+            single_use: true,
+        }
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let keys = KeyRing::load(None, None, Some("secret")).unwrap();
+        let invite = invite(Duration::hours(1));
+        let token = sign(&keys, &invite).unwrap();
+
+        let claims = verify(&keys, &token).unwrap();
+        assert_eq!((claims.channel.as_str(), claims.id), ("home", invite.id));
+
+        let other = KeyRing::load(None, None, Some("other")).unwrap();
+        assert!(verify(&other, &token).is_err());
+    }
+
+    #[test]
+    fn test_sign_without_secret() {
+        let keys = KeyRing::without_secret();
+        let invite = invite(Duration::hours(1));
+        let token = sign(&keys, &invite).unwrap();
+        assert_eq!(verify(&keys, &token).unwrap().id, invite.id);
+
+        // Whatever reads it, an invite link is no session token.
+        let any = keys.decode::<serde_json::Value>(crate::keys::SESSION_AUDIENCE, &token);
+        assert!(any.is_err());
+    }
+
+    #[test]
+    fn test_verify_rejects_tampered_and_expired() {
+        let keys = KeyRing::load(None, None, Some("secret")).unwrap();
+        let token = sign(&keys, &invite(Duration::hours(1))).unwrap();
+        let (_, signature) = token.rsplit_once('.').unwrap();
+        let forged = general_purpose::URL_SAFE_NO_PAD.encode(
+            serde_json::to_vec(&Claims {
+                channel: "work".to_owned(),
+                id: Uuid::new_v4(),
+                exp: i64::MAX,
+            })
+            .unwrap(),
+        );
+        let (header, _) = token.split_once('.').unwrap();
+        let forged = format!("{}.{}.{}", header, forged, signature);
+        assert!(verify(&keys, &forged).is_err());
+
+        let expired = sign(&keys, &invite(Duration::hours(-1))).unwrap();
+        assert!(verify(&keys, &expired).is_err());
+    }
+}
@@ -0,0 +1,206 @@
+use std::env;
+use std::fmt;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+const MAX_TOKENS: u32 = 300;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Message {
+    pub role: String,
+    pub content: String,
+}
+
+impl Message {
+    pub fn system(content: impl Into<String>) -> Self {
+        Self {
+            role: "system".to_owned(),
+            content: content.into(),
+        }
+    }
+
+    pub fn user(content: impl Into<String>) -> Self {
+        Self {
+            role: "user".to_owned(),
+            content: content.into(),
+        }
+    }
+}
+
+#[async_trait]
+pub trait LanguageModel: Send + Sync {
+    /// The model's answer to a conversation.
+    async fn complete(&self, messages: &[Message]) -> Result<String>;
+}
+
+#[derive(Deserialize, Debug)]
+struct Choice {
+    #[serde(default)]
+    finish_reason: Option<String>,
+    message: Message,
+}
+
+#[derive(Deserialize, Debug)]
+struct ChatResponse {
+    choices: Vec<Choice>,
+}
+
+impl ChatResponse {
+    /// The first answer the model finished, rather than one cut off at
+    /// `max_tokens`.
+    fn content(self) -> Result<String> {
+        self.choices
+            .into_iter()
+            .find(|choice| choice.finish_reason.as_deref() == Some("stop"))
+            .map(|choice| choice.message.content)
+            .ok_or_else(|| anyhow!("no finished answer in response"))
+    }
+}
+
+/// A chat completions API: OpenAI itself or a self-hosted server speaking
+/// the same protocol, like llama.cpp, Ollama or vLLM.
+pub struct OpenAiCompatible {
+    client: reqwest::Client,
+    base_url: String,
+    key: Option<String>,
+    model: String,
+    temperature: f32,
+}
+
+impl OpenAiCompatible {
+    pub fn new(
+        base_url: &str,
+        key: Option<String>,
+        model: &str,
+        temperature: f32,
+        timeout: Duration,
+    ) -> Result<Self> {
+        Ok(Self {
+            client: reqwest::Client::builder().timeout(timeout).build()?,
+            base_url: base_url.trim_end_matches('/').to_owned(),
+            key,
+            model: model.to_owned(),
+            temperature,
+        })
+    }
+
+    /// `LLM_BASE_URL`, `LLM_MODEL`, `LLM_TEMPERATURE` and `LLM_TIMEOUT` in
+    /// seconds, defaulting to OpenAI's `gpt-3.5-turbo`. The key is
+    /// `LLM_API_KEY` or `OPENAI_SECRET`; local servers usually need none.
+    /// `None` without a base URL or a key.
+    pub fn from_env() -> Result<Option<Self>> {
+        let var = |name: &str, default: &str| env::var(name).unwrap_or_else(|_| default.to_owned());
+
+        let key = env::var("LLM_API_KEY")
+            .or_else(|_| env::var("OPENAI_SECRET"))
+            .ok();
+        if key.is_none() && env::var("LLM_BASE_URL").is_err() {
+            return Ok(None);
+        }
+
+        Self::new(
+            &var("LLM_BASE_URL", "https://api.openai.com/v1"),
+            key,
+            &var("LLM_MODEL", "gpt-3.5-turbo"),
+            var("LLM_TEMPERATURE", "0.2")
+                .parse()
+                .map_err(|e| anyhow!("Invalid LLM_TEMPERATURE env: {}", e))?,
+            Duration::from_secs(
+                var("LLM_TIMEOUT", "30")
+                    .parse()
+                    .map_err(|e| anyhow!("Invalid LLM_TIMEOUT env: {}", e))?,
+            ),
+        )
+        .map(Some)
+    }
+}
+
+impl fmt::Display for OpenAiCompatible {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}", self.model, self.base_url)
+    }
+}
+
+#[async_trait]
+impl LanguageModel for OpenAiCompatible {
+    async fn complete(&self, messages: &[Message]) -> Result<String> {
+        let mut req = self
+            .client
+            .post(format!("{}/chat/completions", self.base_url))
+            .json(&json!({
+                "model": self.model,
+                "max_tokens": MAX_TOKENS,
+                "temperature": self.temperature,
+                "messages": messages,
+            }));
+        if let Some(key) = &self.key {
+            req = req.bearer_auth(key);
+        }
+
+        let resp = req.send().await?;
+        // Only the status is logged, replies carry the user's schedules.
+        if resp.status() != 200 {
+            return Err(anyhow!("status code: {}", resp.status()));
+        }
+
+        resp.json::<ChatResponse>().await?.content()
+    }
+}
+
+/// Answers with canned replies in order, and remembers what it was asked.
+#[cfg(test)]
+#[derive(Default)]
+pub struct Fake {
+    replies: std::sync::Mutex<std::collections::VecDeque<String>>,
+    pub requests: std::sync::Mutex<Vec<Vec<Message>>>,
+}
+
+#[cfg(test)]
+impl Fake {
+    pub fn new(replies: &[&str]) -> Self {
+        Self {
+            replies: std::sync::Mutex::new(replies.iter().map(|r| r.to_string()).collect()),
+            ..Self::default()
+        }
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl LanguageModel for Fake {
+    async fn complete(&self, messages: &[Message]) -> Result<String> {
+        self.requests.lock().unwrap().push(messages.to_vec());
+        self.replies
+            .lock()
+            .unwrap()
+            .pop_front()
+            .ok_or_else(|| anyhow!("no reply left"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content() {
+        let resp: ChatResponse = serde_json::from_str(
+            r#"{"model": "llama3", "choices": [
+                {"finish_reason": "length", "message": {"role": "assistant", "content": "{\"ti"}},
+                {"finish_reason": "stop", "message": {"role": "assistant", "content": "{}"}}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(resp.content().unwrap(), "{}");
+
+        let resp: ChatResponse = serde_json::from_str(
+            r#"{"choices": [{"finish_reason": null, "message": {"role": "assistant", "content": "{}"}}]}"#,
+        )
+        .unwrap();
+        assert!(resp.content().is_err());
+    }
+}
@@ -1,15 +1,33 @@
+mod api;
+mod assistant;
 mod auth;
+mod bulk;
+mod caldav;
+mod channel;
 mod db;
 mod gpt;
+mod ical;
+mod identity;
+mod idp;
+mod invite;
+mod keys;
+mod llm;
+mod member;
+mod pat;
+mod perm;
+mod recur;
+mod refresh;
 mod render;
+mod rules;
 mod sched;
+mod session;
+mod tz;
 mod user;
 
 use crate::user::User;
 
 use std::collections::HashMap;
 use std::convert::Infallible;
-use std::env;
 use std::future::Future;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::path::PathBuf;
@@ -23,21 +41,22 @@ use axum::extract::{Path, Query, State};
 use axum::http::{header, StatusCode, Uri};
 use axum::response::{IntoResponse, Response};
 use axum::{middleware, Extension};
-use axum::{routing::get, routing::post, Json, Router};
-use clap::Parser;
+use axum::{
+    routing::any, routing::delete, routing::get, routing::patch, routing::post, Router,
+};
+use clap::{Parser, Subcommand};
 use futures::stream::{self, StreamExt};
 use hyper::server::Server;
-use oauth2::basic::BasicClient;
-use oauth2::{CsrfToken, Scope};
 use sched_bird::{ServerApp, ServerAppProps};
-use scylla::IntoTypedRows;
-use serde::Deserialize;
 use tower::ServiceExt;
-use tower_cookies::{CookieManagerLayer, Cookies};
+use tower_cookies::{Cookie, CookieManagerLayer, Cookies};
 use tower_http::services::ServeDir;
-use url::Url;
 use yew::platform::Runtime;
 
+/// Carries a followed invite link through the GitHub sign-in.
+const INVITE_COOKIE: &str = "invite";
+const INVITE_COOKIE_MAX_AGE: i64 = 600;
+
 #[derive(Parser, Debug)]
 #[clap(name = "Sched Bird")]
 struct Opt {
@@ -49,13 +68,56 @@ struct Opt {
 
     #[clap(short = 'd', long = "dist", default_value = "../../../dist")]
     dist: String,
+
+    #[clap(
+        short = 's',
+        long = "store",
+        value_enum,
+        default_value = "scylla",
+        global = true
+    )]
+    store: db::Backend,
+
+    #[clap(long = "sqlite-path", default_value = "sched-bird.db", global = true)]
+    sqlite_path: String,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+/// Maintenance commands run against the store instead of serving.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Writes every schedule of a channel to stdout
+    Export {
+        #[clap(short = 'c', long = "channel")]
+        channel: String,
+
+        #[clap(short = 'f', long = "format", value_enum, default_value = "csv")]
+        format: bulk::Format,
+    },
+    /// Imports schedules into a channel from a file, or stdin when it is `-`
+    Import {
+        #[clap(short = 'c', long = "channel")]
+        channel: String,
+
+        #[clap(short = 'f', long = "format", value_enum, default_value = "csv")]
+        format: bulk::Format,
+
+        /// Validates every row without writing any
+        #[clap(long = "dry-run")]
+        dry_run: bool,
+
+        file: PathBuf,
+    },
 }
 
 #[derive(Clone)]
 pub struct AppState {
-    db: Arc<db::Scylla>,
-    client: BasicClient,
-    authorize_url: Url,
+    db: Arc<dyn db::Store>,
+    providers: idp::Providers,
+    keys: keys::KeyRing,
+    llm: Option<Arc<dyn llm::LanguageModel>>,
 }
 
 #[derive(Clone, Default)]
@@ -80,6 +142,11 @@ async fn main() -> Result<()> {
 
     let opt = Opt::parse();
 
+    if let Some(command) = opt.command {
+        let db = db::connect(opt.store, &opt.sqlite_path).await?;
+        return run_command(db.as_ref(), command).await;
+    }
+
     let sock_addr = SocketAddr::from((
         IpAddr::from_str(opt.addr.as_str()).unwrap_or(IpAddr::V4(Ipv4Addr::LOCALHOST)),
         opt.port,
@@ -87,36 +154,30 @@ async fn main() -> Result<()> {
 
     println!("Listening on {}", sock_addr);
 
-    let client = auth::create_github_client();
+    let providers = idp::Providers::from_env().await?;
+    println!("Identity providers: {:?}", providers.names());
 
-    let (authorize_url, _csrf_state) = client
-        .authorize_url(CsrfToken::new_random)
-        .add_scope(Scope::new("user".to_string()))
-        .url();
+    let db = db::connect(opt.store, &opt.sqlite_path).await?;
 
-    println!("Browse to: {}", authorize_url);
+    let keys = keys::KeyRing::from_env()?;
+    println!(
+        "Signing JWTs with {:?}",
+        keys.signing_kid().unwrap_or("JWT_SECRET")
+    );
 
-    let db = Arc::new(db::Scylla::new().await?);
+    let llm = llm::OpenAiCompatible::from_env()?;
+    match &llm {
+        Some(llm) => println!("Language model: {}", llm),
+        None => println!("Language model: none, reading requests with rules"),
+    }
 
     let shared_state = Arc::new(AppState {
         db,
-        client,
-        authorize_url,
+        providers,
+        keys,
+        llm: llm.map(|llm| Arc::new(llm) as Arc<dyn llm::LanguageModel>),
     });
 
-    if let Some(rows) = shared_state
-        .db
-        .session
-        .query("SELECT id, channel FROM ks.u", &[])
-        .await?
-        .rows
-    {
-        for row in rows.into_typed::<User>() {
-            let row = row?;
-            println!("row: {:?}", row);
-        }
-    }
-
     let index_path = PathBuf::from(&opt.dist).join("index.html");
     let index_html_s = tokio::fs::read_to_string(index_path)
         .await
@@ -150,12 +211,108 @@ async fn main() -> Result<()> {
         )
     };
 
+    let dav = Router::new()
+        .route("/dav", any(caldav::root))
+        .route(caldav::ROOT, any(caldav::root))
+        .route("/dav/calendars", any(caldav::home))
+        .route(caldav::HOME, any(caldav::home))
+        .route("/dav/calendars/:channel", any(caldav::collection))
+        .route("/dav/calendars/:channel/", any(caldav::collection))
+        .route("/dav/calendars/:channel/:resource", any(caldav::resource))
+        .with_state(Arc::clone(&shared_state))
+        .route_layer(middleware::from_fn_with_state(
+            Arc::clone(&shared_state),
+            auth::dav_auth,
+        ));
+
     let app = Router::new()
         .route("/auth", get(auth))
-        .route("/api/v1/channels/:channel/scheds", get(get_scheds))
-        .route("/api/v1/gpt", post(invoke_gpt))
+        .route("/auth/link/:provider", get(link_provider))
+        .route("/api/v1/users/me", patch(api::update_me))
+        .route("/api/v1/users/me/identities", get(api::get_identities))
+        .route("/api/v1/users/me/sessions", get(api::get_sessions))
+        .route(
+            "/api/v1/users/me/tokens",
+            get(api::get_tokens).post(api::create_token),
+        )
+        .route("/api/v1/users/me/tokens/:id", delete(api::delete_token))
+        .route("/api/v1/users/me/sessions/:id", delete(api::delete_session))
+        .route(
+            "/api/v1/users/me/identities/:provider/:subject",
+            delete(api::delete_identity),
+        )
+        .route(
+            "/api/v1/channels",
+            get(api::get_channels).post(api::create_channel),
+        )
+        .route("/api/v1/channels/:channel", patch(api::update_channel))
+        .route(
+            "/api/v1/channels/:channel/switch",
+            post(api::switch_channel),
+        )
+        .route(
+            "/api/v1/channels/:channel/membership",
+            delete(api::leave_channel),
+        )
+        .route("/api/v1/channels/:channel/members", get(api::get_members))
+        .route(
+            "/api/v1/channels/:channel/members/:id",
+            patch(api::update_member).delete(api::remove_member),
+        )
+        .route(
+            "/api/v1/channels/:channel/scheds",
+            get(api::get_scheds).post(api::create_sched),
+        )
+        .route(
+            "/api/v1/channels/:channel/scheds/:sid",
+            get(api::get_sched)
+                .patch(api::update_sched)
+                .delete(api::delete_sched),
+        )
+        .route(
+            "/api/v1/channels/:channel/calendar.ics",
+            get(api::get_calendar).post(api::import_calendar),
+        )
+        .route(
+            "/api/v1/channels/:channel/feed",
+            post(api::create_feed).delete(api::delete_feed),
+        )
+        .route("/api/v1/channels/:channel/export", get(api::export_scheds))
+        .route("/api/v1/channels/:channel/import", post(api::import_scheds))
+        .route(
+            "/api/v1/channels/:channel/invites",
+            get(api::get_invites).post(api::create_invite),
+        )
+        .route(
+            "/api/v1/channels/:channel/invites/:id",
+            delete(api::delete_invite),
+        )
+        .route("/api/v1/invites/:token", post(api::join_channel))
+        .route("/api/v1/gpt", post(assistant::invoke))
         .with_state(Arc::clone(&shared_state))
-        .route_layer(middleware::from_fn_with_state(shared_state, auth::auth))
+        .route_layer(middleware::from_fn_with_state(
+            Arc::clone(&shared_state),
+            auth::auth,
+        ))
+        .route(
+            "/auth/refresh",
+            post(auth::refresh).with_state(Arc::clone(&shared_state)),
+        )
+        .route(
+            "/auth/logout",
+            post(auth::logout).with_state(Arc::clone(&shared_state)),
+        )
+        .route(
+            "/.well-known/jwks.json",
+            get(auth::jwks).with_state(Arc::clone(&shared_state)),
+        )
+        .route(
+            "/feeds/:channel/:token/calendar.ics",
+            get(api::get_feed).with_state(shared_state),
+        )
+        .route("/invites/:token", get(follow_invite))
+        .route("/.well-known/caldav", any(caldav::well_known))
+        .merge(dav)
         .fallback_service(HandleError::new(
             ServeDir::new(PathBuf::from(&opt.dist))
                 .append_index_html_on_directories(false)
@@ -177,6 +334,35 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+async fn run_command(db: &dyn db::Store, command: Command) -> Result<()> {
+    match command {
+        Command::Export { channel, format } => {
+            let scheds = bulk::dump(db, &channel).await?;
+            print!("{}", bulk::write(&scheds, format)?);
+            Ok(())
+        }
+        Command::Import {
+            channel,
+            format,
+            dry_run,
+            file,
+        } => {
+            let text = if file.as_os_str() == "-" {
+                std::io::read_to_string(std::io::stdin())?
+            } else {
+                tokio::fs::read_to_string(&file).await?
+            };
+            let report = bulk::import(db, &channel, bulk::read(&text, format)?, dry_run).await?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+
+            if report.failed > 0 {
+                return Err(anyhow::anyhow!("{} invalid rows", report.failed));
+            }
+            Ok(())
+        }
+    }
+}
+
 fn get_cookie_value(cookies: &Cookies, name: &str) -> String {
     cookies
         .get(name)
@@ -192,8 +378,6 @@ async fn render(
 ) -> impl IntoResponse {
     let url = url.to_string();
 
-    println!("cookies: {:?}", cookies);
-
     let user = get_cookie_value(&cookies, "user");
     let channel = get_cookie_value(&cookies, "channel");
     let token: String = get_cookie_value(&cookies, "auth_token");
@@ -214,71 +398,70 @@ async fn render(
     )
 }
 
-async fn auth() -> impl IntoResponse {
+/// Remembers an invite link across the GitHub sign-in, which `auth` then
+/// accepts.
+async fn follow_invite(Path(token): Path<String>, cookies: Cookies) -> impl IntoResponse {
+    cookies.add(
+        Cookie::parse(format!(
+            "{}={}; Secure; HttpOnly; SameSite=Lax; Path=/; Max-Age={}",
+            INVITE_COOKIE, token, INVITE_COOKIE_MAX_AGE
+        ))
+        .unwrap(),
+    );
+
     Response::builder()
         .status(StatusCode::FOUND)
-        .header(header::LOCATION, "https://sched.sinabro.io/")
+        .header(header::LOCATION, "/auth")
         .body(boxed(Body::empty()))
         .unwrap()
 }
 
-async fn get_scheds(
-    Path(channel): Path<String>,
+async fn auth(
     Extension(user): Extension<User>,
+    Extension(claims): Extension<auth::Claims>,
     State(state): State<Arc<AppState>>,
+    cookies: Cookies,
 ) -> impl IntoResponse {
-    let scheds = state.db.find_sched_by_channel(&channel).await.unwrap();
-
-    let content = serde_json::json!({ "user": user.id, "channel": user.channel, "data": scheds });
-
-    println!("scheds: {:?}", content);
-
-    Json(content)
-}
-
-#[derive(Deserialize, Debug)]
-struct OpenAiRequest {
-    query: String,
-}
-
-async fn invoke_gpt(
-    Extension(user): Extension<User>,
-    State(state): State<Arc<AppState>>,
-    Json(input): Json<OpenAiRequest>,
-) -> impl IntoResponse {
-    let open_ai_secret = env::var("OPENAI_SECRET").unwrap();
-
-    println!("input: {:?}", input);
-
-    let query = gpt::request_gpt_api(&open_ai_secret, &input.query).await;
-
-    println!("query: {:?}", query);
-
-    match query {
-        Ok(query) => match state.db.insert(&query).await {
-            Ok(_) => {
-                let scheds = state.db.find_sched_by_channel(&user.channel).await.unwrap();
-                let content =
-                    serde_json::json!({ "user": user.id, "channel": user.channel, "data": scheds });
+    if let Some(token) = cookies.get(INVITE_COOKIE).map(|c| c.value().to_owned()) {
+        cookies.remove(Cookie::build(INVITE_COOKIE, "").path("/").finish());
+
+        match api::accept_invite(&state, &user, &token).await {
+            Ok(access) => {
+                let mut user = user;
+                user.channel = access.channel.channel;
+                match state
+                    .db
+                    .insert_user(&user)
+                    .await
+                    .and(claims.with_channel(&user.channel).encode(&state.keys))
+                {
+                    Ok(jwt) => auth::set_session_cookies(&cookies, &user, &jwt),
+                    Err(err) => println!("err: {:?}", err),
+                }
+            }
+            Err((status, message)) => {
                 return Response::builder()
-                    .status(StatusCode::OK)
-                    .body(boxed(Body::from(content.to_string())))
+                    .status(status)
+                    .body(boxed(Body::from(message)))
                     .unwrap();
             }
-            Err(err) => {
-                println!("err: {:?}", err);
-                Response::builder()
-                    .status(StatusCode::INTERNAL_SERVER_ERROR)
-                    .body(boxed(Body::from(err.to_string())))
-                    .unwrap()
-            }
-        },
-        Err(err) => {
-            println!("err: {:?}", err);
-            Response::builder()
-                .status(StatusCode::BAD_REQUEST)
-                .body(boxed(Body::from(err.to_string())))
-                .unwrap()
         }
     }
+
+    Response::builder()
+        .status(StatusCode::FOUND)
+        .header(header::LOCATION, "https://sched.sinabro.io/")
+        .body(boxed(Body::empty()))
+        .unwrap()
+}
+
+/// Signs in at another provider to link that account to the user.
+async fn link_provider(
+    Path(provider): Path<String>,
+    Extension(user): Extension<User>,
+    State(state): State<Arc<AppState>>,
+    cookies: Cookies,
+) -> Response {
+    auth::start_login(&state, &cookies, Some(&provider), Some(&user.id))
+        .unwrap_or_else(|status| status.into_response())
 }
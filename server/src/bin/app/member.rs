@@ -0,0 +1,163 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use scylla::FromRow;
+use serde::{Deserialize, Serialize};
+
+/// What a member may do in a channel, from least to most. Viewers read,
+/// editors also manage their own schedules, owners manage everything
+/// including the channel and its members.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    Editor,
+    Owner,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Owner => "owner",
+        })
+    }
+}
+
+impl FromStr for Role {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "viewer" => Ok(Role::Viewer),
+            // Invites granted "member" before there were editors.
+            "editor" | "member" => Ok(Role::Editor),
+            "owner" => Ok(Role::Owner),
+            _ => Err(anyhow!("unknown role {:?}", s)),
+        }
+    }
+}
+
+/// A user's membership of a channel, as stored in `ks.m`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, FromRow)]
+pub struct Member {
+    pub id: String,
+    pub channel: String,
+    /// A `Role`. Memberships from before roles may have none; see
+    /// `Member::role`.
+    pub role: Option<String>,
+}
+
+impl Member {
+    pub fn new(id: &str, channel: &str, role: Role) -> Self {
+        Self {
+            id: id.to_owned(),
+            channel: channel.to_owned(),
+            role: Some(role.to_string()),
+        }
+    }
+
+    /// Unknown roles read as viewers, so a bad row never grants more. A
+    /// membership from before roles makes the user who created the channel,
+    /// whose personal channel shares their id, its owner and anyone else an
+    /// editor. Stored memberships get explicit roles from `legacy_roles`.
+    pub fn role(&self) -> Role {
+        match self.role.as_deref() {
+            None if self.id == self.channel => Role::Owner,
+            None => Role::Editor,
+            Some(role) => role.parse().unwrap_or(Role::Viewer),
+        }
+    }
+
+    pub fn is_owner(&self) -> bool {
+        self.role() == Role::Owner
+    }
+}
+
+/// Fills in the roles of a channel's memberships from before roles. Unless
+/// the channel already has an owner, the member whose personal channel it is
+/// becomes the owner, else the one whose id sorts first, so shared channels
+/// like the seeded `home` keep someone who can manage them. Returns only the
+/// memberships that changed.
+pub fn legacy_roles(members: &[Member]) -> Vec<Member> {
+    let legacy = members.iter().filter(|m| m.role.is_none());
+    let owner = if members.iter().any(|m| m.role.is_some() && m.is_owner()) {
+        None
+    } else {
+        legacy
+            .clone()
+            .find(|m| m.id == m.channel)
+            .or_else(|| legacy.clone().min_by(|a, b| a.id.cmp(&b.id)))
+    };
+
+    legacy
+        .map(|m| {
+            let role = if Some(m) == owner {
+                Role::Owner
+            } else {
+                Role::Editor
+            };
+            Member::new(&m.id, &m.channel, role)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_role() {
+        let mut member = Member::new("21kyu", "home", Role::Editor);
+        assert_eq!(member.role(), Role::Editor);
+
+        member.role = Some("member".to_owned());
+        assert_eq!(member.role(), Role::Editor);
+        member.role = Some("admin".to_owned());
+        assert_eq!(member.role(), Role::Viewer);
+        member.role = None;
+        assert_eq!(member.role(), Role::Editor);
+        member.channel = "21kyu".to_owned();
+        assert!(member.is_owner());
+
+        assert!(Role::Viewer < Role::Editor && Role::Editor < Role::Owner);
+    }
+
+    #[test]
+    fn test_legacy_roles() {
+        let legacy = |id: &str| Member {
+            id: id.to_owned(),
+            channel: "home".to_owned(),
+            role: None,
+        };
+
+        let home = legacy_roles(&[legacy("csj200045"), legacy("21kyu")]);
+        assert_eq!(
+            home,
+            vec![
+                Member::new("csj200045", "home", Role::Editor),
+                Member::new("21kyu", "home", Role::Owner),
+            ]
+        );
+
+        let owned = legacy_roles(&[
+            Member::new("csj200045", "home", Role::Owner),
+            legacy("21kyu"),
+        ]);
+        assert_eq!(owned, vec![Member::new("21kyu", "home", Role::Editor)]);
+
+        let personal = legacy_roles(&[
+            Member::new("21kyu", "csj200045", Role::Editor),
+            Member {
+                channel: "csj200045".to_owned(),
+                ..legacy("csj200045")
+            },
+        ]);
+        assert_eq!(
+            personal,
+            vec![Member::new("csj200045", "csj200045", Role::Owner)]
+        );
+    }
+}
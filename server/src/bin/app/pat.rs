@@ -0,0 +1,256 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::anyhow;
+use axum::http::Method;
+use chrono::{Duration, Utc};
+use percent_encoding::percent_decode_str;
+use scylla::frame::value::Timestamp;
+use scylla::FromRow;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::refresh;
+
+/// Tells personal access tokens apart from JWTs in `Authorization` headers.
+pub const PREFIX: &str = "sbp_";
+/// How often `last_used_at` is written, rather than on every request.
+const LAST_USED_INTERVAL: i64 = 60;
+
+/// What a personal access token may do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    Read,
+    Write,
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Scope::Read => "read",
+            Scope::Write => "write",
+        })
+    }
+}
+
+impl FromStr for Scope {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(Scope::Read),
+            "write" => Ok(Scope::Write),
+            _ => Err(anyhow!("unknown scope {:?}", s)),
+        }
+    }
+}
+
+fn now() -> Timestamp {
+    Timestamp(Duration::milliseconds(Utc::now().timestamp_millis()))
+}
+
+/// A personal access token for scripts and bots, as stored in `ks.t` under
+/// the SHA-256 of its secret. `tid` names it when listing and revoking.
+#[derive(Debug, Clone, PartialEq, FromRow)]
+pub struct PersonalToken {
+    pub hash: String,
+    pub id: String,
+    pub tid: Uuid,
+    pub name: String,
+    /// A `Scope`.
+    pub scope: String,
+    /// The only channel it reaches, or all of the user's.
+    pub channel: Option<String>,
+    pub created_at: Timestamp,
+    pub expires_at: Timestamp,
+    pub last_used_at: Option<Timestamp>,
+}
+
+impl PersonalToken {
+    /// A new token for `id`, with the secret handed to the user once. Only
+    /// the hash is stored.
+    pub fn issue(
+        id: &str,
+        name: &str,
+        scope: Scope,
+        channel: Option<&str>,
+        expires_in: Duration,
+    ) -> (String, Self) {
+        let secret = format!(
+            "{}{}{}",
+            PREFIX,
+            Uuid::new_v4().simple(),
+            Uuid::new_v4().simple()
+        );
+        let expires_at = Utc::now() + expires_in;
+
+        let token = Self {
+            hash: refresh::hash(&secret),
+            id: id.to_owned(),
+            tid: Uuid::new_v4(),
+            name: name.to_owned(),
+            scope: scope.to_string(),
+            channel: channel.map(str::to_owned),
+            created_at: now(),
+            expires_at: Timestamp(Duration::milliseconds(expires_at.timestamp_millis())),
+            last_used_at: None,
+        };
+        (secret, token)
+    }
+
+    /// Unknown scopes read as `Read`, so a bad row never grants more.
+    pub fn scope(&self) -> Scope {
+        self.scope.parse().unwrap_or(Scope::Read)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.0.num_milliseconds() <= Utc::now().timestamp_millis()
+    }
+
+    /// Whether the token may make a request. Tokens reach the channels API,
+    /// the assistant and CalDAV, never the account with its sessions and
+    /// tokens, so one can't outlive or outrank itself. Read tokens only
+    /// read, and may ask the assistant, which refuses their changes itself.
+    /// A token for a channel only reaches that channel.
+    pub fn allows(&self, method: &Method, path: &str) -> bool {
+        if self.scope() == Scope::Read && !is_read(method) && path != "/api/v1/gpt" {
+            return false;
+        }
+
+        if path == "/dav" || path.starts_with("/dav/") {
+            return self.allows_dav(path);
+        }
+
+        let rest = match path.strip_prefix("/api/v1/") {
+            Some(rest) => rest,
+            None => return false,
+        };
+        if rest == "gpt" {
+            return true;
+        }
+
+        match rest.strip_prefix("channels") {
+            // Listing and creating channels.
+            Some("") => self.channel.is_none(),
+            Some(sub) if sub.starts_with('/') => {
+                let mut segments = sub[1..].split('/');
+                let channel = decode_segment(segments.next().unwrap_or_default());
+                // Switching issues a session token.
+                if segments.next() == Some("switch") {
+                    return false;
+                }
+                self.channel.as_deref().is_none_or(|c| c == channel)
+            }
+            _ => false,
+        }
+    }
+
+    /// CalDAV discovery lists every channel of the user, so a token for a
+    /// channel only reaches that channel's collection, which clients are
+    /// then given as the account URL.
+    fn allows_dav(&self, path: &str) -> bool {
+        let channel = path
+            .strip_prefix("/dav/calendars/")
+            .and_then(|rest| rest.split('/').next())
+            .filter(|channel| !channel.is_empty())
+            .map(decode_segment);
+
+        match (self.channel.as_deref(), channel) {
+            (None, _) => true,
+            (Some(scoped), Some(channel)) => scoped == channel,
+            (Some(_), None) => false,
+        }
+    }
+
+    /// Records a use. Returns whether the token changed enough to be stored
+    /// again.
+    pub fn used(&mut self) -> bool {
+        let stale = self.last_used_at.is_none_or(|at| {
+            Utc::now().timestamp_millis() - at.0.num_milliseconds() >= LAST_USED_INTERVAL * 1000
+        });
+        if stale {
+            self.last_used_at = Some(now());
+        }
+        stale
+    }
+}
+
+/// WebDAV reads with `PROPFIND` and `REPORT` besides `GET`.
+fn is_read(method: &Method) -> bool {
+    matches!(
+        method.as_str(),
+        "GET" | "HEAD" | "OPTIONS" | "PROPFIND" | "REPORT"
+    )
+}
+
+fn decode_segment(segment: &str) -> String {
+    percent_decode_str(segment).decode_utf8_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allows() {
+        let (secret, read) =
+            PersonalToken::issue("21kyu", "ci", Scope::Read, None, Duration::days(1));
+        assert!(secret.starts_with(PREFIX));
+        assert!(read.allows(&Method::GET, "/api/v1/channels"));
+        assert!(read.allows(&Method::GET, "/api/v1/channels/home/scheds"));
+        assert!(!read.allows(&Method::POST, "/api/v1/channels/home/scheds"));
+        assert!(read.allows(&Method::POST, "/api/v1/gpt"));
+        assert!(!read.allows(&Method::GET, "/api/v1/users/me/tokens"));
+        assert!(!read.allows(&Method::GET, "/auth"));
+
+        let (_, write) = PersonalToken::issue(
+            "21kyu",
+            "bot",
+            Scope::Write,
+            Some("home"),
+            Duration::days(1),
+        );
+        assert!(write.allows(&Method::POST, "/api/v1/channels/home/scheds"));
+        assert!(write.allows(&Method::POST, "/api/v1/gpt"));
+        assert!(!write.allows(&Method::POST, "/api/v1/channels/work/scheds"));
+        assert!(!write.allows(&Method::POST, "/api/v1/channels/home/switch"));
+        assert!(!write.allows(&Method::POST, "/api/v1/channels"));
+        assert!(!write.allows(&Method::POST, "/api/v1/invites/abc"));
+    }
+
+    #[test]
+    fn test_allows_dav() {
+        let propfind = Method::from_bytes(b"PROPFIND").unwrap();
+        let (_, read) =
+            PersonalToken::issue("21kyu", "phone", Scope::Read, None, Duration::days(1));
+        assert!(read.allows(&propfind, "/dav/calendars/"));
+        assert!(read.allows(&Method::GET, "/dav/calendars/work/a.ics"));
+        assert!(!read.allows(&Method::PUT, "/dav/calendars/work/a.ics"));
+
+        let (_, write) = PersonalToken::issue(
+            "21kyu",
+            "phone",
+            Scope::Write,
+            Some("home"),
+            Duration::days(1),
+        );
+        assert!(write.allows(&propfind, "/dav/calendars/home/"));
+        assert!(write.allows(&Method::PUT, "/dav/calendars/home/a.ics"));
+        assert!(!write.allows(&propfind, "/dav/calendars/"));
+        assert!(!write.allows(&Method::PUT, "/dav/calendars/work/a.ics"));
+        assert!(!write.allows(&propfind, "/dav"));
+    }
+
+    #[test]
+    fn test_used() {
+        let (_, mut token) =
+            PersonalToken::issue("21kyu", "ci", Scope::Read, None, Duration::days(1));
+        assert!(!token.is_expired());
+        assert!(token.used());
+        assert!(!token.used());
+
+        token.scope = "admin".to_owned();
+        assert_eq!(token.scope(), Scope::Read);
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use axum::extract::{FromRequestParts, Path};
+use axum::http::request::Parts;
+use axum::http::StatusCode;
+
+use crate::api::{internal_error, ApiError};
+use crate::channel::Channel;
+use crate::member::{Member, Role};
+use crate::user::User;
+use crate::AppState;
+
+/// The signed-in user's membership of the channel a request is about: the
+/// `:channel` path segment, or their active channel on routes without one.
+/// Extracting it rejects anyone who isn't a member; handlers then ask for
+/// the role they need.
+#[derive(Debug, Clone)]
+pub struct Access {
+    pub user: User,
+    pub member: Member,
+    /// Not stored yet for channels from before `ks.c` rows were written.
+    pub channel: Channel,
+}
+
+impl Access {
+    pub async fn load(state: &AppState, user: &User, channel: &str) -> Result<Self, ApiError> {
+        let member = state
+            .db
+            .find_member(&user.id, channel)
+            .await
+            .map_err(internal_error)?
+            .ok_or_else(|| {
+                println!("{} is not a member of {}", user.id, channel);
+                (
+                    StatusCode::FORBIDDEN,
+                    format!("not a member of channel {}", channel),
+                )
+            })?;
+
+        let found = state
+            .db
+            .find_channel(channel)
+            .await
+            .map_err(internal_error)?
+            .unwrap_or(Channel {
+                channel: channel.to_owned(),
+                ..Default::default()
+            });
+
+        Ok(Self {
+            user: user.to_owned(),
+            member,
+            channel: found,
+        })
+    }
+
+    pub fn role(&self) -> Role {
+        self.member.role()
+    }
+
+    /// Errors unless the member's role is at least `role`.
+    pub fn require(&self, role: Role) -> Result<(), ApiError> {
+        if self.role() < role {
+            return Err((
+                StatusCode::FORBIDDEN,
+                format!(
+                    "{} has the {} role in channel {}, {} required",
+                    self.user.id,
+                    self.role(),
+                    self.channel.channel,
+                    role
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Editors manage the schedules they own, owners manage everyone's.
+    pub fn can_manage(&self, owner: &str) -> bool {
+        match self.role() {
+            Role::Owner => true,
+            Role::Editor => owner == self.user.id,
+            Role::Viewer => false,
+        }
+    }
+
+    pub fn require_manage(&self, owner: &str) -> Result<(), ApiError> {
+        if !self.can_manage(owner) {
+            return Err((
+                StatusCode::FORBIDDEN,
+                format!(
+                    "{} can't manage schedules of {} in channel {}",
+                    self.user.id, owner, self.channel.channel
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for Access {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let user = parts
+            .extensions
+            .get::<User>()
+            .cloned()
+            .ok_or((StatusCode::UNAUTHORIZED, "not signed in".to_owned()))?;
+
+        let channel = match Path::<HashMap<String, String>>::from_request_parts(parts, state).await
+        {
+            Ok(Path(mut params)) => params.remove("channel"),
+            Err(_) => None,
+        }
+        .unwrap_or_else(|| user.channel.to_owned());
+
+        Self::load(state, &user, &channel).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn access(role: Role) -> Access {
+        Access {
+            user: User {
+                id: "21kyu".to_owned(),
+                ..Default::default()
+            },
+            member: Member::new("21kyu", "home", role),
+            channel: Channel {
+                channel: "home".to_owned(),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_access() {
+        let viewer = access(Role::Viewer);
+        assert!(viewer.require(Role::Viewer).is_ok());
+        assert!(viewer.require(Role::Editor).is_err());
+        assert!(!viewer.can_manage("21kyu"));
+
+        let editor = access(Role::Editor);
+        assert!(editor.require(Role::Editor).is_ok());
+        assert!(editor.require(Role::Owner).is_err());
+        assert!(editor.can_manage("21kyu"));
+        assert!(!editor.can_manage("csj200045"));
+
+        let owner = access(Role::Owner);
+        assert!(owner.require(Role::Owner).is_ok());
+        assert!(owner.can_manage("csj200045"));
+    }
+}
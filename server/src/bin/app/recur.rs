@@ -0,0 +1,725 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use chrono::{Datelike, Duration, Months, NaiveDate, Weekday};
+use chrono_tz::Tz;
+
+use crate::sched::Sched;
+use crate::tz;
+
+/// Stops expansion of rules whose filters never match, like the 30th of
+/// February every year.
+const MAX_PERIODS: u32 = 10_000;
+
+/// The largest INTERVAL accepted, a thousand years for yearly rules.
+const MAX_INTERVAL: u32 = 1_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Freq {
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// A weekday, optionally with an ordinal inside the month or year, like
+/// `2TU` (second Tuesday) or `-1FR` (last Friday).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByDay {
+    pub nth: Option<i32>,
+    pub weekday: Weekday,
+}
+
+/// The subset of an RFC 5545 RRULE sched-bird understands. Rules are
+/// expanded on dates, the time of day comes from the schedule itself.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RRule {
+    pub freq: Freq,
+    pub interval: u32,
+    pub count: Option<u32>,
+    pub until: Option<NaiveDate>,
+    pub by_day: Vec<ByDay>,
+    pub by_month_day: Vec<i32>,
+    pub by_month: Vec<u32>,
+}
+
+fn weekday_from_str(s: &str) -> Result<Weekday> {
+    Ok(match s {
+        "MO" => Weekday::Mon,
+        "TU" => Weekday::Tue,
+        "WE" => Weekday::Wed,
+        "TH" => Weekday::Thu,
+        "FR" => Weekday::Fri,
+        "SA" => Weekday::Sat,
+        "SU" => Weekday::Sun,
+        _ => return Err(anyhow!("invalid weekday {}", s)),
+    })
+}
+
+fn weekday_to_str(weekday: Weekday) -> &'static str {
+    match weekday {
+        Weekday::Mon => "MO",
+        Weekday::Tue => "TU",
+        Weekday::Wed => "WE",
+        Weekday::Thu => "TH",
+        Weekday::Fri => "FR",
+        Weekday::Sat => "SA",
+        Weekday::Sun => "SU",
+    }
+}
+
+impl FromStr for ByDay {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.len() < 2 {
+            return Err(anyhow!("invalid BYDAY {}", s));
+        }
+        let (nth, weekday) = s.split_at(s.len() - 2);
+        let nth = match nth {
+            "" => None,
+            nth => {
+                let nth = nth
+                    .trim_start_matches('+')
+                    .parse::<i32>()
+                    .map_err(|_| anyhow!("invalid BYDAY {}", s))?;
+                if nth == 0 || nth.abs() > 53 {
+                    return Err(anyhow!("invalid BYDAY {}", s));
+                }
+                Some(nth)
+            }
+        };
+        Ok(Self {
+            nth,
+            weekday: weekday_from_str(weekday)?,
+        })
+    }
+}
+
+impl fmt::Display for ByDay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(nth) = self.nth {
+            write!(f, "{}", nth)?;
+        }
+        f.write_str(weekday_to_str(self.weekday))
+    }
+}
+
+fn parse_until(s: &str) -> Result<NaiveDate> {
+    let date = s.get(..8).ok_or_else(|| anyhow!("invalid UNTIL {}", s))?;
+    NaiveDate::parse_from_str(date, "%Y%m%d").map_err(|_| anyhow!("invalid UNTIL {}", s))
+}
+
+fn parse_list<T: FromStr>(name: &str, value: &str) -> Result<Vec<T>> {
+    value
+        .split(',')
+        .map(|v| {
+            v.parse::<T>()
+                .map_err(|_| anyhow!("invalid {} {}", name, v))
+        })
+        .collect()
+}
+
+impl FromStr for RRule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let s = s.strip_prefix("RRULE:").unwrap_or(s);
+
+        let mut freq = None;
+        let mut rule = RRule {
+            freq: Freq::Daily,
+            interval: 1,
+            count: None,
+            until: None,
+            by_day: vec![],
+            by_month_day: vec![],
+            by_month: vec![],
+        };
+
+        for part in s.split(';').filter(|p| !p.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| anyhow!("invalid rule part {}", part))?;
+            match key.to_ascii_uppercase().as_str() {
+                "FREQ" => {
+                    freq = Some(match value.to_ascii_uppercase().as_str() {
+                        "DAILY" => Freq::Daily,
+                        "WEEKLY" => Freq::Weekly,
+                        "MONTHLY" => Freq::Monthly,
+                        "YEARLY" => Freq::Yearly,
+                        _ => return Err(anyhow!("unsupported FREQ {}", value)),
+                    })
+                }
+                "INTERVAL" => {
+                    rule.interval = value
+                        .parse()
+                        .ok()
+                        .filter(|i| (1..=MAX_INTERVAL).contains(i))
+                        .ok_or_else(|| anyhow!("invalid INTERVAL {}", value))?
+                }
+                "COUNT" => {
+                    rule.count = Some(
+                        value
+                            .parse()
+                            .map_err(|_| anyhow!("invalid COUNT {}", value))?,
+                    )
+                }
+                "UNTIL" => rule.until = Some(parse_until(value)?),
+                "BYDAY" => rule.by_day = parse_list("BYDAY", &value.to_ascii_uppercase())?,
+                "BYMONTHDAY" => rule.by_month_day = parse_list("BYMONTHDAY", value)?,
+                "BYMONTH" => rule.by_month = parse_list("BYMONTH", value)?,
+                // Week numbering only matters for rules sched-bird does not
+                // expand, so the default of Monday is assumed.
+                "WKST" => {}
+                _ => return Err(anyhow!("unsupported rule part {}", key)),
+            }
+        }
+
+        rule.freq = freq.ok_or_else(|| anyhow!("missing FREQ"))?;
+
+        if rule.count.is_some() && rule.until.is_some() {
+            return Err(anyhow!("COUNT and UNTIL are mutually exclusive"));
+        }
+        if rule.by_month_day.iter().any(|d| *d == 0 || d.abs() > 31) {
+            return Err(anyhow!("invalid BYMONTHDAY"));
+        }
+        if rule.by_month.iter().any(|m| !(1..=12).contains(m)) {
+            return Err(anyhow!("invalid BYMONTH"));
+        }
+        if matches!(rule.freq, Freq::Daily | Freq::Weekly)
+            && rule.by_day.iter().any(|d| d.nth.is_some())
+        {
+            return Err(anyhow!("ordinal BYDAY needs a MONTHLY or YEARLY rule"));
+        }
+        // Without BYMONTH an ordinal counts weekdays of the whole year, which
+        // expansion doesn't do.
+        if rule.freq == Freq::Yearly
+            && rule.by_month.is_empty()
+            && rule.by_day.iter().any(|d| d.nth.is_some())
+        {
+            return Err(anyhow!("ordinal BYDAY in a YEARLY rule needs BYMONTH"));
+        }
+
+        Ok(rule)
+    }
+}
+
+impl fmt::Display for RRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let freq = match self.freq {
+            Freq::Daily => "DAILY",
+            Freq::Weekly => "WEEKLY",
+            Freq::Monthly => "MONTHLY",
+            Freq::Yearly => "YEARLY",
+        };
+        write!(f, "FREQ={}", freq)?;
+        if self.interval != 1 {
+            write!(f, ";INTERVAL={}", self.interval)?;
+        }
+        if let Some(count) = self.count {
+            write!(f, ";COUNT={}", count)?;
+        }
+        if let Some(until) = self.until {
+            write!(f, ";UNTIL={}", until.format("%Y%m%d"))?;
+        }
+        let join = |items: Vec<String>| items.join(",");
+        if !self.by_day.is_empty() {
+            write!(
+                f,
+                ";BYDAY={}",
+                join(self.by_day.iter().map(|d| d.to_string()).collect())
+            )?;
+        }
+        if !self.by_month_day.is_empty() {
+            write!(
+                f,
+                ";BYMONTHDAY={}",
+                join(self.by_month_day.iter().map(|d| d.to_string()).collect())
+            )?;
+        }
+        if !self.by_month.is_empty() {
+            write!(
+                f,
+                ";BYMONTH={}",
+                join(self.by_month.iter().map(|m| m.to_string()).collect())
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// `None` past the dates chrono can represent.
+fn days_in_month(year: i32, month: u32) -> Option<u32> {
+    let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+    let next = first.checked_add_months(Months::new(1))?;
+    Some((next - first).num_days() as u32)
+}
+
+/// Days of `year`/`month` matching a month day like `15` or `-1`.
+fn month_day(year: i32, month: u32, day: i32) -> Option<NaiveDate> {
+    let len = days_in_month(year, month)? as i32;
+    let day = if day < 0 { len + day + 1 } else { day };
+    if day < 1 || day > len {
+        return None;
+    }
+    NaiveDate::from_ymd_opt(year, month, day as u32)
+}
+
+/// Days of `year`/`month` matching a weekday like `TU`, `2TU` or `-1FR`.
+fn month_weekdays(year: i32, month: u32, by_day: &ByDay) -> Vec<NaiveDate> {
+    let days = (1..=days_in_month(year, month).unwrap_or_default())
+        .filter_map(|d| NaiveDate::from_ymd_opt(year, month, d))
+        .filter(|d| d.weekday() == by_day.weekday)
+        .collect::<Vec<_>>();
+
+    match by_day.nth {
+        None => days,
+        Some(nth) if nth > 0 => days.get(nth as usize - 1).copied().into_iter().collect(),
+        Some(nth) => days
+            .len()
+            .checked_sub(nth.unsigned_abs() as usize)
+            .and_then(|i| days.get(i).copied())
+            .into_iter()
+            .collect(),
+    }
+}
+
+impl RRule {
+    /// Candidate dates of the `n`th period after the one holding `start`, or
+    /// `None` once the period lies past the dates chrono can represent.
+    fn period(&self, start: NaiveDate, n: u32) -> Option<Vec<NaiveDate>> {
+        let step = n.checked_mul(self.interval)?;
+        let in_months =
+            |d: &NaiveDate| self.by_month.is_empty() || self.by_month.contains(&d.month());
+        let in_weekdays = |d: &NaiveDate| {
+            self.by_day.is_empty() || self.by_day.iter().any(|b| b.weekday == d.weekday())
+        };
+
+        let mut days = match self.freq {
+            Freq::Daily => {
+                let day = start.checked_add_signed(Duration::days(step as i64))?;
+                vec![day]
+                    .into_iter()
+                    .filter(|d| in_months(d) && in_weekdays(d))
+                    .collect()
+            }
+            Freq::Weekly => {
+                let monday = start
+                    .checked_sub_signed(Duration::days(
+                        start.weekday().num_days_from_monday() as i64
+                    ))?
+                    .checked_add_signed(Duration::weeks(step as i64))?;
+                let weekdays = if self.by_day.is_empty() {
+                    vec![start.weekday()]
+                } else {
+                    self.by_day.iter().map(|b| b.weekday).collect()
+                };
+                weekdays
+                    .into_iter()
+                    .filter_map(|w| {
+                        monday.checked_add_signed(Duration::days(w.num_days_from_monday() as i64))
+                    })
+                    .filter(in_months)
+                    .collect()
+            }
+            Freq::Monthly => {
+                let first = start
+                    .with_day(1)
+                    .unwrap()
+                    .checked_add_months(Months::new(step))?;
+                if !in_months(&first) {
+                    return Some(vec![]);
+                }
+                self.month_days(start, first.year(), first.month())
+            }
+            Freq::Yearly => {
+                let year = start.year().checked_add(i32::try_from(step).ok()?)?;
+                NaiveDate::from_ymd_opt(year, 12, 31)?;
+                let months = if self.by_month.is_empty() {
+                    vec![start.month()]
+                } else {
+                    self.by_month.clone()
+                };
+                months
+                    .into_iter()
+                    .flat_map(|m| self.month_days(start, year, m))
+                    .collect()
+            }
+        };
+
+        days.sort();
+        days.dedup();
+        Some(days)
+    }
+
+    fn month_days(&self, start: NaiveDate, year: i32, month: u32) -> Vec<NaiveDate> {
+        if !self.by_month_day.is_empty() {
+            let days = self
+                .by_month_day
+                .iter()
+                .filter_map(|d| month_day(year, month, *d));
+            // BYDAY narrows BYMONTHDAY down when both are given.
+            return days
+                .filter(|d| {
+                    self.by_day.is_empty() || self.by_day.iter().any(|b| b.weekday == d.weekday())
+                })
+                .collect();
+        }
+        if !self.by_day.is_empty() {
+            return self
+                .by_day
+                .iter()
+                .flat_map(|b| month_weekdays(year, month, b))
+                .collect();
+        }
+        month_day(year, month, start.day() as i32)
+            .into_iter()
+            .collect()
+    }
+
+    /// The period after the one holding `start` that holds `from`, so
+    /// expansion can skip the periods before it.
+    fn period_of(&self, start: NaiveDate, from: NaiveDate) -> u32 {
+        if from <= start {
+            return 0;
+        }
+        let units = match self.freq {
+            Freq::Daily => (from - start).num_days(),
+            Freq::Weekly => {
+                let monday = |d: NaiveDate| d.week(Weekday::Mon).first_day();
+                (monday(from) - monday(start)).num_weeks()
+            }
+            Freq::Monthly => {
+                (from.year() - start.year()) as i64 * 12 + from.month() as i64
+                    - start.month() as i64
+            }
+            Freq::Yearly => (from.year() - start.year()) as i64,
+        };
+        u32::try_from(units / self.interval as i64).unwrap_or(u32::MAX)
+    }
+
+    /// Occurrences starting at `start` that fall within `from..=to`, without
+    /// the `exdates`. `start` itself is always the first occurrence.
+    pub fn occurrences(
+        &self,
+        start: NaiveDate,
+        exdates: &[NaiveDate],
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> Vec<NaiveDate> {
+        let mut found = vec![];
+        let mut count = 0;
+
+        // Without COUNT the periods before `from` don't matter, so a long
+        // running series isn't cut off by MAX_PERIODS.
+        let first = match self.count {
+            Some(_) => 0,
+            None => self.period_of(start, from),
+        };
+
+        'periods: for n in first..first.saturating_add(MAX_PERIODS) {
+            let Some(mut days) = self.period(start, n) else {
+                break;
+            };
+            if n == 0 && !days.contains(&start) {
+                days.insert(0, start);
+            }
+
+            for day in days.into_iter().filter(|d| *d >= start) {
+                if day > to || self.until.is_some_and(|until| day > until) {
+                    break 'periods;
+                }
+                if self.count.is_some_and(|max| count >= max) {
+                    break 'periods;
+                }
+                count += 1;
+                if day >= from && !exdates.contains(&day) {
+                    found.push(day);
+                }
+            }
+        }
+
+        found
+    }
+}
+
+/// Expands a series into one schedule per occurrence within `from..=to`.
+/// Occurrences keep the `sid` of the series, so edits apply to all of them.
+/// `sched` must be in wall-clock time, the series repeats at the same local
+/// time across DST changes.
+pub fn expand(sched: &Sched, from: NaiveDate, to: NaiveDate) -> Result<Vec<Sched>> {
+    let rule = match &sched.rrule {
+        Some(rule) => rule.parse::<RRule>()?,
+        None => return Ok(vec![sched.clone()]),
+    };
+
+    Ok(rule
+        .occurrences(sched.date_at, &sched.exdates, from, to)
+        .into_iter()
+        .map(|date_at| Sched {
+            date_at,
+            ..sched.clone()
+        })
+        .collect())
+}
+
+/// Expands a stored series on the dates of the zone it was written in and
+/// returns the occurrences falling on `from..=to` in wall-clock time of `tz`.
+/// A weekly series on Tuesday mornings in Seoul stays on Monday evenings
+/// for a viewer in New York.
+pub fn expand_stored(series: &Sched, tz: Tz, from: NaiveDate, to: NaiveDate) -> Result<Vec<Sched>> {
+    let zone = tz::zone(series, tz);
+    let own = tz::to_local(series, zone);
+    // A day either side covers where the zones' dates differ.
+    let occurrences = expand(
+        &own,
+        from.pred_opt().unwrap_or(from),
+        to.succ_opt().unwrap_or(to),
+    )?;
+
+    Ok(occurrences
+        .iter()
+        .map(|occurrence| tz::to_local(&tz::to_utc(occurrence, zone), tz))
+        .filter(|occurrence| occurrence.date_at >= from && occurrence.date_at <= to)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn dates(rule: &str, start: NaiveDate, to: NaiveDate) -> Vec<NaiveDate> {
+        rule.parse::<RRule>()
+            .unwrap()
+            .occurrences(start, &[], start, to)
+    }
+
+    #[test]
+    fn test_every_other_tuesday() {
+        let days = dates(
+            "FREQ=WEEKLY;INTERVAL=2;BYDAY=TU",
+            date(2023, 7, 4),
+            date(2023, 8, 31),
+        );
+
+        assert_eq!(
+            days,
+            vec![
+                date(2023, 7, 4),
+                date(2023, 7, 18),
+                date(2023, 8, 1),
+                date(2023, 8, 15),
+                date(2023, 8, 29)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_weekly_multiple_days_with_count() {
+        let days = dates(
+            "FREQ=WEEKLY;BYDAY=MO,WE;COUNT=4",
+            date(2023, 7, 3),
+            date(2023, 12, 31),
+        );
+
+        assert_eq!(
+            days,
+            vec![
+                date(2023, 7, 3),
+                date(2023, 7, 5),
+                date(2023, 7, 10),
+                date(2023, 7, 12)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_monthly_nth_weekday() {
+        let second_tuesday = dates(
+            "FREQ=MONTHLY;BYDAY=2TU",
+            date(2023, 7, 11),
+            date(2023, 9, 30),
+        );
+        assert_eq!(
+            second_tuesday,
+            vec![date(2023, 7, 11), date(2023, 8, 8), date(2023, 9, 12)]
+        );
+
+        let last_friday = dates(
+            "FREQ=MONTHLY;BYDAY=-1FR",
+            date(2023, 6, 30),
+            date(2023, 8, 31),
+        );
+        assert_eq!(
+            last_friday,
+            vec![date(2023, 6, 30), date(2023, 7, 28), date(2023, 8, 25)]
+        );
+    }
+
+    #[test]
+    fn test_monthly_skips_missing_days() {
+        let days = dates("FREQ=MONTHLY", date(2023, 1, 31), date(2023, 5, 31));
+
+        assert_eq!(
+            days,
+            vec![date(2023, 1, 31), date(2023, 3, 31), date(2023, 5, 31)]
+        );
+    }
+
+    #[test]
+    fn test_yearly_until() {
+        let days = dates(
+            "FREQ=YEARLY;UNTIL=20250630T000000Z",
+            date(2023, 6, 30),
+            date(2030, 1, 1),
+        );
+
+        assert_eq!(
+            days,
+            vec![date(2023, 6, 30), date(2024, 6, 30), date(2025, 6, 30)]
+        );
+    }
+
+    #[test]
+    fn test_stops_at_the_end_of_time() {
+        let end = NaiveDate::MAX;
+        for rule in [
+            "FREQ=DAILY",
+            "FREQ=WEEKLY;BYDAY=MO,SU",
+            "FREQ=MONTHLY;BYMONTHDAY=-1",
+        ] {
+            let days = dates(
+                &format!("{};INTERVAL=1000", rule),
+                date(262_000, 1, 31),
+                end,
+            );
+            assert!(days.len() > 1 && days.len() < 100);
+        }
+
+        let days = dates("FREQ=YEARLY;INTERVAL=1000", date(2023, 7, 3), end);
+        assert_eq!(days.last().unwrap().year() % 1000, 23);
+    }
+
+    #[test]
+    fn test_long_running_series() {
+        let rule = "FREQ=DAILY".parse::<RRule>().unwrap();
+        let from = date(2024, 3, 1);
+        let days = rule.occurrences(date(1994, 1, 1), &[], from, date(2024, 3, 3));
+        assert_eq!(days, vec![from, date(2024, 3, 2), date(2024, 3, 3)]);
+
+        let rule = "FREQ=WEEKLY;INTERVAL=3;BYDAY=MO,FR"
+            .parse::<RRule>()
+            .unwrap();
+        let days = rule.occurrences(date(1990, 1, 1), &[], from, date(2024, 3, 31));
+        let all = rule.occurrences(date(1990, 1, 1), &[], date(1990, 1, 1), date(2024, 3, 31));
+        assert!(!days.is_empty());
+        assert!(all.ends_with(&days));
+    }
+
+    #[test]
+    fn test_exdates_and_window() {
+        let rule = "FREQ=DAILY".parse::<RRule>().unwrap();
+
+        let days = rule.occurrences(
+            date(2023, 7, 1),
+            &[date(2023, 7, 4)],
+            date(2023, 7, 3),
+            date(2023, 7, 6),
+        );
+
+        assert_eq!(
+            days,
+            vec![date(2023, 7, 3), date(2023, 7, 5), date(2023, 7, 6)]
+        );
+    }
+
+    #[test]
+    fn test_expand_keeps_sid() {
+        let mut sched = Sched::new("home", "21kyu", "스터디", date(2023, 7, 4));
+        sched.rrule = Some("FREQ=WEEKLY;INTERVAL=2;BYDAY=TU".to_owned());
+        sched.exdates = vec![date(2023, 7, 18)];
+
+        let scheds = expand(&sched, date(2023, 7, 1), date(2023, 8, 10)).unwrap();
+
+        assert_eq!(
+            scheds.iter().map(|s| s.date_at).collect::<Vec<_>>(),
+            vec![date(2023, 7, 4), date(2023, 8, 1)]
+        );
+        assert!(scheds.iter().all(|s| s.sid == sched.sid));
+    }
+
+    #[test]
+    fn test_expand_stored_in_own_zone() {
+        let seoul = tz::parse_tz("Asia/Seoul").unwrap();
+        let new_york = tz::parse_tz("America/New_York").unwrap();
+
+        // Tuesdays 08:30 in Seoul are Mondays 23:30 in UTC.
+        let mut local = Sched::new("home", "21kyu", "스터디", date(2023, 7, 4));
+        local.set_time(crate::sched::SchedTime {
+            start: chrono::NaiveTime::from_hms_opt(8, 30, 0),
+            end: None,
+            all_day: false,
+        });
+        local.rrule = Some("FREQ=WEEKLY;BYDAY=TU".to_owned());
+        let stored = tz::to_utc(&local, seoul);
+        assert_eq!(stored.tz.as_deref(), Some("Asia/Seoul"));
+
+        let starts = |tz: Tz| {
+            expand_stored(&stored, tz, date(2023, 7, 1), date(2023, 7, 12))
+                .unwrap()
+                .iter()
+                .map(|s| (s.date_at, s.start_time.unwrap().format("%H:%M").to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            starts(seoul),
+            vec![
+                (date(2023, 7, 4), "08:30".to_owned()),
+                (date(2023, 7, 11), "08:30".to_owned())
+            ]
+        );
+        assert_eq!(
+            starts(Tz::UTC),
+            vec![
+                (date(2023, 7, 3), "23:30".to_owned()),
+                (date(2023, 7, 10), "23:30".to_owned())
+            ]
+        );
+        assert_eq!(
+            starts(new_york),
+            vec![
+                (date(2023, 7, 3), "19:30".to_owned()),
+                (date(2023, 7, 10), "19:30".to_owned())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_roundtrip_and_errors() {
+        let rule = "RRULE:FREQ=MONTHLY;INTERVAL=2;BYDAY=-1FR;COUNT=3"
+            .parse::<RRule>()
+            .unwrap();
+        assert_eq!(
+            rule.to_string(),
+            "FREQ=MONTHLY;INTERVAL=2;COUNT=3;BYDAY=-1FR"
+        );
+
+        assert!("INTERVAL=2".parse::<RRule>().is_err());
+        assert!("FREQ=HOURLY".parse::<RRule>().is_err());
+        assert!("FREQ=DAILY;INTERVAL=4294967295".parse::<RRule>().is_err());
+        assert!("FREQ=WEEKLY;BYDAY=2TU".parse::<RRule>().is_err());
+        assert!("FREQ=YEARLY;BYDAY=20MO".parse::<RRule>().is_err());
+        assert!("FREQ=YEARLY;BYMONTH=11;BYDAY=4TH".parse::<RRule>().is_ok());
+        assert!("FREQ=DAILY;COUNT=2;UNTIL=20230101"
+            .parse::<RRule>()
+            .is_err());
+    }
+}
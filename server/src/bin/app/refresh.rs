@@ -0,0 +1,69 @@
+use chrono::{Duration, Utc};
+use scylla::frame::value::Timestamp;
+use scylla::FromRow;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+/// How long a refresh token is good for. Every refresh issues a new one, so
+/// a session lasts as long as it is used at least this often.
+pub const REFRESH_MAX_AGE: i64 = 30 * 24 * 60 * 60;
+
+/// A refresh token, as stored in `ks.r` under the SHA-256 of its secret.
+/// Tokens rotated from the same sign-in share `session`; a used one shown
+/// again means it leaked, and the whole session is revoked.
+#[derive(Debug, Clone, PartialEq, FromRow)]
+pub struct RefreshToken {
+    pub hash: String,
+    pub session: Uuid,
+    pub id: String,
+    pub expires_at: Timestamp,
+    pub used: bool,
+}
+
+impl RefreshToken {
+    /// A new token for `id` in `session`, with the secret handed to the
+    /// client. Only the hash is stored.
+    pub fn issue(id: &str, session: Uuid) -> (String, Self) {
+        let secret = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+        let expires_at = Utc::now() + Duration::seconds(REFRESH_MAX_AGE);
+
+        let token = Self {
+            hash: hash(&secret),
+            session,
+            id: id.to_owned(),
+            expires_at: Timestamp(Duration::milliseconds(expires_at.timestamp_millis())),
+            used: false,
+        };
+        (secret, token)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at.0.num_milliseconds() <= Utc::now().timestamp_millis()
+    }
+}
+
+pub fn hash(secret: &str) -> String {
+    Sha256::digest(secret.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_issue() {
+        let session = Uuid::new_v4();
+        let (secret, token) = RefreshToken::issue("21kyu", session);
+
+        assert_eq!(token.hash, hash(&secret));
+        assert_ne!(token.hash, secret);
+        assert_eq!((token.id.as_str(), token.session), ("21kyu", session));
+        assert!(!token.used && !token.is_expired());
+
+        let (other, _) = RefreshToken::issue("21kyu", session);
+        assert_ne!(secret, other);
+    }
+}
@@ -0,0 +1,1716 @@
+use std::collections::HashSet;
+use std::ops::Range;
+
+use anyhow::{anyhow, Result};
+use chrono::{Datelike, Duration, Months, NaiveDate, NaiveTime, Timelike, Weekday};
+
+use crate::api::SchedPatch;
+use crate::gpt::{Intent, Question, SchedPayload, Target};
+use crate::recur::{ByDay, Freq, RRule};
+use crate::sched::Sched;
+use crate::user::User;
+
+/// Korean particles that may follow a date or time, like the `에` in `3시에`
+/// or the `로` in `3시로`.
+const PARTICLES: &[&str] = &[
+    "에는", "에도", "부터", "까지", "에서", "으로", "에", "엔", "은", "는", "과", "와", "쯤", "경",
+    "로",
+];
+
+/// Date and time words Korean writes without a space before what follows,
+/// like `내일오후3시`.
+const PREFIXES: &[&str] = &[
+    "다다음주",
+    "다음주",
+    "다음달",
+    "이번주",
+    "매주",
+    "격주",
+    "매일",
+    "매달",
+    "매월",
+    "매년",
+    "내일",
+    "모레",
+    "오늘",
+    "오전",
+    "오후",
+];
+
+/// English words that only lead into a date or time, like the `on` in `on
+/// Tuesday`.
+const FILLERS: &[&str] = &[
+    "at", "on", "in", "from", "by", "for", "until", "till", "to", "the", "@",
+];
+
+/// English verbs that start a request to change or cancel a schedule.
+const UPDATE_VERBS: &[&str] = &["move", "reschedule", "postpone", "change", "push"];
+const DELETE_VERBS: &[&str] = &["cancel", "delete", "remove"];
+
+/// Stems of the Korean verbs that end such a request, like `옮겨줘` and
+/// `취소해주세요`.
+const UPDATE_STEMS: &[&str] = &[
+    "옮겨", "옮기", "변경", "바꿔", "바꾸", "미뤄", "미루", "당겨", "당기",
+];
+const DELETE_STEMS: &[&str] = &["취소", "삭제", "지워", "지우"];
+
+/// English words that start a question, like `what's on next week?`.
+const QUESTION_WORDS: &[&str] = &[
+    "what", "what's", "whats", "when", "when's", "who", "is", "are", "am", "do", "does", "any",
+    "anything", "show", "list",
+];
+
+/// Stems of the Korean words that ask, like `뭐 있어?` or `언제 한가해?`.
+const QUESTION_STEMS: &[&str] = &[
+    "뭐",
+    "무슨",
+    "언제",
+    "누가",
+    "있어",
+    "있나",
+    "있니",
+    "있는지",
+    "있을까",
+    "알려",
+    "보여",
+    "한가",
+    "비어",
+    "비는",
+];
+
+/// Asking when someone is free rather than what is on.
+const FREE_WORDS: &[&str] = &["free", "available"];
+const FREE_STEMS: &[&str] = &["한가", "비어", "비는", "시간되", "시간돼"];
+
+/// Words for the one asking, as in `when am I free?` or `내 일정`.
+const SELF_WORDS: &[&str] = &["i", "me", "my", "나", "내", "제", "저"];
+
+/// Schedules a template answer lists before saying how many more there are.
+const MAX_LISTED: usize = 10;
+
+const WEEKDAYS: [Weekday; 5] = [
+    Weekday::Mon,
+    Weekday::Tue,
+    Weekday::Wed,
+    Weekday::Thu,
+    Weekday::Fri,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Unit {
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+    Year,
+}
+
+/// A time of day as written. Without `am`/`pm` or a 24-hour form, 1 to 6
+/// o'clock are taken as the afternoon.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Clock {
+    hour: u32,
+    minute: u32,
+    pm: Option<bool>,
+    /// Has a colon, `시` or `am`/`pm`, so it isn't just a number.
+    explicit: bool,
+    /// Written as `09:00` or `15시`, so never moved to the afternoon.
+    twenty_four: bool,
+}
+
+impl Clock {
+    fn time(&self) -> Option<NaiveTime> {
+        let hour = match self.pm {
+            Some(true) if self.hour < 12 => self.hour + 12,
+            Some(false) if self.hour == 12 => 0,
+            None if !self.twenty_four && (1..=6).contains(&self.hour) => self.hour + 12,
+            _ => self.hour,
+        };
+        NaiveTime::from_hms_opt(hour % 24, self.minute, 0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Date(NaiveDate),
+    /// A weekday in the upcoming days, or in the week this many weeks on.
+    Weekday(Weekday, Option<i64>),
+    Time(Clock, Option<Clock>),
+    Duration(i64),
+    Repeat(Freq, u32, Vec<Weekday>),
+}
+
+struct Token<'a> {
+    /// As written, for the title.
+    text: &'a str,
+    /// Lowercased, without punctuation or a trailing particle.
+    word: String,
+}
+
+impl<'a> Token<'a> {
+    fn new(text: &'a str) -> Self {
+        let mut word = text
+            .trim_matches(|c: char| matches!(c, ',' | '.' | '!' | '?' | '(' | ')' | '"' | '\''))
+            .to_lowercase();
+        if let Some(stem) = PARTICLES
+            .iter()
+            .find_map(|p| word.strip_suffix(p).filter(|stem| !stem.is_empty()))
+        {
+            word = stem.to_owned();
+        }
+        Self { text, word }
+    }
+}
+
+fn is_hangul(c: char) -> bool {
+    ('가'..='힣').contains(&c)
+}
+
+/// Splits what is written together but read apart: `3-4pm`, `10:00~11:00`,
+/// `6월30일` and `다음주화요일`.
+fn split(piece: &str) -> Vec<&str> {
+    let iso = piece.matches('-').count() == 2;
+    for (at, c) in piece.char_indices() {
+        let before = &piece[..at];
+        let after = &piece[at + c.len_utf8()..];
+        let range = c == '~'
+            || c == '-'
+                && !iso
+                && (before.ends_with(|c: char| c.is_ascii_digit())
+                    || before.ends_with("am")
+                    || before.ends_with("pm"))
+                && after.starts_with(|c: char| c.is_ascii_digit());
+        if range {
+            let mut pieces = split(before);
+            pieces.push(&piece[at..at + c.len_utf8()]);
+            pieces.extend(split(after));
+            return pieces.into_iter().filter(|p| !p.is_empty()).collect();
+        }
+    }
+
+    if let Some(prefix) = PREFIXES
+        .iter()
+        .find(|p| piece.len() > p.len() && piece.starts_with(*p))
+    {
+        let mut pieces = vec![&piece[..prefix.len()]];
+        pieces.extend(split(&piece[prefix.len()..]));
+        return pieces;
+    }
+
+    let mut prev = None;
+    for (at, c) in piece.char_indices() {
+        if c.is_ascii_digit() && prev.is_some_and(is_hangul) {
+            let mut pieces = vec![&piece[..at]];
+            pieces.extend(split(&piece[at..]));
+            return pieces;
+        }
+        prev = Some(c);
+    }
+    vec![piece]
+}
+
+fn tokenize(query: &str) -> Vec<Token<'_>> {
+    query
+        .split_whitespace()
+        .flat_map(split)
+        .map(Token::new)
+        .collect()
+}
+
+fn word<'a>(tokens: &'a [Token], i: usize) -> &'a str {
+    tokens.get(i).map(|t| t.word.as_str()).unwrap_or_default()
+}
+
+/// `2`, `two` or `두`, as in `2 weeks`, `two weeks` and `두 달`.
+fn number(word: &str) -> Option<u32> {
+    if let Ok(n) = word.parse() {
+        return Some(n);
+    }
+    Some(match word {
+        "a" | "an" | "one" | "한" | "하나" => 1,
+        "two" | "두" | "둘" => 2,
+        "three" | "세" | "셋" => 3,
+        "four" | "네" | "넷" => 4,
+        "five" | "다섯" => 5,
+        "six" | "여섯" => 6,
+        "seven" | "일곱" => 7,
+        "eight" | "여덟" => 8,
+        "nine" | "아홉" => 9,
+        "ten" | "열" => 10,
+        "eleven" | "열한" | "열하나" => 11,
+        "twelve" | "열두" | "열둘" => 12,
+        _ => return None,
+    })
+}
+
+/// Native Korean numbers as they are written before a counter, longest
+/// first.
+const KO_NUMBERS: &[&str] = &[
+    "열한", "열두", "다섯", "여섯", "일곱", "여덟", "아홉", "한", "두", "세", "네", "열",
+];
+
+fn unit(word: &str) -> Option<Unit> {
+    Some(match word {
+        "minute" | "minutes" | "min" | "mins" => Unit::Minute,
+        "hour" | "hours" | "hr" | "hrs" | "h" => Unit::Hour,
+        "day" | "days" => Unit::Day,
+        "week" | "weeks" | "wk" | "wks" => Unit::Week,
+        "month" | "months" => Unit::Month,
+        "year" | "years" => Unit::Year,
+        _ => return None,
+    })
+}
+
+/// A Korean counter and what follows it, like `주` and `후` in `2주후`.
+fn ko_unit(word: &str) -> Option<(Unit, &str)> {
+    [
+        ("시간", Unit::Hour),
+        ("주일", Unit::Week),
+        ("개월", Unit::Month),
+        ("분", Unit::Minute),
+        ("일", Unit::Day),
+        ("주", Unit::Week),
+        ("달", Unit::Month),
+        ("년", Unit::Year),
+        ("해", Unit::Year),
+    ]
+    .into_iter()
+    .find_map(|(counter, unit)| word.strip_prefix(counter).map(|rest| (unit, rest)))
+}
+
+/// An amount of time in one word, like `30min`, `2주후`, `이틀` or `한달`,
+/// with what follows it.
+fn count_word(word: &str) -> Option<(u32, Unit, &str)> {
+    for (days, n, unit) in [
+        ("하루", 1, Unit::Day),
+        ("이틀", 2, Unit::Day),
+        ("사흘", 3, Unit::Day),
+        ("나흘", 4, Unit::Day),
+        ("보름", 15, Unit::Day),
+        ("일주일", 1, Unit::Week),
+    ] {
+        if let Some(rest) = word.strip_prefix(days) {
+            return Some((n, unit, rest));
+        }
+    }
+
+    let digits = word.len() - word.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    if digits > 0 {
+        let n = word[..digits].parse().ok()?;
+        let rest = &word[digits..];
+        if let Some(unit) = unit(rest) {
+            return Some((n, unit, ""));
+        }
+        let (unit, rest) = ko_unit(rest)?;
+        return Some((n, unit, rest));
+    }
+
+    let (n, rest) = KO_NUMBERS
+        .iter()
+        .find_map(|k| Some((number(k)?, word.strip_prefix(k)?)))?;
+    match ko_unit(rest)? {
+        // `네일` is a nail, not four days.
+        (Unit::Minute | Unit::Day, _) => None,
+        (unit, rest) => Some((n, unit, rest)),
+    }
+}
+
+/// An amount of time in one or two tokens, with what follows it in the last.
+fn count<'a>(tokens: &'a [Token], i: usize) -> Option<(usize, u32, Unit, &'a str)> {
+    if let Some((n, unit, rest)) = count_word(word(tokens, i)) {
+        return Some((1, n, unit, rest));
+    }
+    let n = number(word(tokens, i))?;
+    let next = word(tokens, i + 1);
+    match unit(next) {
+        Some(unit) => Some((2, n, unit, "")),
+        None => ko_unit(next).map(|(unit, rest)| (2, n, unit, rest)),
+    }
+}
+
+fn add(date: NaiveDate, n: u32, unit: Unit) -> Option<NaiveDate> {
+    match unit {
+        Unit::Day => date.checked_add_signed(Duration::days(n.into())),
+        Unit::Week => date.checked_add_signed(Duration::weeks(n.into())),
+        Unit::Month => date.checked_add_months(Months::new(n)),
+        Unit::Year => date.checked_add_months(n.checked_mul(12).map(Months::new)?),
+        Unit::Minute | Unit::Hour => None,
+    }
+}
+
+fn month(word: &str) -> Option<u32> {
+    Some(match word {
+        "january" | "jan" => 1,
+        "february" | "feb" => 2,
+        "march" | "mar" => 3,
+        "april" | "apr" => 4,
+        "may" => 5,
+        "june" | "jun" => 6,
+        "july" | "jul" => 7,
+        "august" | "aug" => 8,
+        "september" | "sep" | "sept" => 9,
+        "october" | "oct" => 10,
+        "november" | "nov" => 11,
+        "december" | "dec" => 12,
+        _ => return None,
+    })
+}
+
+/// `30` or `30th`.
+fn day(word: &str) -> Option<u32> {
+    let digits = ["st", "nd", "rd", "th"]
+        .iter()
+        .find_map(|s| word.strip_suffix(s))
+        .unwrap_or(word);
+    digits.parse().ok().filter(|d| (1..=31).contains(d))
+}
+
+fn weekday(word: &str) -> Option<Weekday> {
+    Some(match word.strip_suffix('s').unwrap_or(word) {
+        "monday" | "mon" | "월요일" => Weekday::Mon,
+        "tuesday" | "tue" | "tues" | "화요일" => Weekday::Tue,
+        "wednesday" | "wed" | "수요일" => Weekday::Wed,
+        "thursday" | "thu" | "thur" | "thurs" | "목요일" => Weekday::Thu,
+        "friday" | "fri" | "금요일" => Weekday::Fri,
+        "saturday" | "sat" | "토요일" => Weekday::Sat,
+        "sunday" | "sun" | "일요일" => Weekday::Sun,
+        _ => return None,
+    })
+}
+
+/// `every other Tuesday`, `every 2 weeks`, `daily`, `매주 월요일`,
+/// `화요일마다` or `격주`.
+fn repeat(tokens: &[Token], i: usize) -> Option<(usize, Part)> {
+    let w = word(tokens, i);
+    let (mut n, freq, interval, mut weekdays) = match w {
+        "daily" | "매일" => (1, Freq::Daily, 1, vec![]),
+        "weekly" | "매주" => (1, Freq::Weekly, 1, vec![]),
+        "격주" => (1, Freq::Weekly, 2, vec![]),
+        "monthly" | "매달" | "매월" => (1, Freq::Monthly, 1, vec![]),
+        "yearly" | "annually" | "매년" | "매해" => (1, Freq::Yearly, 1, vec![]),
+        "평일마다" => (1, Freq::Weekly, 1, WEEKDAYS.to_vec()),
+        "every" => {
+            let (skip, interval) = match word(tokens, i + 1) {
+                "other" => (2, 2),
+                _ => (1, 1),
+            };
+            let next = word(tokens, i + skip);
+            match (next, weekday(next), count(tokens, i + skip)) {
+                ("day", ..) => (skip + 1, Freq::Daily, interval, vec![]),
+                ("week", ..) => (skip + 1, Freq::Weekly, interval, vec![]),
+                ("month", ..) => (skip + 1, Freq::Monthly, interval, vec![]),
+                ("year", ..) => (skip + 1, Freq::Yearly, interval, vec![]),
+                ("weekday" | "weekdays", ..) => {
+                    (skip + 1, Freq::Weekly, interval, WEEKDAYS.to_vec())
+                }
+                (_, Some(weekday), _) => (skip + 1, Freq::Weekly, interval, vec![weekday]),
+                (_, None, Some((m, n, unit, ""))) if interval == 1 => {
+                    (skip + m, freq(unit)?, n, vec![])
+                }
+                _ => return None,
+            }
+        }
+        _ => {
+            if let Some(weekday) = w.strip_suffix("마다").and_then(weekday) {
+                (1, Freq::Weekly, 1, vec![weekday])
+            } else {
+                match count(tokens, i)? {
+                    (m, n, unit, "마다") => (m, freq(unit)?, n, vec![]),
+                    _ => return None,
+                }
+            }
+        }
+    };
+
+    // `매주 월요일과 수요일`, `every Monday, Wednesday and Friday`.
+    if freq == Freq::Weekly {
+        loop {
+            let next = word(tokens, i + n);
+            if let Some(weekday) = weekday(next.strip_suffix("마다").unwrap_or(next)) {
+                weekdays.push(weekday);
+            } else if matches!(next, "평일" | "weekday" | "weekdays") {
+                weekdays.extend(WEEKDAYS);
+            } else if !matches!(next, "and" | "&") || weekday(word(tokens, i + n + 1)).is_none() {
+                break;
+            }
+            n += 1;
+        }
+    }
+    Some((n, Part::Repeat(freq, interval, weekdays)))
+}
+
+fn freq(unit: Unit) -> Option<Freq> {
+    match unit {
+        Unit::Day => Some(Freq::Daily),
+        Unit::Week => Some(Freq::Weekly),
+        Unit::Month => Some(Freq::Monthly),
+        Unit::Year => Some(Freq::Yearly),
+        Unit::Minute | Unit::Hour => None,
+    }
+}
+
+/// `in two weeks`, `3 days later`, `2주 후` or `이틀 뒤`.
+fn relative(tokens: &[Token], i: usize, today: NaiveDate) -> Option<(usize, Part)> {
+    let after = |rest: &str| matches!(rest, "후" | "뒤" | "이후");
+
+    if matches!(word(tokens, i), "in" | "after") {
+        let (n, amount, unit, _) = count(tokens, i + 1)?;
+        return Some((1 + n, Part::Date(add(today, amount, unit)?)));
+    }
+
+    let (n, amount, unit, rest) = count(tokens, i)?;
+    let date = add(today, amount, unit)?;
+    if after(rest) {
+        return Some((n, Part::Date(date)));
+    }
+    match (word(tokens, i + n), word(tokens, i + n + 1)) {
+        ("later", _) => Some((n + 1, Part::Date(date))),
+        ("from", "now") => Some((n + 2, Part::Date(date))),
+        (next, _) if rest.is_empty() && after(next) => Some((n + 1, Part::Date(date))),
+        _ => None,
+    }
+}
+
+/// `for 30 minutes`, `1시간` or `30분 동안`.
+fn duration(tokens: &[Token], i: usize) -> Option<(usize, Part)> {
+    let minutes = |amount: u32, unit| match unit {
+        Unit::Minute => Some(i64::from(amount)),
+        Unit::Hour => Some(i64::from(amount) * 60),
+        _ => None,
+    };
+
+    if word(tokens, i) == "for" {
+        let (n, amount, unit, _) = count(tokens, i + 1)?;
+        return Some((1 + n, Part::Duration(minutes(amount, unit)?)));
+    }
+
+    let (n, amount, unit, rest) = count(tokens, i)?;
+    let minutes = minutes(amount, unit)?;
+    match rest {
+        "동안" | "간" => Some((n, Part::Duration(minutes))),
+        "" if word(tokens, i + n) == "동안" => Some((n + 1, Part::Duration(minutes))),
+        "" if unit == Unit::Hour => Some((n, Part::Duration(minutes))),
+        _ => None,
+    }
+}
+
+fn ymd(year: i32, month: u32, day: u32) -> Option<NaiveDate> {
+    NaiveDate::from_ymd_opt(year, month, day)
+}
+
+/// `2023-06-30`, `6/30`, `2024년 6월 30일`, `June 30th` or `30 June 2024`.
+/// Without a year it is this year's.
+fn date(tokens: &[Token], i: usize, today: NaiveDate) -> Option<(usize, Part)> {
+    let w = word(tokens, i);
+    let found = |n, date| Some((n, Part::Date(date?)));
+
+    for format in ["%Y-%m-%d", "%Y/%m/%d", "%Y.%m.%d"] {
+        if let Ok(date) = NaiveDate::parse_from_str(w, format) {
+            return Some((1, Part::Date(date)));
+        }
+    }
+    if let Some((m, d)) = w.split_once('/') {
+        if let (Ok(m), Ok(d)) = (m.parse(), d.parse()) {
+            return found(1, ymd(today.year(), m, d));
+        }
+    }
+
+    let (n, year) = match w {
+        "올해" | "금년" => (1, today.year()),
+        "내년" => (1, today.year() + 1),
+        _ => match w.strip_suffix('년').and_then(|y| y.parse().ok()) {
+            Some(year) if year >= 1000 => (1, year),
+            _ => (0, today.year()),
+        },
+    };
+    let ko_month = word(tokens, i + n)
+        .strip_suffix('월')
+        .and_then(|m| m.parse().ok());
+    let ko_day = word(tokens, i + n + 1)
+        .strip_suffix('일')
+        .and_then(|d| d.parse().ok());
+    if let (Some(m), Some(d)) = (ko_month, ko_day) {
+        return found(n + 2, ymd(year, m, d));
+    }
+
+    let year_at = |j: usize| match word(tokens, j).parse::<i32>() {
+        Ok(year) if year >= 1000 => (1, year),
+        _ => (0, today.year()),
+    };
+    if let (Some(m), Some(d)) = (month(w), day(word(tokens, i + 1))) {
+        let (y, year) = year_at(i + 2);
+        return found(2 + y, ymd(year, m, d));
+    }
+    if let Some(d) = day(w) {
+        let of = usize::from(word(tokens, i + 1) == "of");
+        if let Some(m) = month(word(tokens, i + 1 + of)) {
+            let (y, year) = year_at(i + 2 + of);
+            return found(2 + of + y, ymd(year, m, d));
+        }
+    }
+    None
+}
+
+/// `15일`: that day this month, or next month once it has passed.
+fn day_of_month(tokens: &[Token], i: usize, today: NaiveDate) -> Option<(usize, Part)> {
+    let d = word(tokens, i).strip_suffix('일')?.parse().ok()?;
+    let date = ymd(today.year(), today.month(), d)
+        .filter(|date| *date >= today)
+        .or_else(|| {
+            ymd(today.year(), today.month(), 1)?
+                .checked_add_months(Months::new(1))?
+                .with_day(d)
+        })?;
+    Some((1, Part::Date(date)))
+}
+
+/// `today`, `tomorrow`, `모레`, `next week` or `다음달`.
+fn named_day(tokens: &[Token], i: usize, today: NaiveDate) -> Option<(usize, Part)> {
+    let (n, days) = match (word(tokens, i), word(tokens, i + 1), word(tokens, i + 2)) {
+        ("today" | "tonight" | "오늘" | "금일", ..) => (1, 0),
+        ("tomorrow" | "tmr" | "tmrw" | "내일", ..) => (1, 1),
+        ("day", "after", "tomorrow") => (3, 2),
+        ("모레" | "내일모레", ..) => (1, 2),
+        ("글피", ..) => (1, 3),
+        ("next", "week", _) | ("다음", "주", _) => (2, 7),
+        ("다음주" | "담주", ..) => (1, 7),
+        ("다다음주", ..) => (1, 14),
+        ("next", "month", _) | ("다음", "달", _) => {
+            return Some((2, Part::Date(add(today, 1, Unit::Month)?)))
+        }
+        ("다음달" | "담달", ..) => return Some((1, Part::Date(add(today, 1, Unit::Month)?))),
+        ("next", "year", _) => return Some((2, Part::Date(add(today, 1, Unit::Year)?))),
+        ("내년", ..) => return Some((1, Part::Date(add(today, 1, Unit::Year)?))),
+        _ => return None,
+    };
+    Some((n, Part::Date(today + Duration::days(days))))
+}
+
+/// `Tuesday`, `this Friday`, `next Tuesday`, `Tuesday next week` or
+/// `다음주 화요일`. `next` is the week after this one.
+fn named_weekday(tokens: &[Token], i: usize) -> Option<(usize, Part)> {
+    let (mut n, mut week) = match (word(tokens, i), word(tokens, i + 1)) {
+        ("this" | "next", "week") | ("이번" | "다음", "주") => (2, None),
+        ("this" | "이번주" | "금주", _) => (1, Some(0)),
+        ("next" | "다음주" | "담주", _) => (1, Some(1)),
+        ("다다음주", _) => (1, Some(2)),
+        ("coming" | "on", _) => (1, None),
+        _ => (0, None),
+    };
+    if n == 2 {
+        week = Some(i64::from(matches!(word(tokens, i), "next" | "다음")));
+    }
+
+    let weekday = weekday(word(tokens, i + n))?;
+    n += 1;
+    if week.is_none() {
+        match (word(tokens, i + n), word(tokens, i + n + 1)) {
+            ("next", "week") => (n, week) = (n + 2, Some(1)),
+            ("this", "week") => (n, week) = (n + 2, Some(0)),
+            _ => {}
+        }
+    }
+    Some((n, Part::Weekday(weekday, week)))
+}
+
+fn meridiem(word: &str) -> Option<bool> {
+    match word {
+        "am" | "a.m" => Some(false),
+        "pm" | "p.m" => Some(true),
+        _ => None,
+    }
+}
+
+/// One time of day. Bare numbers only count where a time is expected.
+fn clock(tokens: &[Token], i: usize, bare: bool) -> Option<(usize, Clock)> {
+    let (mut n, pm) = match word(tokens, i) {
+        "오전" | "아침" | "새벽" => (1, Some(false)),
+        "오후" | "저녁" | "밤" | "낮" => (1, Some(true)),
+        _ => (0, None),
+    };
+    let w = word(tokens, i + n);
+    let at = |hour, minute| Clock {
+        hour,
+        minute,
+        pm: None,
+        explicit: true,
+        twenty_four: true,
+    };
+
+    match w {
+        "noon" | "정오" => return Some((n + 1, at(12, 0))),
+        "midnight" | "자정" => return Some((n + 1, at(0, 0))),
+        _ => {}
+    }
+
+    // 3시, 3시 30분, 3시반, 세시
+    if let Some((hour, half)) = w
+        .strip_suffix("시반")
+        .map(|h| (h, true))
+        .or_else(|| w.strip_suffix('시').map(|h| (h, false)))
+    {
+        let hour = number(hour).filter(|h| *h <= 24)?;
+        n += 1;
+        let minute = if half {
+            30
+        } else if word(tokens, i + n) == "반" {
+            n += 1;
+            30
+        } else if let Some(minute) = word(tokens, i + n)
+            .strip_suffix('분')
+            .and_then(|m| m.parse().ok())
+            .filter(|m| *m < 60)
+        {
+            n += 1;
+            minute
+        } else {
+            0
+        };
+        let clock = Clock {
+            hour,
+            minute,
+            pm,
+            explicit: true,
+            twenty_four: hour > 12,
+        };
+        return Some((n, clock));
+    }
+    if pm.is_some() {
+        return None;
+    }
+
+    // 3pm, 3:30 p.m., 15:00, 3
+    let (digits, mut pm) = ["am", "pm", "a.m", "p.m"]
+        .iter()
+        .find_map(|m| Some((w.strip_suffix(m)?, meridiem(m))))
+        .unwrap_or((w, None));
+    n += 1;
+    if pm.is_none() {
+        pm = meridiem(word(tokens, i + n));
+        if pm.is_some() || matches!(word(tokens, i + n), "o'clock" | "oclock") {
+            n += 1;
+        }
+    }
+    let (hour, minute) = match digits.split_once(':') {
+        Some((h, m)) if m.len() == 2 => (h, m.parse().ok().filter(|m| *m < 60)?),
+        Some(_) => return None,
+        None => (digits, 0),
+    };
+    let explicit = pm.is_some() || digits.contains(':');
+    let clock = Clock {
+        hour: hour
+            .parse()
+            .ok()
+            .filter(|h| *h <= if pm.is_some() { 12 } else { 23 })?,
+        minute,
+        pm,
+        explicit,
+        twenty_four: hour.starts_with('0') || hour.parse::<u32>().ok()? > 12,
+    };
+    (explicit || bare).then_some((n, clock))
+}
+
+/// A time or a range of times: `3pm`, `at 3`, `3-4pm`, `from 10 to 11:30`,
+/// `오후 3시부터 5시까지`.
+fn time(tokens: &[Token], i: usize) -> Option<(usize, Part)> {
+    let lead = usize::from(matches!(word(tokens, i), "at" | "@" | "from"));
+    let (n, start) = clock(tokens, i + lead, lead == 1 || is_range(tokens, i + lead))?;
+
+    let mut j = i + lead + n;
+    let sep = matches!(
+        word(tokens, j),
+        "-" | "~" | "to" | "until" | "till" | "through"
+    );
+    if sep {
+        j += 1;
+    }
+    if sep || start.explicit {
+        if let Some((m, end)) = clock(tokens, j, sep) {
+            if start.explicit || end.explicit {
+                return Some((j + m - i, Part::Time(start, Some(end))));
+            }
+        }
+    }
+    (start.explicit || lead == 1).then_some((lead + n, Part::Time(start, None)))
+}
+
+/// Whether a bare number at `i` starts a range ending in a proper time,
+/// like the `3` in `3-4pm`.
+fn is_range(tokens: &[Token], i: usize) -> bool {
+    matches!(word(tokens, i + 1), "-" | "~" | "to")
+        && clock(tokens, i + 2, false).is_some_and(|(_, end)| end.explicit)
+}
+
+/// Gives a range's times the same half of the day, so `3-4pm` starts at 3pm
+/// and `오후 3시부터 5시까지` ends at 5pm.
+fn range(mut start: Clock, mut end: Clock) -> (Option<NaiveTime>, Option<NaiveTime>) {
+    if end.pm.is_none() && !end.twenty_four {
+        end.pm = start.pm;
+    }
+    if start.pm.is_none() && !start.twenty_four && start.hour <= end.hour {
+        start.pm = end.pm;
+    }
+    let start = start.time();
+    let mut end = end.time();
+    if let (Some(s), Some(e)) = (start, end) {
+        if e < s && e.hour() < 12 && e + Duration::hours(12) > s {
+            end = Some(e + Duration::hours(12));
+        }
+    }
+    (start, end)
+}
+
+fn next_weekday(from: NaiveDate, weekday: Weekday) -> NaiveDate {
+    let days = (7 + weekday.num_days_from_monday() - from.weekday().num_days_from_monday()) % 7;
+    from + Duration::days(days.into())
+}
+
+/// The title is what no rule read, without the words around it that ask
+/// for a schedule, like `add` or `등록해줘`.
+fn title(tokens: &[Token], used: &[bool]) -> String {
+    let mut words = tokens
+        .iter()
+        .enumerate()
+        .filter(|(i, token)| {
+            let filler = FILLERS.contains(&token.word.as_str()) && used.get(i + 1) == Some(&true);
+            !used[*i] && !filler
+        })
+        .map(|(_, token)| token.text)
+        .collect::<Vec<_>>();
+
+    while words.len() > 1
+        && matches!(
+            words[0].to_lowercase().as_str(),
+            "add" | "schedule" | "book" | "create"
+        )
+    {
+        words.remove(0);
+    }
+    while let Some(last) = words.last() {
+        let last = last.trim_end_matches(|c: char| c.is_ascii_punctuation());
+        if matches!(last, "추가" | "등록" | "일정추가" | "일정등록")
+            || last.ends_with('줘')
+            || last.ends_with("주세요")
+        {
+            words.pop();
+        } else {
+            break;
+        }
+    }
+    words
+        .join(" ")
+        .trim_matches(|c: char| c.is_ascii_punctuation() || c.is_whitespace())
+        .to_owned()
+}
+
+/// Runs the rules over the tokens, giving each part with the tokens it was
+/// read from, and which tokens were read.
+fn read(tokens: &[Token], today: NaiveDate) -> (Vec<(Range<usize>, Part)>, Vec<bool>) {
+    let mut used = vec![false; tokens.len()];
+    let mut parts = vec![];
+
+    let mut i = 0;
+    while i < tokens.len() {
+        let found = repeat(tokens, i)
+            .or_else(|| relative(tokens, i, today))
+            .or_else(|| duration(tokens, i))
+            .or_else(|| date(tokens, i, today))
+            .or_else(|| named_weekday(tokens, i))
+            .or_else(|| named_day(tokens, i, today))
+            .or_else(|| time(tokens, i))
+            .or_else(|| day_of_month(tokens, i, today));
+        match found {
+            Some((n, part)) => {
+                used[i..i + n].iter_mut().for_each(|u| *u = true);
+                parts.push((i..i + n, part));
+                i += n;
+            }
+            None => i += 1,
+        }
+    }
+    (parts, used)
+}
+
+/// What some parts of a request say about when, with no date if none was
+/// mentioned.
+#[derive(Debug, Default, PartialEq)]
+struct When {
+    date: Option<NaiveDate>,
+    start_time: Option<NaiveTime>,
+    end_time: Option<NaiveTime>,
+    duration: Option<i64>,
+    rrule: Option<String>,
+}
+
+fn when(parts: impl IntoIterator<Item = Part>, today: NaiveDate) -> When {
+    let mut date = None;
+    let mut weekday = None;
+    let mut times = (None, None);
+    let mut minutes = None;
+    let mut rule = None;
+    for part in parts {
+        match part {
+            Part::Date(d) => date = date.or(Some(d)),
+            Part::Weekday(w, week) => weekday = weekday.or(Some((w, week))),
+            Part::Time(start, end) if times.0.is_none() => {
+                times = match end {
+                    Some(end) => range(start, end),
+                    None => (start.time(), None),
+                }
+            }
+            Part::Time(..) => {}
+            Part::Duration(m) => minutes = minutes.or(Some(m)),
+            // `weekly sync every Monday` repeats on Mondays.
+            Part::Repeat(freq, interval, weekdays) => {
+                if rule
+                    .as_ref()
+                    .is_none_or(|(_, _, days): &(_, _, Vec<_>)| days.is_empty())
+                {
+                    rule = Some((freq, interval, weekdays));
+                }
+            }
+        }
+    }
+
+    let date = match (date, weekday, &rule) {
+        (Some(date), ..) => Some(date),
+        (None, _, Some((_, _, weekdays))) if !weekdays.is_empty() => {
+            weekdays.iter().map(|w| next_weekday(today, *w)).min()
+        }
+        (None, Some((w, Some(week))), _) => {
+            let monday = today - Duration::days(today.weekday().num_days_from_monday().into());
+            Some(monday + Duration::weeks(week) + Duration::days(w.num_days_from_monday().into()))
+        }
+        (None, Some((w, None)), Some(_)) => Some(next_weekday(today, w)),
+        (None, Some((w, None)), None) => Some(next_weekday(today + Duration::days(1), w)),
+        (None, None, _) => None,
+    };
+
+    let rrule = rule.map(|(freq, interval, weekdays)| {
+        RRule {
+            freq,
+            interval,
+            count: None,
+            until: None,
+            by_day: weekdays
+                .into_iter()
+                .map(|weekday| ByDay { nth: None, weekday })
+                .collect(),
+            by_month_day: vec![],
+            by_month: vec![],
+        }
+        .to_string()
+    });
+
+    let (start_time, end_time) = times;
+    When {
+        date,
+        start_time,
+        end_time,
+        duration: minutes,
+        rrule,
+    }
+}
+
+/// The schedule to create. The rest of the request is the title.
+fn payload(
+    user: &User,
+    today: NaiveDate,
+    tokens: &[Token],
+    parts: Vec<(Range<usize>, Part)>,
+    used: &[bool],
+) -> Result<SchedPayload> {
+    if parts
+        .iter()
+        .all(|(_, part)| matches!(part, Part::Duration(_)))
+    {
+        return Err(anyhow!("no date or time in request"));
+    }
+    let when = when(parts.into_iter().map(|(_, part)| part), today);
+
+    let title = title(tokens, used);
+    if title.is_empty() {
+        return Err(anyhow!("no title in request"));
+    }
+
+    Ok(SchedPayload {
+        title,
+        date: when.date.unwrap_or(today),
+        owner: user.id.to_owned(),
+        channel: user.channel.to_owned(),
+        start_time: when.start_time,
+        end_time: when.end_time,
+        duration: when
+            .duration
+            .filter(|_| when.start_time.is_some() && when.end_time.is_none()),
+        all_day: Some(when.start_time.is_none()),
+        rrule: when.rrule,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Verb {
+    Update,
+    Delete,
+}
+
+/// The verb asking to change or cancel a schedule and where it is: first
+/// in English, last in Korean before any `주세요`.
+fn verb(tokens: &[Token]) -> Option<(usize, Verb)> {
+    let first = tokens.iter().position(|t| t.word != "please")?;
+    if UPDATE_VERBS.contains(&tokens[first].word.as_str()) {
+        return Some((first, Verb::Update));
+    }
+    if DELETE_VERBS.contains(&tokens[first].word.as_str()) {
+        return Some((first, Verb::Delete));
+    }
+
+    let last = tokens
+        .iter()
+        .rposition(|t| !matches!(t.word.as_str(), "주세요" | "줘" | "please"))?;
+    let word = tokens[last].word.as_str();
+    if UPDATE_STEMS.iter().any(|stem| word.starts_with(stem)) {
+        return Some((last, Verb::Update));
+    }
+    if DELETE_STEMS.iter().any(|stem| word.starts_with(stem)) {
+        return Some((last, Verb::Delete));
+    }
+    None
+}
+
+/// Where the new date or time starts in an update: after the last `to`
+/// in `move the picnic to July 2nd`, or at the run of parts ending in `로`
+/// in `봄소풍을 7월 2일로 옮겨줘`. Otherwise every part is a change.
+fn changes_at(tokens: &[Token], parts: &[(Range<usize>, Part)]) -> usize {
+    if let Some((range, _)) = parts
+        .iter()
+        .rev()
+        .find(|(range, _)| range.start > 0 && tokens[range.start - 1].word == "to")
+    {
+        return range.start;
+    }
+
+    let Some(last) = parts.iter().rposition(|(range, _)| {
+        tokens[range.end - 1]
+            .text
+            .trim_end_matches(|c: char| c.is_ascii_punctuation())
+            .ends_with('로')
+    }) else {
+        return 0;
+    };
+    let mut at = last;
+    while at > 0 && parts[at - 1].0.end == parts[at].0.start {
+        at -= 1;
+    }
+    parts[at].0.start
+}
+
+/// The words no rule read, as the user calls the schedule, without the
+/// object particle in `봄소풍을`.
+fn target_title(tokens: &[Token], used: &[bool]) -> String {
+    tokens
+        .iter()
+        .enumerate()
+        .filter(|(i, token)| {
+            let filler = FILLERS.contains(&token.word.as_str()) && used.get(i + 1) == Some(&true);
+            !used[*i] && !filler
+        })
+        .map(|(_, token)| {
+            let text = token.text.trim_matches(|c: char| c.is_ascii_punctuation());
+            ["을", "를"]
+                .iter()
+                .find_map(|p| text.strip_suffix(p).filter(|stem| !stem.is_empty()))
+                .unwrap_or(text)
+        })
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn is_question(query: &str, tokens: &[Token]) -> bool {
+    query.trim_end().ends_with('?')
+        || tokens
+            .first()
+            .is_some_and(|t| QUESTION_WORDS.contains(&t.word.as_str()))
+        || tokens
+            .iter()
+            .any(|t| QUESTION_STEMS.iter().any(|stem| t.word.starts_with(stem)))
+}
+
+/// `next week`, `이번달`, `this weekend` or `July`: the days from today on
+/// in a week or month, the upcoming one for a month name. Not when a day
+/// follows, as in `다음주 화요일` or `7월 3일`.
+fn span(tokens: &[Token], i: usize, today: NaiveDate) -> Option<(usize, NaiveDate, NaiveDate)> {
+    let monday = today - Duration::days(today.weekday().num_days_from_monday().into());
+    let week = |n: i64| {
+        let from = monday + Duration::weeks(n);
+        (from, from + Duration::days(6))
+    };
+    let month_of = |date: NaiveDate| {
+        let from = date.with_day(1)?;
+        Some((
+            from,
+            from.checked_add_months(Months::new(1))? - Duration::days(1),
+        ))
+    };
+
+    let (n, (from, to)) = match (word(tokens, i), word(tokens, i + 1)) {
+        ("this", "week") | ("이번", "주") => (2, week(0)),
+        ("이번주", _) => (1, week(0)),
+        ("next", "week") | ("다음", "주") => (2, week(1)),
+        ("다음주" | "담주", _) => (1, week(1)),
+        ("다다음주", _) => (1, week(2)),
+        ("this", "weekend") | ("이번", "주말") => (2, (monday + Duration::days(5), week(0).1)),
+        ("weekend" | "주말", _) => (1, (monday + Duration::days(5), week(0).1)),
+        ("this", "month") | ("이번", "달") => (2, month_of(today)?),
+        ("이번달", _) => (1, month_of(today)?),
+        ("next", "month") | ("다음", "달") => (2, month_of(add(today, 1, Unit::Month)?)?),
+        ("다음달" | "담달", _) => (1, month_of(add(today, 1, Unit::Month)?)?),
+        (w, next) => {
+            let m = month(w).or_else(|| w.strip_suffix('월')?.parse().ok())?;
+            if day(next).is_some() || next.ends_with('일') {
+                return None;
+            }
+            let year = today.year() + i32::from(m < today.month());
+            (1, month_of(ymd(year, m, 1)?)?)
+        }
+    };
+    if weekday(word(tokens, i + n)).is_some() {
+        return None;
+    }
+    Some((n, from.max(today), to))
+}
+
+fn question(
+    user: &User,
+    members: &[String],
+    today: NaiveDate,
+    tokens: &[Token],
+    parts: Vec<(Range<usize>, Part)>,
+) -> Question {
+    let (from, to) = (0..tokens.len())
+        .find_map(|i| span(tokens, i, today))
+        .map(|(_, from, to)| (from, to))
+        .or_else(|| {
+            let date = when(parts.into_iter().map(|(_, part)| part), today).date?;
+            Some((date, date))
+        })
+        .unwrap_or((today, today + Duration::days(6)));
+
+    let user = tokens.iter().find_map(|token| {
+        let word = token.word.as_str();
+        if SELF_WORDS.contains(&word) {
+            return Some(user.id.to_owned());
+        }
+        let name = ["'s", "님", "씨", "이", "가", "의"]
+            .iter()
+            .find_map(|suffix| word.strip_suffix(suffix))
+            .unwrap_or(word);
+        members
+            .iter()
+            .find(|member| member.to_lowercase() == word || member.to_lowercase() == name)
+            .cloned()
+    });
+    let free = tokens.iter().any(|token| {
+        FREE_WORDS.contains(&token.word.as_str())
+            || FREE_STEMS.iter().any(|stem| token.word.starts_with(stem))
+    });
+
+    Question {
+        from,
+        to,
+        user,
+        free,
+    }
+}
+
+fn day_name(date: NaiveDate, korean: bool) -> String {
+    if korean {
+        format!("{}월 {}일", date.month(), date.day())
+    } else {
+        date.format("%b %-d").to_string()
+    }
+}
+
+/// `10월 19일부터 10월 25일까지` or `from Oct 19 to Oct 25`.
+fn period(from: NaiveDate, to: NaiveDate, korean: bool) -> String {
+    match (from == to, korean) {
+        (true, true) => day_name(from, korean),
+        (true, false) => format!("on {}", day_name(from, korean)),
+        (false, true) => format!(
+            "{}부터 {}까지",
+            day_name(from, korean),
+            day_name(to, korean)
+        ),
+        (false, false) => format!(
+            "from {} to {}",
+            day_name(from, korean),
+            day_name(to, korean)
+        ),
+    }
+}
+
+/// Answers a question with a template, in Korean when it is asked in
+/// Korean: the schedules in its days, or the days without any.
+pub fn answer(query: &str, question: &Question, scheds: &[Sched]) -> String {
+    let korean = query.chars().any(is_hangul);
+    let when = period(question.from, question.to, korean);
+    let who = question.user.as_deref();
+    let scheds = scheds
+        .iter()
+        .filter(|sched| who.is_none_or(|who| sched.id == who))
+        .collect::<Vec<_>>();
+
+    if question.free {
+        let busy = scheds
+            .iter()
+            .map(|sched| sched.date_at)
+            .collect::<HashSet<_>>();
+        let mut runs: Vec<(NaiveDate, NaiveDate)> = vec![];
+        for date in question
+            .from
+            .iter_days()
+            .take_while(|date| *date <= question.to)
+        {
+            if busy.contains(&date) {
+                continue;
+            }
+            match runs.last_mut() {
+                Some((_, end)) if *end + Duration::days(1) == date => *end = date,
+                _ => runs.push((date, date)),
+            }
+        }
+        let runs = runs
+            .iter()
+            .map(|(from, to)| match (from == to, korean) {
+                (true, _) => day_name(*from, korean),
+                (false, true) => format!("{}~{}", day_name(*from, korean), day_name(*to, korean)),
+                (false, false) => format!("{}-{}", day_name(*from, korean), day_name(*to, korean)),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        let ko_who = who.map(|who| format!("{} ", who)).unwrap_or_default();
+        return match (korean, runs.is_empty(), who) {
+            (true, true, _) => format!("{} {}매일 일정이 있어요.", when, ko_who),
+            (true, false, _) => format!("{} {}일정이 없는 날: {}", when, ko_who, runs),
+            (false, true, Some(who)) => format!("{} has something every day {}.", who, when),
+            (false, true, None) => format!("Something is on every day {}.", when),
+            (false, false, Some(who)) => format!("{} is free on {}.", who, runs),
+            (false, false, None) => format!("Nothing is scheduled on {}.", runs),
+        };
+    }
+
+    let listed = scheds
+        .iter()
+        .take(MAX_LISTED)
+        .map(|sched| {
+            let time = sched
+                .start_time
+                .filter(|_| !sched.all_day)
+                .map(|start| format!(" {}", start.format("%H:%M")))
+                .unwrap_or_default();
+            format!(
+                "{}{} {}",
+                day_name(sched.date_at, korean),
+                time,
+                sched.sched
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    let more = scheds.len().saturating_sub(MAX_LISTED);
+
+    if korean {
+        let who = who.map(|who| format!("{} ", who)).unwrap_or_default();
+        return match (scheds.len(), more) {
+            (0, _) => format!("{} {}일정이 없어요.", when, who),
+            (n, 0) => format!("{} {}일정 {}개: {}", when, who, n, listed),
+            (n, more) => format!("{} {}일정 {}개: {} 외 {}개", when, who, n, listed, more),
+        };
+    }
+    let who = who.map(|who| format!(" for {}", who)).unwrap_or_default();
+    match (scheds.len(), more) {
+        (0, _) => format!("Nothing is scheduled{} {}.", who, when),
+        (1, _) => format!("1 schedule{} {}: {}.", who, when, listed),
+        (n, 0) => format!("{} schedules{} {}: {}.", n, who, when, listed),
+        (n, more) => format!(
+            "{} schedules{} {}: {} and {} more.",
+            n, who, when, listed, more
+        ),
+    }
+}
+
+/// Reads a Korean or English request without a language model, for when
+/// none is configured or it fails. Understands dates like `6월 30일`,
+/// `June 30`, `다음주 화요일`, `tomorrow` and `in two weeks`, times and
+/// ranges like `3pm`, `오후 3시 반` and `10:00-11:30`, durations and simple
+/// repeats like `매주 화요일` or `every other week`.
+///
+/// Requests like `봄소풍을 7월 2일로 옮겨줘` or `cancel my dentist
+/// appointment on friday` move or cancel a schedule, where the date and
+/// time before the new ones narrow down which one is meant.
+///
+/// Questions like `what's happening next week?` or `21kyu 7월에 언제 한가해?`
+/// are about the week, month or day mentioned, the coming seven days
+/// otherwise, and the member of `members` named.
+pub fn extract_intent(
+    user: &User,
+    members: &[String],
+    today: NaiveDate,
+    query: &str,
+) -> Result<Intent> {
+    let tokens = tokenize(query);
+    let (mut parts, mut used) = read(&tokens, today);
+    let Some((at, verb)) = verb(&tokens) else {
+        if is_question(query, &tokens) {
+            return Ok(Intent::Query(question(
+                user, members, today, &tokens, parts,
+            )));
+        }
+        return payload(user, today, &tokens, parts, &used).map(Intent::Create);
+    };
+    used[at] = true;
+    for (i, token) in tokens.iter().enumerate() {
+        if matches!(token.word.as_str(), "주세요" | "줘" | "please") {
+            used[i] = true;
+        }
+    }
+
+    let changes = match verb {
+        Verb::Update => {
+            let start = changes_at(&tokens, &parts);
+            let split = parts
+                .iter()
+                .position(|(range, _)| range.start >= start)
+                .unwrap_or(parts.len());
+            parts.split_off(split)
+        }
+        Verb::Delete => vec![],
+    };
+    let found = when(parts.into_iter().map(|(_, part)| part), today);
+    let target = Target {
+        title: target_title(&tokens, &used),
+        date: found.date,
+        start_time: found.start_time,
+    };
+    if target.is_empty() {
+        return Err(anyhow!("no schedule named in request"));
+    }
+    if verb == Verb::Delete {
+        return Ok(Intent::Delete { target });
+    }
+
+    let change = when(changes.into_iter().map(|(_, part)| part), today);
+    let changes = SchedPatch {
+        date_at: change.date,
+        start_time: change.start_time,
+        end_time: change.end_time,
+        duration: change.duration.filter(|_| change.end_time.is_none()),
+        rrule: change.rrule,
+        ..SchedPatch::default()
+    };
+    if changes == SchedPatch::default() {
+        return Err(anyhow!("no new date or time in request"));
+    }
+    Ok(Intent::Update { target, changes })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user() -> User {
+        User {
+            id: "21kyu".to_owned(),
+            channel: "home".to_owned(),
+            tz: None,
+        }
+    }
+
+    fn extract_sched(user: &User, today: NaiveDate, query: &str) -> Result<SchedPayload> {
+        match extract_intent(user, &[], today, query)? {
+            Intent::Create(payload) => Ok(payload),
+            intent => Err(anyhow!("not a new schedule: {:?}", intent)),
+        }
+    }
+
+    fn hm(hour: u32, minute: u32) -> Option<NaiveTime> {
+        NaiveTime::from_hms_opt(hour, minute, 0)
+    }
+
+    #[test]
+    fn test_corpus() {
+        // A Wednesday.
+        let today = NaiveDate::from_ymd_opt(2023, 6, 28).unwrap();
+        let corpus = [
+            ("6월 30일 봄소풍", (6, 30), None, None, None, "봄소풍"),
+            (
+                "다음주 화요일 오후 3시에 치과",
+                (7, 4),
+                hm(15, 0),
+                None,
+                None,
+                "치과",
+            ),
+            (
+                "내일오후3시30분 팀 회의",
+                (6, 29),
+                hm(15, 30),
+                None,
+                None,
+                "팀 회의",
+            ),
+            (
+                "7월 2일 오전 10시부터 11시 반까지 스터디",
+                (7, 2),
+                hm(10, 0),
+                hm(11, 30),
+                None,
+                "스터디",
+            ),
+            ("모레 5시 헬스", (6, 30), hm(17, 0), None, None, "헬스"),
+            ("3일 후 병원", (7, 1), None, None, None, "병원"),
+            ("15일에 회식", (7, 15), None, None, None, "회식"),
+            (
+                "금요일 점심 약속 등록해줘",
+                (6, 30),
+                None,
+                None,
+                None,
+                "점심 약속",
+            ),
+            (
+                "내년 1월 1일 새해 계획",
+                (1, 1),
+                None,
+                None,
+                None,
+                "새해 계획",
+            ),
+            (
+                "매주 화요일 저녁 7시 스터디",
+                (7, 4),
+                hm(19, 0),
+                None,
+                Some("FREQ=WEEKLY;BYDAY=TU"),
+                "스터디",
+            ),
+            (
+                "매월 25일 월세 내기",
+                (7, 25),
+                None,
+                None,
+                Some("FREQ=MONTHLY"),
+                "월세 내기",
+            ),
+            (
+                "tomorrow 3pm dentist",
+                (6, 29),
+                hm(15, 0),
+                None,
+                None,
+                "dentist",
+            ),
+            (
+                "team dinner in two weeks",
+                (7, 12),
+                None,
+                None,
+                None,
+                "team dinner",
+            ),
+            (
+                "Lunch with Mina tomorrow at noon",
+                (6, 29),
+                hm(12, 0),
+                None,
+                None,
+                "Lunch with Mina",
+            ),
+            ("picnic on June 30th", (6, 30), None, None, None, "picnic"),
+            (
+                "this friday 7pm movie night",
+                (6, 30),
+                hm(19, 0),
+                None,
+                None,
+                "movie night",
+            ),
+            (
+                "Add dentist appointment next monday at 9am",
+                (7, 3),
+                hm(9, 0),
+                None,
+                None,
+                "dentist appointment",
+            ),
+            (
+                "meeting on 7/4 at 9",
+                (7, 4),
+                hm(9, 0),
+                None,
+                None,
+                "meeting",
+            ),
+            ("in 3 days call mom", (7, 1), None, None, None, "call mom"),
+            (
+                "2023-07-15 10:00-11:30 workshop",
+                (7, 15),
+                hm(10, 0),
+                hm(11, 30),
+                None,
+                "workshop",
+            ),
+            (
+                "every other tuesday 3-4pm 1:1",
+                (7, 4),
+                hm(15, 0),
+                hm(16, 0),
+                Some("FREQ=WEEKLY;INTERVAL=2;BYDAY=TU"),
+                "1:1",
+            ),
+            (
+                "30 December 2023 year-end party",
+                (12, 30),
+                None,
+                None,
+                None,
+                "year-end party",
+            ),
+        ];
+
+        for (query, (month, day), start, end, rrule, title) in corpus {
+            let payload = extract_sched(&user(), today, query).unwrap();
+            let year = if query.starts_with("내년") {
+                2024
+            } else {
+                2023
+            };
+
+            assert_eq!(payload.date, ymd(year, month, day).unwrap(), "{}", query);
+            assert_eq!(
+                (payload.start_time, payload.end_time),
+                (start, end),
+                "{}",
+                query
+            );
+            assert_eq!(payload.rrule.as_deref(), rrule, "{}", query);
+            assert_eq!(payload.title, title, "{}", query);
+            assert!(payload.validate(&user()).is_ok());
+            assert!(payload.time().is_ok(), "{}", query);
+        }
+    }
+
+    #[test]
+    fn test_duration() {
+        let today = NaiveDate::from_ymd_opt(2023, 6, 28).unwrap();
+
+        let payload = extract_sched(
+            &user(),
+            today,
+            "standup every weekday at 10:30 for 15 minutes",
+        )
+        .unwrap();
+        assert_eq!(payload.date, today);
+        assert_eq!(payload.title, "standup");
+        assert_eq!(
+            payload.rrule.as_deref(),
+            Some("FREQ=WEEKLY;BYDAY=MO,TU,WE,TH,FR")
+        );
+        assert_eq!(payload.time().unwrap().end, hm(10, 45));
+
+        let payload = extract_sched(&user(), today, "1시간 동안 오후 2시 요가").unwrap();
+        assert_eq!(payload.time().unwrap().end, hm(15, 0));
+    }
+
+    #[test]
+    fn test_rejects() {
+        let today = NaiveDate::from_ymd_opt(2023, 6, 28).unwrap();
+
+        assert!(extract_sched(&user(), today, "봄소풍").is_err());
+        assert!(extract_sched(&user(), today, "내일").is_err());
+        assert!(extract_sched(&user(), today, "1시간 요가").is_err());
+        assert!(extract_sched(&user(), today, "dinner in 400000000 years").is_err());
+
+        let payload =
+            extract_sched(&user(), today, "weekly sync every monday and thursday 10am").unwrap();
+        assert_eq!(payload.rrule.as_deref(), Some("FREQ=WEEKLY;BYDAY=MO,TH"));
+        assert_eq!(payload.date, ymd(2023, 6, 29).unwrap());
+    }
+
+    #[test]
+    fn test_intents() {
+        let today = NaiveDate::from_ymd_opt(2023, 6, 28).unwrap();
+        let update = |query| match extract_intent(&user(), &[], today, query).unwrap() {
+            Intent::Update { target, changes } => (target.words(), target.date, changes),
+            intent => panic!("{}: unexpected intent {:?}", query, intent),
+        };
+        let delete = |query| match extract_intent(&user(), &[], today, query).unwrap() {
+            Intent::Delete { target } => (target.words(), target.date),
+            intent => panic!("{}: unexpected intent {:?}", query, intent),
+        };
+
+        assert_eq!(
+            update("봄소풍을 7월 2일로 옮겨줘"),
+            (
+                vec!["봄소풍".to_owned()],
+                None,
+                SchedPatch {
+                    date_at: ymd(2023, 7, 2),
+                    ..SchedPatch::default()
+                }
+            )
+        );
+        assert_eq!(
+            update("내일 회의를 3시로 미뤄줘"),
+            (
+                vec!["회의".to_owned()],
+                ymd(2023, 6, 29),
+                SchedPatch {
+                    start_time: hm(15, 0),
+                    ..SchedPatch::default()
+                }
+            )
+        );
+        assert_eq!(
+            update("move the picnic to July 2nd"),
+            (
+                vec!["picnic".to_owned()],
+                None,
+                SchedPatch {
+                    date_at: ymd(2023, 7, 2),
+                    ..SchedPatch::default()
+                }
+            )
+        );
+        assert_eq!(
+            update("reschedule dentist on friday to monday 10am"),
+            (
+                vec!["dentist".to_owned()],
+                ymd(2023, 6, 30),
+                SchedPatch {
+                    date_at: ymd(2023, 7, 3),
+                    start_time: hm(10, 0),
+                    ..SchedPatch::default()
+                }
+            )
+        );
+
+        assert_eq!(
+            delete("cancel my dentist appointment on friday"),
+            (
+                vec!["dentist".to_owned(), "appointment".to_owned()],
+                ymd(2023, 6, 30)
+            )
+        );
+        assert_eq!(
+            delete("봄소풍 일정 취소해줘"),
+            (vec!["봄소풍".to_owned()], None)
+        );
+        assert_eq!(
+            delete("치과 예약 삭제해 주세요"),
+            (vec!["치과".to_owned(), "예약".to_owned()], None)
+        );
+
+        assert!(matches!(
+            extract_intent(&user(), &[], today, "oil change tomorrow").unwrap(),
+            Intent::Create(_)
+        ));
+        assert!(extract_intent(&user(), &[], today, "취소해줘").is_err());
+        assert!(extract_intent(&user(), &[], today, "봄소풍 옮겨줘").is_err());
+    }
+
+    #[test]
+    fn test_questions() {
+        let today = NaiveDate::from_ymd_opt(2023, 6, 28).unwrap();
+        let members = ["21kyu".to_owned(), "csj200045".to_owned()];
+        let question = |query| match extract_intent(&user(), &members, today, query).unwrap() {
+            Intent::Query(question) => question,
+            intent => panic!("{}: unexpected intent {:?}", query, intent),
+        };
+        let asked = |(m, d), (to_m, to_d), user: Option<&str>, free| Question {
+            from: ymd(2023, m, d).unwrap(),
+            to: ymd(2023, to_m, to_d).unwrap(),
+            user: user.map(str::to_owned),
+            free,
+        };
+
+        assert_eq!(
+            question("what's happening next week?"),
+            asked((7, 3), (7, 9), None, false)
+        );
+        assert_eq!(
+            question("when is 21kyu free in July?"),
+            asked((7, 1), (7, 31), Some("21kyu"), true)
+        );
+        assert_eq!(
+            question("anything on this month"),
+            asked((6, 28), (6, 30), None, false)
+        );
+        assert_eq!(
+            question("다음주 일정 알려줘"),
+            asked((7, 3), (7, 9), None, false)
+        );
+        assert_eq!(
+            question("내 일정 뭐 있어?"),
+            asked((6, 28), (7, 4), Some("21kyu"), false)
+        );
+        assert_eq!(
+            question("csj200045님 7월에 언제 한가해?"),
+            asked((7, 1), (7, 31), Some("csj200045"), true)
+        );
+        assert_eq!(
+            question("내일 뭐 있어?"),
+            asked((6, 29), (6, 29), None, false)
+        );
+        assert_eq!(
+            question("다음주 화요일에 뭐 있어?"),
+            asked((7, 4), (7, 4), None, false)
+        );
+    }
+
+    #[test]
+    fn test_answer() {
+        let date = |d| ymd(2023, 7, d).unwrap();
+        let mut dentist = Sched::new("home", "21kyu", "치과", date(4));
+        dentist.start_time = hm(15, 0);
+        dentist.all_day = false;
+        let picnic = Sched::new("home", "csj200045", "봄소풍", date(6));
+        let scheds = [dentist, picnic];
+
+        let week = Question {
+            from: date(3),
+            to: date(9),
+            user: None,
+            free: false,
+        };
+        assert_eq!(
+            answer("다음주 일정 알려줘", &week, &scheds),
+            "7월 3일부터 7월 9일까지 일정 2개: 7월 4일 15:00 치과, 7월 6일 봄소풍"
+        );
+        assert_eq!(
+            answer("what's on next week?", &week, &scheds),
+            "2 schedules from Jul 3 to Jul 9: Jul 4 15:00 치과, Jul 6 봄소풍."
+        );
+        assert_eq!(
+            answer("what's on next week?", &week, &[]),
+            "Nothing is scheduled from Jul 3 to Jul 9."
+        );
+
+        let free = Question {
+            user: Some("21kyu".to_owned()),
+            free: true,
+            ..week
+        };
+        assert_eq!(
+            answer("when is 21kyu free next week?", &free, &scheds),
+            "21kyu is free on Jul 3, Jul 5-Jul 9."
+        );
+        assert_eq!(
+            answer("21kyu 다음주에 언제 한가해?", &free, &scheds),
+            "7월 3일부터 7월 9일까지 21kyu 일정이 없는 날: 7월 3일, 7월 5일~7월 9일"
+        );
+    }
+}
@@ -1,14 +1,186 @@
-use chrono::NaiveDate;
-use scylla::{frame::value::Timestamp, FromRow};
+use anyhow::{anyhow, Result};
+use chrono::{Duration, NaiveDate, NaiveTime, Timelike, Utc};
+use scylla::cql_to_rust::{FromRow, FromRowError};
+use scylla::frame::response::result::Row;
+use scylla::frame::value::{Time, Timestamp};
 use serde::ser::{Serialize, SerializeStruct, Serializer};
+use uuid::Uuid;
 
-#[derive(Debug, FromRow, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Sched {
     pub channel: String,
     pub id: String,
     pub sched: String,
     pub date_at: NaiveDate,
     pub create_at: Timestamp,
+    pub sid: Uuid,
+    pub start_time: Option<NaiveTime>,
+    pub end_time: Option<NaiveTime>,
+    pub all_day: bool,
+    /// RFC 5545 recurrence rule, which makes this row the first occurrence
+    /// of a series. See `recur`.
+    pub rrule: Option<String>,
+    /// Occurrences left out of the series.
+    pub exdates: Vec<NaiveDate>,
+    /// iCalendar UID of an imported schedule, `sid` stands in for others.
+    pub uid: Option<String>,
+    /// Time zone a series was written in, whose wall-clock dates its rule
+    /// repeats on. Single schedules have none. See `tz::zone`.
+    pub tz: Option<String>,
+}
+
+impl Sched {
+    pub fn new(channel: &str, id: &str, sched: &str, date_at: NaiveDate) -> Self {
+        Self {
+            channel: channel.to_owned(),
+            id: id.to_owned(),
+            sched: sched.to_owned(),
+            date_at,
+            create_at: Timestamp(Duration::milliseconds(Utc::now().timestamp_millis())),
+            sid: Uuid::new_v4(),
+            start_time: None,
+            end_time: None,
+            all_day: true,
+            rrule: None,
+            exdates: vec![],
+            uid: None,
+            tz: None,
+        }
+    }
+
+    pub fn set_time(&mut self, time: SchedTime) {
+        self.start_time = time.start;
+        self.end_time = time.end;
+        self.all_day = time.all_day;
+    }
+}
+
+/// The time of day a schedule takes, as stored in `ks.s`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchedTime {
+    pub start: Option<NaiveTime>,
+    pub end: Option<NaiveTime>,
+    pub all_day: bool,
+}
+
+impl SchedTime {
+    /// Resolves what clients send: a start time with either an end time or
+    /// a duration in minutes, or an all-day flag. A schedule without a start
+    /// time is all-day.
+    pub fn resolve(
+        start: Option<NaiveTime>,
+        end: Option<NaiveTime>,
+        duration: Option<i64>,
+        all_day: Option<bool>,
+    ) -> Result<Self> {
+        if start.is_none() && (end.is_some() || duration.is_some()) {
+            return Err(anyhow!("missing start time"));
+        }
+
+        if all_day == Some(true) || (start.is_none() && all_day.is_none()) {
+            if start.is_some() || end.is_some() || duration.is_some() {
+                return Err(anyhow!("all-day schedules have no time"));
+            }
+            return Ok(Self {
+                start: None,
+                end: None,
+                all_day: true,
+            });
+        }
+
+        let start = start.ok_or_else(|| anyhow!("missing start time"))?;
+
+        let end = match (end, duration) {
+            (Some(_), Some(_)) => return Err(anyhow!("give either an end time or a duration")),
+            (Some(end), None) => Some(end),
+            (None, Some(minutes)) => {
+                if !(0..24 * 60).contains(&minutes) {
+                    return Err(anyhow!("duration must end within the day"));
+                }
+                let (end, wrapped) = start.overflowing_add_signed(Duration::minutes(minutes));
+                if wrapped != 0 {
+                    return Err(anyhow!("duration must end within the day"));
+                }
+                Some(end)
+            }
+            (None, None) => None,
+        };
+
+        if end.is_some_and(|end| end < start) {
+            return Err(anyhow!("end time is before start time"));
+        }
+
+        Ok(Self {
+            start: Some(start),
+            end,
+            all_day: false,
+        })
+    }
+}
+
+pub fn time_to_cql(time: Option<NaiveTime>) -> Option<Time> {
+    time.map(|t| {
+        Time(Duration::nanoseconds(
+            t.num_seconds_from_midnight() as i64 * 1_000_000_000,
+        ))
+    })
+}
+
+fn time_from_cql(time: Option<Time>) -> Option<NaiveTime> {
+    time.map(|Time(d)| NaiveTime::MIN + d)
+}
+
+impl FromRow for Sched {
+    fn from_row(row: Row) -> Result<Self, FromRowError> {
+        let (
+            channel,
+            id,
+            sched,
+            date_at,
+            create_at,
+            sid,
+            start_time,
+            end_time,
+            all_day,
+            rrule,
+            exdates,
+            uid,
+            tz,
+        ) = <(
+            String,
+            String,
+            String,
+            NaiveDate,
+            Timestamp,
+            Uuid,
+            Option<Time>,
+            Option<Time>,
+            Option<bool>,
+            Option<String>,
+            Option<Vec<NaiveDate>>,
+            Option<String>,
+            Option<String>,
+        )>::from_row(row)?;
+
+        let start_time = time_from_cql(start_time);
+
+        Ok(Self {
+            channel,
+            id,
+            sched,
+            date_at,
+            create_at,
+            sid,
+            start_time,
+            end_time: time_from_cql(end_time),
+            // Rows written before times existed carry no flag and no time.
+            all_day: all_day.unwrap_or(start_time.is_none()),
+            rrule,
+            exdates: exdates.unwrap_or_default(),
+            uid,
+            tz,
+        })
+    }
 }
 
 impl Serialize for Sched {
@@ -16,12 +188,62 @@ impl Serialize for Sched {
     where
         S: Serializer,
     {
-        let mut state = serializer.serialize_struct("Sched", 5)?;
+        let mut state = serializer.serialize_struct("Sched", 12)?;
+        state.serialize_field("sid", &self.sid)?;
         state.serialize_field("channel", &self.channel)?;
         state.serialize_field("id", &self.id)?;
         state.serialize_field("sched", &self.sched)?;
         state.serialize_field("date_at", &self.date_at)?;
+        state.serialize_field("start_time", &self.start_time)?;
+        state.serialize_field("end_time", &self.end_time)?;
+        state.serialize_field("all_day", &self.all_day)?;
+        state.serialize_field("rrule", &self.rrule)?;
+        state.serialize_field("exdates", &self.exdates)?;
+        state.serialize_field("tz", &self.tz)?;
         state.serialize_field("create_at", "123")?;
         state.end()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(h: u32, m: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(h, m, 0).unwrap()
+    }
+
+    #[test]
+    fn test_resolve_duration() {
+        let resolved = SchedTime::resolve(Some(time(10, 30)), None, Some(15), None).unwrap();
+
+        assert_eq!(resolved.start, Some(time(10, 30)));
+        assert_eq!(resolved.end, Some(time(10, 45)));
+        assert!(!resolved.all_day);
+    }
+
+    #[test]
+    fn test_resolve_defaults_to_all_day() {
+        let resolved = SchedTime::resolve(None, None, None, None).unwrap();
+
+        assert!(resolved.all_day);
+        assert_eq!(resolved.start, None);
+    }
+
+    #[test]
+    fn test_resolve_rejects_inconsistent_times() {
+        assert!(SchedTime::resolve(Some(time(10, 0)), Some(time(9, 0)), None, None).is_err());
+        assert!(SchedTime::resolve(Some(time(23, 0)), None, Some(120), None).is_err());
+        assert!(SchedTime::resolve(Some(time(0, 0)), None, Some(i64::MAX), None).is_err());
+        assert!(SchedTime::resolve(Some(time(0, 0)), None, Some(i64::MIN), None).is_err());
+        assert!(SchedTime::resolve(Some(time(10, 0)), None, None, Some(true)).is_err());
+        assert!(SchedTime::resolve(None, Some(time(10, 0)), None, None).is_err());
+    }
+
+    #[test]
+    fn test_cql_time_roundtrip() {
+        let t = Some(time(10, 30));
+
+        assert_eq!(time_from_cql(time_to_cql(t)), t);
+    }
+}
@@ -0,0 +1,74 @@
+use chrono::{Duration, Utc};
+use scylla::frame::value::Timestamp;
+use scylla::FromRow;
+use uuid::Uuid;
+
+/// How often `last_seen_at` is written, rather than on every request.
+const LAST_SEEN_INTERVAL: i64 = 60;
+
+/// A sign-in on one device, as stored in `ks.d`. Access tokens carry its id
+/// as `jti` and refresh tokens rotated from it share it, so deleting the
+/// row signs the device out.
+#[derive(Debug, Clone, PartialEq, FromRow)]
+pub struct Session {
+    pub id: Uuid,
+    pub user: String,
+    /// The User-Agent it signed in with.
+    pub device: String,
+    pub ip: Option<String>,
+    pub created_at: Timestamp,
+    pub last_seen_at: Timestamp,
+}
+
+fn now() -> Timestamp {
+    Timestamp(Duration::milliseconds(Utc::now().timestamp_millis()))
+}
+
+impl Session {
+    pub fn new(user: &str, device: &str, ip: Option<&str>) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            user: user.to_owned(),
+            device: device.to_owned(),
+            ip: ip.map(str::to_owned),
+            created_at: now(),
+            last_seen_at: now(),
+        }
+    }
+
+    /// Records a request from `ip`. Returns whether the session changed
+    /// enough to be stored again.
+    pub fn seen(&mut self, ip: Option<&str>) -> bool {
+        let stale = Utc::now().timestamp_millis() - self.last_seen_at.0.num_milliseconds()
+            >= LAST_SEEN_INTERVAL * 1000;
+        let moved = ip.is_some() && ip != self.ip.as_deref();
+        if !stale && !moved {
+            return false;
+        }
+
+        self.last_seen_at = now();
+        if moved {
+            self.ip = ip.map(str::to_owned);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seen() {
+        let mut session = Session::new("21kyu", "curl/8.0", Some("10.0.0.1"));
+        assert!(!session.seen(Some("10.0.0.1")));
+        assert!(!session.seen(None));
+
+        assert!(session.seen(Some("10.0.0.2")));
+        assert_eq!(session.ip.as_deref(), Some("10.0.0.2"));
+
+        session.last_seen_at = Timestamp(Duration::milliseconds(0));
+        assert!(session.seen(None));
+        assert!(session.last_seen_at.0.num_milliseconds() > 0);
+    }
+}
@@ -0,0 +1,141 @@
+use anyhow::{anyhow, Result};
+use chrono::{Duration, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use crate::sched::Sched;
+
+pub fn parse_tz(name: &str) -> Result<Tz> {
+    name.parse::<Tz>()
+        .map_err(|e| anyhow!("invalid time zone {}: {}", name, e))
+}
+
+pub fn today(tz: Tz) -> NaiveDate {
+    Utc::now().with_timezone(&tz).date_naive()
+}
+
+pub fn local_to_utc(tz: Tz, local: NaiveDateTime) -> NaiveDateTime {
+    match tz.from_local_datetime(&local).earliest() {
+        Some(dt) => dt.naive_utc(),
+        // Wall-clock times skipped by a DST jump are taken as UTC offsets
+        // would have been just before the jump.
+        None => tz
+            .from_local_datetime(&(local - Duration::hours(1)))
+            .earliest()
+            .map(|dt| dt.naive_utc() + Duration::hours(1))
+            .unwrap_or(local),
+    }
+}
+
+pub fn utc_to_local(tz: Tz, utc: NaiveDateTime) -> NaiveDateTime {
+    tz.from_utc_datetime(&utc).naive_local()
+}
+
+/// The zone a series repeats in: the one it was written in, or `tz` for
+/// series stored before zones were kept and for single schedules.
+pub fn zone(sched: &Sched, tz: Tz) -> Tz {
+    match (&sched.rrule, sched.tz.as_deref()) {
+        (Some(_), Some(name)) => parse_tz(name).unwrap_or(tz),
+        _ => tz,
+    }
+}
+
+/// Converts the wall-clock date and times of a schedule written in `tz` to
+/// UTC for storage. All-day schedules are floating dates and stay as they are.
+/// The stored end time wraps past midnight when the UTC day changes, which
+/// makes it earlier than the start time. Exception dates of a series move
+/// along with the date. A schedule that already names its zone is taken as
+/// written there, and series remember theirs.
+pub fn to_utc(sched: &Sched, tz: Tz) -> Sched {
+    let tz = sched
+        .tz
+        .as_deref()
+        .and_then(|name| parse_tz(name).ok())
+        .unwrap_or(tz);
+    let mut converted = convert(sched, |dt| local_to_utc(tz, dt));
+    converted.tz = sched.rrule.as_ref().map(|_| tz.name().to_owned());
+    converted
+}
+
+/// Converts a stored schedule to wall-clock date and times in `tz`. Series
+/// are only meant to be expanded in their own `zone`.
+pub fn to_local(sched: &Sched, tz: Tz) -> Sched {
+    convert(sched, |dt| utc_to_local(tz, dt))
+}
+
+fn convert(sched: &Sched, f: impl Fn(NaiveDateTime) -> NaiveDateTime) -> Sched {
+    let mut converted = sched.clone();
+
+    let start = match (sched.all_day, sched.start_time) {
+        (false, Some(start)) => sched.date_at.and_time(start),
+        _ => return converted,
+    };
+    let end = sched.end_time.map(|end| {
+        let end = sched.date_at.and_time(end);
+        if end < start {
+            end + Duration::days(1)
+        } else {
+            end
+        }
+    });
+
+    let start = f(start);
+    let shift = start.date() - sched.date_at;
+    converted.date_at = start.date();
+    converted.exdates = sched.exdates.iter().map(|d| *d + shift).collect();
+    converted.start_time = Some(start.time());
+    converted.end_time = end.map(|end| f(end).time());
+
+    converted
+}
+
+/// Local dates `from..=to` in `tz` touch at most one more UTC date on each
+/// side, which is what has to be read from storage before filtering.
+pub fn utc_date_range(from: NaiveDate, to: Option<NaiveDate>) -> (NaiveDate, Option<NaiveDate>) {
+    (
+        from - Duration::days(1),
+        to.map(|to| to + Duration::days(1)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveTime;
+
+    use super::*;
+
+    fn timed(date: NaiveDate, start: (u32, u32), end: (u32, u32)) -> Sched {
+        let mut sched = Sched::new("home", "21kyu", "스탠드업", date);
+        sched.start_time = NaiveTime::from_hms_opt(start.0, start.1, 0);
+        sched.end_time = NaiveTime::from_hms_opt(end.0, end.1, 0);
+        sched.all_day = false;
+        sched
+    }
+
+    #[test]
+    fn test_seoul_morning_is_previous_utc_day() {
+        let seoul = parse_tz("Asia/Seoul").unwrap();
+        let local = timed(NaiveDate::from_ymd_opt(2023, 7, 1).unwrap(), (8, 30), (9, 30));
+
+        let stored = to_utc(&local, seoul);
+
+        assert_eq!(stored.date_at, NaiveDate::from_ymd_opt(2023, 6, 30).unwrap());
+        assert_eq!(stored.start_time, NaiveTime::from_hms_opt(23, 30, 0));
+        assert_eq!(stored.end_time, NaiveTime::from_hms_opt(0, 30, 0));
+
+        assert_eq!(to_local(&stored, seoul), local);
+    }
+
+    #[test]
+    fn test_all_day_is_floating() {
+        let seoul = parse_tz("Asia/Seoul").unwrap();
+        let sched = Sched::new("home", "21kyu", "봄소풍", NaiveDate::from_ymd_opt(2023, 6, 30).unwrap());
+
+        assert_eq!(to_utc(&sched, seoul), sched);
+        assert_eq!(to_local(&sched, seoul), sched);
+    }
+
+    #[test]
+    fn test_parse_tz_rejects_unknown() {
+        assert!(parse_tz("Mars/Olympus").is_err());
+    }
+}
@@ -4,4 +4,5 @@ use scylla::FromRow;
 pub struct User {
     pub id: String,
     pub channel: String,
+    pub tz: Option<String>,
 }
@@ -5,6 +5,10 @@ pub struct ItemProps {
     pub user: String,
     pub date_at: String,
     pub sched: String,
+    #[prop_or_default]
+    pub time: Option<String>,
+    #[prop_or_default]
+    pub repeats: bool,
 }
 
 #[function_component]
@@ -13,6 +17,12 @@ pub fn Item(props: &ItemProps) -> Html {
         <article class="flex max-w-xl flex-col items-start justify-between">
             <div class="flex items-center gap-x-4 text-xs">
               <p class="text-gray-500">{props.date_at.to_owned()}</p>
+              if let Some(time) = &props.time {
+                <p class="text-gray-500">{time.to_owned()}</p>
+              }
+              if props.repeats {
+                <p class="text-gray-500" title="Repeats">{"↻"}</p>
+              }
               <a href="#" class="relative rounded-full bg-gray-50 px-3 py-1.5 font-medium text-gray-600 hover:bg-gray-100">{props.user.to_owned()}</a>
             </div>
             <div class="group relative">
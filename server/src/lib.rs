@@ -20,12 +20,56 @@ pub enum Route {
     NotFound,
 }
 
+/// Seconds before the access token runs out at which the session is
+/// renewed.
+#[cfg(feature = "hydration")]
+const REFRESH_MARGIN: u64 = 60;
+
+/// Renews the session for as long as the page is open, first right away as
+/// the access token may be nearly used up by the time the page loads. Stops
+/// once there is no session to renew.
+#[cfg(feature = "hydration")]
+async fn keep_session() {
+    #[derive(serde::Deserialize)]
+    struct Refreshed {
+        expires_in: u64,
+    }
+
+    loop {
+        let refreshed = match reqwest::Client::new()
+            .post("https://sched.sinabro.io/auth/refresh")
+            .send()
+            .await
+        {
+            Ok(resp) if resp.status() == 200 => resp.json::<Refreshed>().await.ok(),
+            _ => None,
+        };
+        let expires_in = match refreshed {
+            Some(refreshed) => refreshed.expires_in,
+            None => return,
+        };
+
+        yew::platform::time::sleep(std::time::Duration::from_secs(
+            expires_in.saturating_sub(REFRESH_MARGIN).max(1),
+        ))
+        .await;
+    }
+}
+
 #[function_component]
 pub fn App() -> Html {
     let ctx = use_state(|| Auth {
         ..Default::default()
     });
 
+    use_effect_with_deps(
+        |_| {
+            #[cfg(feature = "hydration")]
+            wasm_bindgen_futures::spawn_local(keep_session());
+        },
+        (),
+    );
+
     html! {
         <ContextProvider<Auth> context={(*ctx).clone()}>
         <BrowserRouter>
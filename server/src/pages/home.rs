@@ -9,20 +9,110 @@ use crate::{
 
 #[derive(Serialize, Deserialize, Debug, Default, PartialEq, Clone)]
 pub struct Sched {
+    #[serde(default)]
+    sid: String,
     channel: String,
     id: String,
     sched: String,
     date_at: String,
+    #[serde(default)]
+    start_time: Option<String>,
+    #[serde(default)]
+    end_time: Option<String>,
+    #[serde(default = "all_day_default")]
+    all_day: bool,
+    #[serde(default)]
+    rrule: Option<String>,
     create_at: String,
 }
 
+fn all_day_default() -> bool {
+    true
+}
+
+impl Sched {
+    /// "10:30", "10:30 - 10:45", or `None` for all-day schedules.
+    fn time(&self) -> Option<String> {
+        if self.all_day {
+            return None;
+        }
+        let short = |t: &String| t.get(..5).unwrap_or(t).to_owned();
+        match (&self.start_time, &self.end_time) {
+            (Some(start), Some(end)) => Some(format!("{} - {}", short(start), short(end))),
+            (Some(start), None) => Some(short(start)),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Default, PartialEq, Clone)]
 struct SchedResponse {
     user: String,
     channel: String,
+    #[serde(default)]
+    tz: String,
     data: Vec<Sched>,
+    #[serde(default)]
+    next: Option<String>,
+}
+
+/// The schedules to choose from when a query matched several.
+#[cfg(feature = "hydration")]
+#[derive(Deserialize, Debug)]
+struct Ambiguous {
+    candidates: Vec<Sched>,
+}
+
+/// The assistant's answer to a question, with the schedules it is about.
+#[cfg(feature = "hydration")]
+#[derive(Deserialize, Debug)]
+struct Reply {
+    answer: String,
+    #[serde(default)]
+    scheds: Vec<Sched>,
+}
+
+#[cfg(feature = "hydration")]
+enum Answer {
+    Done(SchedResponse),
+    Choose(Vec<Sched>),
+    Reply(Reply),
+}
+
+/// Sends a query to the assistant, with the schedule picked from the
+/// candidates of an earlier answer.
+#[cfg(feature = "hydration")]
+async fn ask(query: &str, sid: Option<&str>) -> Answer {
+    let mut map = std::collections::HashMap::new();
+    map.insert("query", query);
+    if let Some(sid) = sid {
+        map.insert("sid", sid);
+    }
+
+    let resp = reqwest::Client::new()
+        .post("https://sched.sinabro.io/api/v1/gpt")
+        .json(&map)
+        .send()
+        .await
+        .unwrap();
+
+    if resp.status() == 409 {
+        return Answer::Choose(resp.json::<Ambiguous>().await.unwrap().candidates);
+    }
+
+    assert_eq!(resp.status(), 200);
+
+    let body = resp.text().await.unwrap();
+    if let Ok(reply) = serde_json::from_str::<Reply>(&body) {
+        return Answer::Reply(reply);
+    }
+    Answer::Done(serde_json::from_str(&body).unwrap())
 }
 
+/// Distance in pixels from the bottom of the list at which the next page is
+/// requested.
+const SCROLL_THRESHOLD: i32 = 200;
+
 #[cfg(feature = "ssr")]
 async fn fetch_sched(token: &str, channel: &str) -> SchedResponse {
     let client = reqwest::Client::new();
@@ -33,7 +123,6 @@ async fn fetch_sched(token: &str, channel: &str) -> SchedResponse {
     let resp = client
         .get(url)
         .header("authorization", format!("Bearer {}", token))
-        .header("channel", channel)
         .send()
         .await
         .unwrap();
@@ -44,14 +133,33 @@ async fn fetch_sched(token: &str, channel: &str) -> SchedResponse {
 
     let scheds = resp.json::<SchedResponse>().await;
 
-    match scheds {
-        Ok(scheds) => scheds,
-        Err(_) => SchedResponse::default(),
+    scheds.unwrap_or_default()
+}
+
+#[cfg(feature = "hydration")]
+async fn fetch_next_sched(channel: &str, cursor: &str) -> Option<SchedResponse> {
+    let client = reqwest::Client::new();
+    let url = format!(
+        "https://sched.sinabro.io/api/v1/channels/{}/scheds",
+        channel
+    );
+    let resp = client
+        .get(url)
+        .query(&[("cursor", cursor)])
+        .send()
+        .await
+        .ok()?;
+
+    if resp.status() != 200 {
+        return None;
     }
+
+    resp.json::<SchedResponse>().await.ok()
 }
 
 #[function_component]
 fn Content() -> HtmlResult {
+    #[cfg_attr(not(feature = "ssr"), allow(unused_variables))]
     let ctx = use_context::<Auth>().unwrap();
 
     let scheds = use_prepared_state!(
@@ -62,10 +170,15 @@ fn Content() -> HtmlResult {
 
     let message = use_state(|| "".to_string());
     let send = use_state(|| false);
+    let loading = use_state(|| false);
+    let candidates = use_state(Vec::<Sched>::new);
+    let reply = use_state(|| None::<(String, Vec<Sched>)>);
     let state = use_state_eq(|| SchedResponse {
         user: scheds.user.to_string(),
         channel: scheds.channel.to_string(),
+        tz: scheds.tz.to_string(),
         data: scheds.data.to_owned(),
+        next: scheds.next.to_owned(),
     });
 
     let onchange = {
@@ -77,47 +190,105 @@ fn Content() -> HtmlResult {
         })
     };
 
-    let onclick = {
+    #[cfg_attr(not(feature = "hydration"), allow(unused_variables))]
+    let submit = {
         let message = message.clone();
         let send = send.clone();
         let state = state.clone();
+        let candidates = candidates.clone();
+        let reply = reply.clone();
 
-        Callback::from(move |_| {
+        Callback::from(move |sid: Option<String>| {
             send.set(true);
-            let send = send.clone();
-            let message = message.clone();
-            let state = state.clone();
-            let user = state.user.to_string();
-            let channel = state.channel.to_string();
 
             #[cfg(feature = "hydration")]
             {
-                let mut map = std::collections::HashMap::new();
-                map.insert(
-                    "query",
-                    format!("{} (channel: {}, id: {})", *message, channel, user),
-                );
+                let send = send.clone();
+                let message = message.clone();
+                let state = state.clone();
+                let candidates = candidates.clone();
+                let reply = reply.clone();
 
                 wasm_bindgen_futures::spawn_local(async move {
-                    let client = reqwest::Client::new();
-                    let resp = client
-                        .post("https://sched.sinabro.io/api/v1/gpt")
-                        .json(&map)
-                        .send()
-                        .await
-                        .unwrap();
+                    let answer = ask(&message, sid.as_deref()).await;
 
-                    assert_eq!(resp.status(), 200);
+                    send.set(false);
+                    match answer {
+                        Answer::Done(scheds) => {
+                            message.set("".to_string());
+                            candidates.set(vec![]);
+                            reply.set(None);
+                            state.set(scheds);
+                        }
+                        Answer::Choose(found) => {
+                            reply.set(None);
+                            candidates.set(found);
+                        }
+                        Answer::Reply(found) => {
+                            message.set("".to_string());
+                            candidates.set(vec![]);
+                            reply.set(Some((found.answer, found.scheds)));
+                        }
+                    }
+                });
+            }
+        })
+    };
+    let onclick = submit.reform(|_: MouseEvent| None);
 
-                    let scheds = resp.json::<SchedResponse>().await.unwrap();
+    let onlogout = Callback::from(|_| {
+        #[cfg(feature = "hydration")]
+        wasm_bindgen_futures::spawn_local(async {
+            let _ = reqwest::Client::new()
+                .post("https://sched.sinabro.io/auth/logout")
+                .send()
+                .await;
 
-                    send.set(false);
-                    message.set("".to_string());
-                    state.set(SchedResponse {
-                        user: scheds.user.clone(),
-                        channel: scheds.channel.clone(),
-                        data: scheds.data.to_vec(),
-                    });
+            if let Some(window) = web_sys::window() {
+                let _ = window.location().reload();
+            }
+        });
+    });
+
+    #[cfg_attr(not(feature = "hydration"), allow(unused_variables))]
+    let onscroll = {
+        let loading = loading.clone();
+        let state = state.clone();
+
+        Callback::from(move |e: Event| {
+            let list: web_sys::Element = e.target_unchecked_into();
+            let remaining = list.scroll_height() - list.scroll_top() - list.client_height();
+
+            if remaining > SCROLL_THRESHOLD || *loading {
+                return;
+            }
+
+            let cursor = match &state.next {
+                Some(cursor) => cursor.to_owned(),
+                None => return,
+            };
+
+            loading.set(true);
+
+            #[cfg(feature = "hydration")]
+            {
+                let loading = loading.clone();
+                let state = state.clone();
+
+                wasm_bindgen_futures::spawn_local(async move {
+                    if let Some(page) = fetch_next_sched(&state.channel, &cursor).await {
+                        let mut data = state.data.to_vec();
+                        data.extend(page.data);
+
+                        state.set(SchedResponse {
+                            user: state.user.clone(),
+                            channel: state.channel.clone(),
+                            tz: page.tz,
+                            data,
+                            next: page.next,
+                        });
+                    }
+                    loading.set(false);
                 });
             }
         })
@@ -127,21 +298,53 @@ fn Content() -> HtmlResult {
       <div class="bg-white py-8">
         <div class="mx-auto max-w-7xl px-6 pb-10 mb-5">
             <div class="mx-auto max-w-2xl">
-                <h2 class="text-3xl font-bold tracking-tight text-gray-900 text-4xl mt-6">{"Hello, "}{state.user.to_string()}</h2>
-                <p class="mt-2 text-lg leading-8 text-gray-600">{"Schedules registered in the "}{state.channel.to_string()}{" channel after today"}</p>
+                <div class="flex items-baseline justify-between">
+                    <h2 class="text-3xl font-bold tracking-tight text-gray-900 text-4xl mt-6">{"Hello, "}{state.user.to_string()}</h2>
+                    <button onclick={onlogout} class="text-sm text-gray-500 hover:text-gray-900">{"Sign out"}</button>
+                </div>
+                <p class="mt-2 text-lg leading-8 text-gray-600">{"Schedules registered in the "}{state.channel.to_string()}{" channel after today"}
+                    if !state.tz.is_empty() {
+                        {format!(" ({})", state.tz)}
+                    }
+                </p>
             </div>
-            <div class="mx-auto mt-10 grid max-w-2xl grid-cols-1 gap-x-8 gap-y-10 border-t border-gray-200 pt-10">
+            <div {onscroll} class="mx-auto mt-10 grid max-w-2xl max-h-[70vh] overflow-y-auto grid-cols-1 gap-x-8 gap-y-10 border-t border-gray-200 pt-10">
             {for state.data.iter().map(|sched| {
-                html! {<Item user={sched.id.clone()} sched={sched.sched.clone()} date_at={sched.date_at.clone()} />}
+                html! {<Item user={sched.id.clone()} sched={sched.sched.clone()} date_at={sched.date_at.clone()} time={sched.time()} repeats={sched.rrule.is_some()} />}
             })}
+            if *loading {
+                <p class="text-sm text-gray-500">{"Loading..."}</p>
+            }
             </div>
         </div>
 
         <div  class="relative">
             <div class="fixed bottom-0 left-0 right-0 bg-white border-t border-gray-200">
+                if let Some((answer, scheds)) = &*reply {
+                    <div class="mx-auto max-w-7xl px-6 pt-3">
+                        <p class="text-sm text-gray-900">{answer.to_string()}</p>
+                        <ul class="mt-1 text-sm text-gray-500">
+                        {for scheds.iter().map(|sched| {
+                            html! {<li>{format!("{}{} {}", sched.date_at, sched.time().map(|time| format!(" {}", time)).unwrap_or_default(), sched.sched)}</li>}
+                        })}
+                        </ul>
+                    </div>
+                }
+                if !candidates.is_empty() {
+                    <div class="mx-auto max-w-7xl px-6 pt-3">
+                        <p class="text-sm text-gray-500">{"Which one did you mean?"}</p>
+                        <div class="mt-2 flex flex-wrap gap-2">
+                        {for candidates.iter().map(|sched| {
+                            let sid = sched.sid.clone();
+                            let onclick = submit.reform(move |_: MouseEvent| Some(sid.clone()));
+                            html! {<button {onclick} class="rounded-md bg-stone-100 px-3 py-1.5 text-sm text-gray-900 hover:bg-stone-200">{format!("{} {}", sched.date_at, sched.sched)}</button>}
+                        })}
+                        </div>
+                    </div>
+                }
                 <div class="mx-auto max-w-7xl px-6 py-3 flex gap-x-4">
                     <label for="command" class="sr-only">{"command"}</label>
-                    <input {onchange} value={(*message).clone()} id="command" name="command" type="text" required=true class="min-w-0 flex-auto rounded-md border-0 bg-white/5 px-3.5 py-2 shadow-sm ring-1 ring-inset ring-white/10 focus:ring-2 focus:ring-inset focus:ring-indigo-500" placeholder="When and what schedule would you like to register?" />
+                    <input {onchange} value={(*message).clone()} id="command" name="command" type="text" required=true class="min-w-0 flex-auto rounded-md border-0 bg-white/5 px-3.5 py-2 shadow-sm ring-1 ring-inset ring-white/10 focus:ring-2 focus:ring-inset focus:ring-indigo-500" placeholder="Add, move or cancel a schedule, or ask what's on" />
                     if *send {
                         <button class="flex-none rounded-md bg-stone-300 px-3.5 py-2.5 text-sm font-semibold text-white shadow-sm">{"Proc"}</button>
                     } else {
@@ -162,6 +365,7 @@ pub fn Comp() -> HtmlResult {
         channel: _,
         mut token,
     } = ctx;
+    #[cfg_attr(not(feature = "ssr"), allow(unused_variables))]
     let token_clone = token.clone();
     let token_state = use_transitive_state!(|_| -> String { token_clone }, ())?;
 